@@ -0,0 +1,12 @@
+//! Cache Command - manage the LLM response cache
+
+use anyhow::Result;
+use crate::config::Config;
+use crate::services::llm::ResponseCache;
+
+/// Delete every cached LLM response; the scraping cache is left alone
+pub async fn clear(config: &Config) -> Result<()> {
+    let removed = ResponseCache::from_config(&config.llm.cache).clear()?;
+    println!("Removed {} cached LLM responses from {}", removed, config.llm.cache.path);
+    Ok(())
+}
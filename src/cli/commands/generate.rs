@@ -1,57 +1,359 @@
 //! Generate Command
 
-use anyhow::Result;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
 use uuid::Uuid;
-use crate::services::llm::LlmClient;
-use crate::services::generation::GenerationService;
-use crate::services::StorageService;
+use crate::config::Config;
+use crate::models::{
+    ChapterCandidate, ChapterPlan, ChapterRevision, ExistingChapterPolicy, GeneratedChapter, GenerationJob, NovelOutline,
+    NovelProject, RevisionSource,
+};
+use crate::services::llm::{
+    config_for_project, create_embedding_provider, CancellationToken, GenerationOptions, LlmError,
+};
+use crate::services::generation::{GenerationService, CONTINUITY_INSTRUCTION};
+use crate::services::{ChapterBrief, ContextService, SegmentOptions, StorageService, UsageTracker};
+
+/// Earlier passages added to a chapter's prompt when retrieval is configured
+const RELATED_PASSAGES: usize = 4;
 
-pub async fn run(project_id: &str, chapters: &str) -> Result<()> {
-    tracing::info!("Generating chapters {} for: {}", chapters, project_id);
+/// Generate a chapter range, or resume the last job
+///
+/// With `candidates` above 1, plot-twist chapters are drafted that many
+/// times and the best draft is kept; the others are saved under
+/// `chapters/candidates/`. `judge` adds an LLM judge to the ranking.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    config: &Config,
+    project_id: &str,
+    chapters: Option<&str>,
+    resume: bool,
+    skip_existing: bool,
+    parallel: Option<u32>,
+    candidates: u32,
+    judge: bool,
+) -> Result<()> {
+    tracing::info!("Generating chapters {:?} for: {} (resume: {})", chapters, project_id, resume);
 
     let project_uuid = Uuid::parse_str(project_id)?;
 
-    // Parse chapter range
-    let chapter_nums: Vec<u32> = if chapters.contains('-') {
-        let parts: Vec<&str> = chapters.split('-').collect();
-        let start: u32 = parts[0].parse()?;
-        let end: u32 = parts[1].parse()?;
-        (start..=end).collect()
+    // Create storage for project
+    let storage = StorageService::new_project(".", project_uuid)?;
+
+    let mut job = if resume {
+        let job: GenerationJob = storage.load()?
+            .with_context(|| format!("Project {} has no generation job to resume", project_id))?;
+        if job.is_finished() {
+            println!("✓ Chapters {}-{} are already generated", job.start, job.end);
+            return Ok(());
+        }
+        println!(
+            "Resuming chapters {}-{}: {} of {} left",
+            job.start, job.end, job.remaining().len(), job.chapters.len()
+        );
+        for failure in job.failures() {
+            println!("  Retrying chapter {} (failed: {})", failure.number, failure.error.as_deref().unwrap_or("unknown error"));
+        }
+        job
     } else {
-        vec![chapters.parse()?]
+        let chapters = chapters.context("Give a chapter range with --chapters, or --resume the last job")?;
+        let (start, end) = parse_range(chapters)?;
+        if let Some(previous_job) = storage.load::<GenerationJob>()? {
+            if !previous_job.is_finished() {
+                println!(
+                    "⚠ Replacing the unfinished job for chapters {}-{} ({} left); use --resume to continue it instead",
+                    previous_job.start, previous_job.end, previous_job.remaining().len()
+                );
+            }
+        }
+        let policy = if skip_existing { ExistingChapterPolicy::Skip } else { ExistingChapterPolicy::Overwrite };
+        println!("Generating {} chapters...", end - start + 1);
+        GenerationJob::new(project_uuid, start, end, policy)
     };
+    job.start();
+    storage.save(&job)?;
 
-    println!("Generating {} chapters...", chapter_nums.len());
+    // Project-level model settings override the global ones
+    let llm_config = match storage.load::<NovelProject>()? {
+        Some(project) => config_for_project(&config.llm, &project.model_config),
+        None => config.llm.clone(),
+    };
+    let plan: ChapterPlan = storage.load()?
+        .with_context(|| format!("Project {} has no chapter plan; run `plan -i {}` first", project_id, project_id))?;
+    let outline: Option<NovelOutline> = storage.load()?;
+    if outline.is_none() {
+        println!("⚠ No outline found; generating without world and character settings");
+    }
 
-    // Create mock LLM client
-    let llm_client = LlmClient::new(Box::new(crate::services::llm::QwenProvider::new("mock".to_string(), None)));
-    let service = GenerationService::new(llm_client);
+    let usage = Arc::new(UsageTracker::open(".", project_uuid)?);
+    let cancel = cancel_on_ctrl_c();
+    let service = GenerationService::from_config(&llm_config)?
+        .with_usage_tracker(usage.clone())
+        .with_cancellation(cancel.clone())
+        .with_segments(SegmentOptions::default());
 
-    // Create storage for project
-    let storage = StorageService::new_project(".", project_uuid)?;
+    if job.policy == ExistingChapterPolicy::Skip {
+        for chapter_num in job.remaining() {
+            if storage.load_chapter(chapter_num)?.is_some() {
+                println!("Chapter {} exists; skipped", chapter_num);
+                job.mark_skipped(chapter_num);
+            }
+        }
+        storage.save(&job)?;
+    }
 
-    for chapter_num in &chapter_nums {
-        let context = format!("Previous chapters context for chapter {}", chapter_num);
-        let prompt = format!("Generate chapter {} content based on outline", chapter_num);
+    let chapter_nums = job.remaining();
+    let concurrency = parallel.unwrap_or(config.generation.concurrency).max(1) as usize;
+    if concurrency > 1 && candidates > 1 {
+        println!("Writing chapters in order to draft candidates; up to {} candidates are drafted at a time", concurrency);
+    } else if concurrency > 1 {
+        let previous = match chapter_nums.first() {
+            Some(first) if *first > 1 => storage.load_chapter(first - 1)?,
+            _ => None,
+        };
+        println!("Drafting {} chapters, {} at a time...", chapter_nums.len(), concurrency);
 
-        let chapter = service.generate_chapter(
+        let result = service.generate_parallel(
             project_uuid,
-            *chapter_num,
-            &context,
-            &prompt,
-        ).await?;
+            outline.as_ref(),
+            &plan,
+            &chapter_nums,
+            previous.as_ref(),
+            concurrency,
+            &GenerationOptions::default(),
+            |chapter| {
+                storage.save_chapter(chapter, RevisionSource::Generated)?;
+                job.mark_done(chapter.chapter_number);
+                storage.save(&job)?;
+                println!("✓ {} ({} characters)", chapter.title, chapter.word_count);
+                Ok(())
+            },
+        ).await;
+
+        let drafts = match result {
+            Ok(drafts) => drafts,
+            Err(e) => {
+                // Drafts are saved in order, so the first one left is the one that failed
+                if let Some(failed) = job.remaining().first().copied() {
+                    job.mark_failed(failed, format!("{:#}", e));
+                    storage.save(&job)?;
+                }
+                return Err(e.context(format!(
+                    "Parallel generation failed; run `generate -i {} --resume` to retry",
+                    project_id
+                )));
+            }
+        };
+
+        // Save the openings rewritten by the continuity pass as revisions of
+        // the drafts; unchanged chapters add no revision. A cancelled pass may
+        // have rewritten some openings and not others.
+        let mut smoothed = 0;
+        for chapter in &drafts.chapters {
+            let revision = ChapterRevision::new(chapter, RevisionSource::Revised).with_instruction(CONTINUITY_INSTRUCTION);
+            if storage.save_chapter_revision(chapter, revision)?.source == RevisionSource::Revised {
+                smoothed += 1;
+            }
+        }
+        if cancel.is_cancelled() {
+            println!(
+                "\n⏹ Cancelled after {} chapters; {} chapter openings were smoothed",
+                drafts.chapters.len(),
+                smoothed
+            );
+        }
+        if !drafts.unsmoothed.is_empty() {
+            let seams: Vec<String> = drafts.unsmoothed.iter().map(|n| format!("{}→{}", n - 1, n)).collect();
+            println!("⚠ Openings not smoothed: {}", seams.join(", "));
+        }
+
+        for (chapter_num, e) in &drafts.failed {
+            println!("✗ Chapter {} failed: {:#}", chapter_num, e);
+            job.mark_failed(*chapter_num, format!("{:#}", e));
+        }
+        storage.save(&job)?;
+        finish(&job, project_id, drafts.chapters.len(), chapter_nums.len(), &usage);
+        if !drafts.failed.is_empty() {
+            anyhow::bail!(
+                "{} chapters failed; run `generate -i {} --resume` to retry",
+                drafts.failed.len(),
+                project_id
+            );
+        }
+        return Ok(());
+    }
+
+    // With [llm.embedding] configured, prompts also get the earlier passages
+    // about each chapter's characters and places
+    let mut retrieval = match &llm_config.embedding {
+        Some(_) => {
+            let mut retrieval = ContextService::new(create_embedding_provider(&llm_config)?, outline.as_ref());
+            let first = chapter_nums.first().copied().unwrap_or(1);
+            retrieval.index_chapters(&storage.load_chapters(1..=first.saturating_sub(1))?).await
+                .context("Failed to index earlier chapters for retrieval")?;
+            Some(retrieval)
+        }
+        None => None,
+    };
+
+    let mut previous: Option<GeneratedChapter> = None;
+    let mut generated = 0;
+    for chapter_num in chapter_nums.iter().copied() {
+        // Continue from the chapter before, whether written now or earlier
+        if chapter_num > 1 && previous.as_ref().map(|p| p.chapter_number + 1) != Some(chapter_num) {
+            previous = storage.load_chapter(chapter_num - 1)?;
+        }
+
+        let mut alternates = Vec::new();
+        let mut brief = ChapterBrief::new(outline.as_ref(), &plan, chapter_num, previous.as_ref());
+        if let (Ok(brief), Some(retrieval)) = (&mut brief, &retrieval) {
+            match retrieval.get_context(brief, RELATED_PASSAGES).await {
+                Ok(related) => brief.related = related,
+                Err(e) => println!("⚠ Retrieval failed; writing without earlier passages: {:#}", e),
+            }
+        }
+        let result = match brief {
+            Ok(brief) if candidates > 1 && brief.summary.is_plot_twist_chapter => {
+                println!("\n=== {} ===", brief.title());
+                println!("Plot-twist chapter: drafting {} candidates{}...", candidates, if judge { ", ranked with an LLM judge" } else { "" });
+
+                service.generate_candidates(
+                    project_uuid,
+                    &brief,
+                    candidates,
+                    concurrency,
+                    judge,
+                    &GenerationOptions::default(),
+                ).await.map(|mut ranked| {
+                    print_candidates(&ranked);
+                    let best = ranked.remove(0);
+                    alternates = ranked;
+                    best.chapter
+                })
+            }
+            Ok(brief) => {
+                println!("\n=== {} ===", brief.title());
+
+                // Print text as it streams in
+                let result = service.generate_planned_chapter_streaming(
+                    project_uuid,
+                    &brief,
+                    &GenerationOptions::default(),
+                    |chunk| {
+                        print!("{}", chunk);
+                        let _ = std::io::stdout().flush();
+                    },
+                ).await;
+                println!();
+                result
+            }
+            Err(e) => Err(e),
+        };
+
+        let chapter = match result {
+            Ok(chapter) => chapter,
+            Err(e) if LlmError::is_cancelled(&e) => {
+                println!("\n⏹ Cancelled during chapter {}; it was not saved", chapter_num);
+                break;
+            }
+            Err(e) => {
+                job.mark_failed(chapter_num, format!("{:#}", e));
+                storage.save(&job)?;
+                return Err(e.context(format!(
+                    "Chapter {} failed; run `generate -i {} --resume` to retry from it",
+                    chapter_num, project_id
+                )));
+            }
+        };
 
         // Save chapter to project directory
-        storage.save(&chapter)?;
+        storage.save_chapter(&chapter, RevisionSource::Generated)?;
+        if !alternates.is_empty() {
+            storage.save_candidates(chapter_num, &alternates)?;
+            println!("Kept {} alternate drafts in chapters/candidates/", alternates.len());
+        }
+        job.mark_done(chapter_num);
+        storage.save(&job)?;
+        generated += 1;
+        if let Some(retrieval) = &mut retrieval {
+            if let Err(e) = retrieval.index_chapters(std::slice::from_ref(&chapter)).await {
+                println!("⚠ Failed to index chapter {} for retrieval: {:#}", chapter_num, e);
+            }
+        }
 
-        println!("\n=== Chapter {} ===", chapter.chapter_number);
-        println!("Title: {}", chapter.title);
-        println!("Content preview: {}", &chapter.content[..chapter.content.len().min(200)]);
+        println!("\nTitle: {}", chapter.title);
         println!("Word count: {}", chapter.word_count);
+        previous = Some(chapter);
+
+        if cancel.is_cancelled() {
+            println!("\n⏹ Cancelled after chapter {}", chapter_num);
+            break;
+        }
     }
 
-    println!("\n✅ Generated {} chapters", chapter_nums.len());
+    finish(&job, project_id, generated, chapter_nums.len(), &usage);
+    Ok(())
+}
+
+/// Print the outcome of a run
+fn finish(job: &GenerationJob, project_id: &str, generated: usize, requested: usize, usage: &UsageTracker) {
+    println!("\n✅ Generated {} of {} chapters", generated, requested);
     println!("Saved to: projects/{}/chapters/", project_id);
+    if !job.is_finished() {
+        println!("Run `generate -i {} --resume` to continue", project_id);
+    }
+    println!("Tokens used so far: {} (see `usage -i {}`)", usage.ledger().total().total(), project_id);
+}
 
-    Ok(())
+/// Print the ranking of a chapter's candidate drafts, best first
+fn print_candidates(ranked: &[ChapterCandidate]) {
+    for candidate in ranked {
+        let score = &candidate.score;
+        let judge = score.judge.map_or("-".to_string(), |j| format!("{:.2}", j));
+        println!(
+            "  {}. draft {}: {:.2} ({} characters; length fit {:.2}, repetition {:.2}, key events {:.2}, filter {}, judge {})",
+            candidate.rank,
+            candidate.candidate,
+            score.total,
+            candidate.chapter.word_count,
+            score.length_fit,
+            score.repetition,
+            score.coverage,
+            if score.content_passed { "ok" } else { "flagged" },
+            judge
+        );
+    }
+}
+
+/// Parse a chapter number or a range like "1-10"
+fn parse_range(chapters: &str) -> Result<(u32, u32)> {
+    let (start, end) = match chapters.split_once('-') {
+        Some((start, end)) => (start.trim().parse()?, end.trim().parse()?),
+        None => {
+            let chapter = chapters.trim().parse()?;
+            (chapter, chapter)
+        }
+    };
+    anyhow::ensure!(start >= 1 && start <= end, "Invalid chapter range: {}", chapters);
+    Ok((start, end))
+}
+
+/// Token cancelled by the first Ctrl-C; a second one exits at once
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("\nCancelling... finished chapters are kept (Ctrl-C again to quit now)");
+        cancel.cancel();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    token
 }
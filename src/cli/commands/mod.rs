@@ -4,5 +4,9 @@ pub mod feasibility;
 pub mod outline;
 pub mod plan;
 pub mod generate;
+pub mod revise;
+pub mod revisions;
 pub mod publish;
 pub mod check;
+pub mod usage;
+pub mod cache;
@@ -0,0 +1,69 @@
+//! Revise Command - rewrite a chapter following an editor's instruction
+
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use uuid::Uuid;
+use crate::config::Config;
+use crate::models::{ChapterPlan, ChapterRevision, NovelOutline, NovelProject, RevisionSource};
+use crate::services::llm::{config_for_project, GenerationOptions};
+use crate::services::generation::GenerationService;
+use crate::services::{ChapterBrief, StorageService, UsageTracker};
+
+pub async fn run(config: &Config, project_id: &str, chapter_number: u32, instruction: &str) -> Result<()> {
+    tracing::info!("Revising chapter {} of {}: {}", chapter_number, project_id, instruction);
+
+    let project_uuid = Uuid::parse_str(project_id)?;
+    let storage = StorageService::new_project(".", project_uuid)?;
+
+    let chapter = storage.load_chapter(chapter_number)?
+        .with_context(|| format!("Project {} has no chapter {}", project_id, chapter_number))?;
+
+    // Project-level model settings override the global ones
+    let llm_config = match storage.load::<NovelProject>()? {
+        Some(project) => config_for_project(&config.llm, &project.model_config),
+        None => config.llm.clone(),
+    };
+
+    // Story context, when the plan covers this chapter
+    let plan: Option<ChapterPlan> = storage.load()?;
+    let outline: Option<NovelOutline> = storage.load()?;
+    let previous = match chapter_number {
+        1 => None,
+        n => storage.load_chapter(n - 1)?,
+    };
+    let brief = plan
+        .as_ref()
+        .and_then(|plan| ChapterBrief::new(outline.as_ref(), plan, chapter_number, previous.as_ref()).ok());
+    if brief.is_none() {
+        println!("⚠ Chapter {} is not in the chapter plan; revising without story context", chapter_number);
+    }
+
+    let usage = Arc::new(UsageTracker::open(".", project_uuid)?);
+    let service = GenerationService::from_config(&llm_config)?.with_usage_tracker(usage);
+
+    println!("\n=== {} (revising: {}) ===", chapter.title, instruction);
+    let revised = service.revise_chapter(
+        &chapter,
+        instruction,
+        brief.as_ref(),
+        &GenerationOptions::default(),
+        |chunk| {
+            print!("{}", chunk);
+            let _ = std::io::stdout().flush();
+        },
+    ).await?;
+    println!();
+
+    let revision = ChapterRevision::new(&revised, RevisionSource::Revised).with_instruction(instruction);
+    let revision = storage.save_chapter_revision(&revised, revision)?;
+
+    println!("\n✅ Revised chapter {}: {} → {} characters", chapter_number, chapter.word_count, revised.word_count);
+    println!(
+        "Saved as revision {}; see the changes with `revisions -i {} -c {} diff {}`",
+        revision.revision, project_id, chapter_number, revision.revision.saturating_sub(1).max(1)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Revisions Command - list, compare and restore saved versions of a chapter
+
+use anyhow::{bail, Context, Result};
+use uuid::Uuid;
+use crate::services::revision::collapse;
+use crate::services::{DiffKind, StorageService, TextChanges};
+
+/// Characters of unchanged text shown on each side of an edit
+const DIFF_CONTEXT_CHARS: usize = 40;
+
+/// List every revision of a chapter
+pub async fn list(project_id: &str, chapter_number: u32) -> Result<()> {
+    let storage = open(project_id)?;
+    let revisions = storage.list_revisions(chapter_number)?;
+    if revisions.is_empty() {
+        println!("Chapter {} has no saved revisions", chapter_number);
+        return Ok(());
+    }
+
+    println!("Revisions of chapter {}:", chapter_number);
+    for revision in &revisions {
+        let mut line = format!(
+            "  #{:<3} {:<9} {}  {} chars",
+            revision.revision,
+            revision.source,
+            revision.created_at.format("%Y-%m-%d %H:%M"),
+            revision.word_count
+        );
+        if let Some(from) = revision.restored_from {
+            line.push_str(&format!("  (restored from #{})", from));
+        }
+        if let Some(instruction) = &revision.instruction {
+            line.push_str(&format!("  \"{}\"", instruction));
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Show what changed from revision `from` to `to` (the latest if `None`)
+pub async fn diff(project_id: &str, chapter_number: u32, from: u32, to: Option<u32>) -> Result<()> {
+    let storage = open(project_id)?;
+    let revisions = storage.list_revisions(chapter_number)?;
+    let find = |number: u32| {
+        revisions
+            .iter()
+            .find(|r| r.revision == number)
+            .with_context(|| format!("Chapter {} has no revision {}", chapter_number, number))
+    };
+    let old = find(from)?;
+    let new = match to {
+        Some(to) => find(to)?,
+        None => revisions.last().context("Chapter has no saved revisions")?,
+    };
+
+    println!("--- #{} ({}) {}", old.revision, old.source, old.title);
+    println!("+++ #{} ({}) {}", new.revision, new.source, new.title);
+
+    let changes = TextChanges::new(&old.content, &new.content);
+    if changes.is_unchanged() {
+        println!("No changes");
+        return Ok(());
+    }
+    for span in &changes.spans {
+        match span.kind {
+            DiffKind::Equal => print!("{}", collapse(&span.text, DIFF_CONTEXT_CHARS)),
+            DiffKind::Delete => print!("[-{}-]", span.text),
+            DiffKind::Insert => print!("{{+{}+}}", span.text),
+        }
+    }
+    println!();
+    println!("\n{} characters deleted, {} inserted", changes.deleted(), changes.inserted());
+    Ok(())
+}
+
+/// Make an older revision the chapter's text again
+pub async fn restore(project_id: &str, chapter_number: u32, revision: u32) -> Result<()> {
+    let storage = open(project_id)?;
+    let latest = storage.list_revisions(chapter_number)?.last().map(|r| r.revision);
+    if latest == Some(revision) {
+        bail!("Revision {} is already the chapter's current text", revision);
+    }
+
+    let restored = storage.restore_revision(chapter_number, revision)?;
+    println!(
+        "✅ Restored chapter {} to revision {}; saved as revision {}",
+        chapter_number, revision, restored.revision
+    );
+    Ok(())
+}
+
+fn open(project_id: &str) -> Result<StorageService> {
+    let project_uuid = Uuid::parse_str(project_id)?;
+    StorageService::new_project(".", project_uuid)
+}
@@ -0,0 +1,55 @@
+//! Usage Command - token usage and cost report
+
+use anyhow::Result;
+use uuid::Uuid;
+use crate::config::Config;
+use crate::models::UsageLedger;
+use crate::services::{StorageService, UsageReport};
+
+pub async fn run(config: &Config, project_id: &str) -> Result<()> {
+    let project_uuid = Uuid::parse_str(project_id)?;
+
+    let storage = StorageService::new_project(".", project_uuid)?;
+    let ledger = storage
+        .load::<UsageLedger>()?
+        .unwrap_or_else(|| UsageLedger::new(project_uuid));
+
+    if ledger.records.is_empty() {
+        println!("No LLM usage recorded for project {}", project_id);
+        return Ok(());
+    }
+
+    let report = UsageReport::build(&ledger, &config.llm.pricing);
+
+    println!("\n=== Token Usage: {} ===", project_id);
+    println!(
+        "{:<10} {:>6} {:>14} {:>14} {:>12}",
+        "Stage", "Calls", "Prompt", "Completion", "Cost"
+    );
+    // Stages that made no calls, such as outline and plan, are left out
+    for stage in report.stages.iter().filter(|s| s.calls > 0) {
+        println!(
+            "{:<10} {:>6} {:>14} {:>14} {:>12.4}",
+            stage.stage.to_string(),
+            stage.calls,
+            stage.usage.prompt_tokens,
+            stage.usage.completion_tokens,
+            stage.cost
+        );
+    }
+    println!(
+        "{:<10} {:>6} {:>14} {:>14} {:>12.4}",
+        "Total",
+        ledger.records.len(),
+        report.total.prompt_tokens,
+        report.total.completion_tokens,
+        report.total_cost
+    );
+
+    if !report.unpriced.is_empty() {
+        println!("\n⚠️  No pricing configured for: {}", report.unpriced.join(", "));
+        println!("   Add [[llm.pricing]] entries to config.toml to include them in the cost.");
+    }
+
+    Ok(())
+}
@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
 
+pub use crate::models::{EndpointConfig, TaskRoutes};
+
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -41,6 +43,10 @@ pub struct LlmConfig {
     /// Group ID (required for MiniMax)
     pub group_id: Option<String>,
 
+    /// How long Ollama keeps the model loaded, e.g. "10m" (Ollama only)
+    #[serde(default)]
+    pub keep_alive: Option<String>,
+
     /// Temperature for generation
     #[serde(default = "default_temperature")]
     pub temperature: f32,
@@ -48,6 +54,148 @@ pub struct LlmConfig {
     /// Max tokens per request
     #[serde(default = "default_max_tokens")]
     pub max_tokens: u32,
+
+    /// Nucleus sampling threshold (optional)
+    #[serde(default)]
+    pub top_p: Option<f32>,
+
+    /// Sampling seed, for providers that accept one (optional)
+    #[serde(default)]
+    pub seed: Option<u64>,
+
+    /// Context window in tokens, overriding the one known for the model
+    #[serde(default)]
+    pub context_window: Option<u32>,
+
+    /// Most tokens the model writes in one reply, overriding the one known for the model
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+
+    /// Retry policy for failed requests
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Record/replay cassette for reproducible runs (optional)
+    #[serde(default)]
+    pub cassette: Option<CassetteConfig>,
+
+    /// Token prices used for cost reports
+    #[serde(default)]
+    pub pricing: Vec<ModelPricing>,
+
+    /// Providers tried in order when the primary one fails
+    #[serde(default)]
+    pub fallbacks: Vec<EndpointConfig>,
+
+    /// Per-task provider overrides
+    #[serde(default)]
+    pub routes: TaskRoutes,
+
+    /// Client-side request limits per provider
+    #[serde(default)]
+    pub rate_limits: Vec<RateLimitConfig>,
+
+    /// On-disk cache of responses (off by default)
+    #[serde(default)]
+    pub cache: ResponseCacheConfig,
+
+    /// Embedding provider for retrieval (defaults to the `[llm]` provider)
+    #[serde(default)]
+    pub embedding: Option<EmbeddingConfig>,
+}
+
+impl LlmConfig {
+    /// This config pointed at `endpoint`
+    ///
+    /// Fields the endpoint leaves unset are inherited from `[llm]` when it
+    /// uses the same provider. For another provider they come from the first
+    /// fallback or route entry of that provider, so credentials only need to
+    /// be written once.
+    pub fn with_endpoint(&self, endpoint: &EndpointConfig) -> LlmConfig {
+        let mut config = self.clone();
+
+        if let Some(provider) = &endpoint.provider {
+            if !provider.eq_ignore_ascii_case(&self.provider) {
+                let known = self
+                    .fallbacks
+                    .iter()
+                    .chain(self.routes.iter())
+                    .find(|e| e.provider.as_deref().is_some_and(|p| p.eq_ignore_ascii_case(provider)));
+
+                config.provider = provider.clone();
+                config.api_key = known.and_then(|e| e.api_key.clone()).unwrap_or_default();
+                config.base_url = known.and_then(|e| e.base_url.clone());
+                config.group_id = known.and_then(|e| e.group_id.clone());
+                config.model = known.and_then(|e| e.model.clone());
+            }
+        }
+
+        if let Some(model) = &endpoint.model {
+            config.model = Some(model.clone());
+        }
+        if let Some(api_key) = &endpoint.api_key {
+            config.api_key = api_key.clone();
+        }
+        if let Some(base_url) = &endpoint.base_url {
+            config.base_url = Some(base_url.clone());
+        }
+        if let Some(group_id) = &endpoint.group_id {
+            config.group_id = Some(group_id.clone());
+        }
+        config
+    }
+
+    /// The provider settings of this config as a self-contained endpoint
+    pub fn endpoint(&self) -> EndpointConfig {
+        EndpointConfig {
+            provider: Some(self.provider.clone()),
+            model: self.model.clone(),
+            api_key: Some(self.api_key.clone()),
+            base_url: self.base_url.clone(),
+            group_id: self.group_id.clone(),
+        }
+    }
+}
+
+/// Token prices for a provider, or one of its models
+///
+/// Prices are per million tokens; use the same currency for every entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPricing {
+    /// Provider name (qwen, minimax, openai, ...)
+    pub provider: String,
+
+    /// Model name; applies to all of the provider's models when unset
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// Price per million prompt tokens
+    pub input_per_million: f64,
+
+    /// Price per million completion tokens
+    pub output_per_million: f64,
+}
+
+/// Request limits for one provider
+///
+/// Limits are shared by every client of the provider in the process; unset
+/// limits are not enforced.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Provider name (qwen, minimax, openai, ...)
+    pub provider: String,
+
+    /// Requests per minute
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+
+    /// Prompt plus completion tokens per minute
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
+
+    /// Requests in flight at once
+    #[serde(default)]
+    pub max_concurrent: Option<u32>,
 }
 
 fn default_temperature() -> f32 {
@@ -58,6 +206,144 @@ fn default_max_tokens() -> u32 {
     4096
 }
 
+/// Retry settings for LLM requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Retries after the first attempt (0 disables retrying)
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Delay before the first retry, in milliseconds
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound for a single retry delay, in milliseconds
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+
+    /// Backoff growth factor per retry
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1_000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: default_max_retries(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            multiplier: default_backoff_multiplier(),
+        }
+    }
+}
+
+/// Record/replay settings for LLM calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CassetteConfig {
+    /// Cassette file path
+    pub path: String,
+
+    /// Whether to record real responses or replay recorded ones
+    pub mode: CassetteMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    /// Call the real provider and save every request/response pair
+    Record,
+    /// Serve saved responses without any network access
+    Replay,
+}
+
+/// Embedding provider settings
+///
+/// Unset credentials are taken from `[llm]` when the provider matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    /// Provider: "qwen" (DashScope), "openai", "openai_compatible", or
+    /// "local" for offline hashed n-gram vectors
+    #[serde(default)]
+    pub provider: Option<String>,
+
+    /// Embedding model, e.g. text-embedding-v2 or text-embedding-3-small
+    #[serde(default)]
+    pub model: Option<String>,
+
+    #[serde(default)]
+    pub api_key: Option<String>,
+
+    #[serde(default)]
+    pub base_url: Option<String>,
+
+    /// Texts per request (defaults to the provider's limit)
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+
+    /// Vector size of the local provider (default 512)
+    #[serde(default)]
+    pub dimensions: Option<usize>,
+}
+
+/// On-disk cache of LLM responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseCacheConfig {
+    /// Serve repeated requests from the cache
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Cache directory
+    #[serde(default = "default_response_cache_path")]
+    pub path: String,
+
+    /// Hours before an entry expires
+    #[serde(default = "default_response_cache_ttl_hours")]
+    pub ttl_hours: u32,
+
+    /// Size cap in megabytes; the oldest entries are evicted first
+    #[serde(default = "default_response_cache_max_mb")]
+    pub max_size_mb: u64,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_response_cache_path(),
+            ttl_hours: default_response_cache_ttl_hours(),
+            max_size_mb: default_response_cache_max_mb(),
+        }
+    }
+}
+
+fn default_response_cache_path() -> String {
+    "data/cache/llm".to_string()
+}
+
+fn default_response_cache_ttl_hours() -> u32 {
+    24 * 7
+}
+
+fn default_response_cache_max_mb() -> u64 {
+    100
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FanqieConfig {
     /// Enable Fanqie integration
@@ -94,6 +380,10 @@ pub struct GenerationConfig {
     /// Chapters per batch generation
     #[serde(default = "default_batch_size")]
     pub batch_size: u32,
+
+    /// Chapters drafted at the same time; 1 writes them one after another
+    #[serde(default = "default_concurrency")]
+    pub concurrency: u32,
 }
 
 fn default_word_count() -> u64 {
@@ -108,6 +398,10 @@ fn default_batch_size() -> u32 {
     10
 }
 
+fn default_concurrency() -> u32 {
+    1
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -117,8 +411,21 @@ impl Default for Config {
                 base_url: None,
                 model: None,
                 group_id: None,
+                keep_alive: None,
                 temperature: 0.8,
                 max_tokens: 4096,
+                top_p: None,
+                seed: None,
+                context_window: None,
+                max_output_tokens: None,
+                retry: RetryConfig::default(),
+                cassette: None,
+                pricing: Vec::new(),
+                fallbacks: Vec::new(),
+                routes: TaskRoutes::default(),
+                rate_limits: Vec::new(),
+                cache: ResponseCacheConfig::default(),
+                embedding: None,
             },
             fanqie: None,
             storage: StorageConfig {
@@ -128,6 +435,7 @@ impl Default for Config {
                 default_word_count: 1_000_000,
                 words_per_chapter: 10_000,
                 batch_size: 10,
+                concurrency: 1,
             },
         }
     }
@@ -4,11 +4,13 @@ use eframe::App;
 use egui::Context;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
 use crate::config::Config;
-use crate::models::{NovelGenre, NovelProject};
-use crate::services::StorageService;
+use crate::models::{ChapterRevision, GeneratedChapter, NovelGenre, NovelProject, RevisionSource};
+use crate::services::llm::{CancellationToken, LlmError};
+use crate::services::{StorageService, TextChanges};
 
 /// Screen types for navigation
 #[derive(Debug, Clone, PartialEq)]
@@ -31,6 +33,8 @@ pub enum TaskState {
     Idle,
     Running { progress: f32, message: String },
     Completed,
+    /// Stopped by the user; work finished before that was kept
+    Cancelled,
     Failed { error: String },
 }
 
@@ -64,6 +68,26 @@ pub struct GenerateForm {
     pub chapter_range: String,
 }
 
+/// Output shared with a background chapter generation task
+#[derive(Debug, Default)]
+pub struct GenerationOutput {
+    /// Text streamed so far
+    pub text: String,
+
+    /// Latest task state reported by the background task
+    pub state: Option<TaskState>,
+}
+
+impl GenerationOutput {
+    /// Whether the background task is still running
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, Some(TaskState::Running { .. }))
+    }
+}
+
+/// Installed model names, or why they could not be listed
+pub type ModelList = Result<Vec<String>, String>;
+
 /// Form for publishing
 #[derive(Debug, Clone, Default)]
 pub struct PublishForm {
@@ -130,6 +154,34 @@ pub struct NovelApp {
     /// Chapter generation result
     pub chapter_result: Option<String>,
 
+    /// Streaming output of the running chapter generation
+    pub generation_output: Arc<Mutex<GenerationOutput>>,
+
+    /// Cancels the running chapter generation
+    pub generation_cancel: Option<CancellationToken>,
+
+    /// Saved chapter numbers of the selected project
+    pub chapter_list: Vec<u32>,
+
+    /// Chapter shown on the chapter screen
+    pub current_chapter: Option<GeneratedChapter>,
+
+    /// Editor instruction for revising the current chapter
+    pub revision_instruction: String,
+
+    /// Streaming output of the running chapter revision
+    pub revision_output: Arc<Mutex<GenerationOutput>>,
+
+    /// Saved revisions of the current chapter, oldest first
+    pub chapter_revisions: Vec<ChapterRevision>,
+
+    /// Revisions compared in the history view, (older, newer), with their
+    /// diff
+    pub revision_diff: Option<((u32, u32), TextChanges)>,
+
+    /// Text being edited by hand, while the chapter is in edit mode
+    pub chapter_edit: Option<String>,
+
     /// Publish result
     pub publish_result: Option<String>,
 
@@ -138,6 +190,9 @@ pub struct NovelApp {
 
     /// Application configuration (loaded from config.local.toml)
     pub config: Config,
+
+    /// Models installed on the Ollama server, filled in by a background task
+    pub ollama_models: Arc<Mutex<Option<ModelList>>>,
 }
 
 impl Default for NovelApp {
@@ -170,9 +225,19 @@ impl Default for NovelApp {
             analysis_result: None,
             outline_result: None,
             chapter_result: None,
+            generation_output: Arc::new(Mutex::new(GenerationOutput::default())),
+            generation_cancel: None,
+            chapter_list: Vec::new(),
+            current_chapter: None,
+            revision_instruction: String::new(),
+            revision_output: Arc::new(Mutex::new(GenerationOutput::default())),
+            chapter_revisions: Vec::new(),
+            revision_diff: None,
+            chapter_edit: None,
             publish_result: None,
             projects_loaded: false,
             config,
+            ollama_models: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -294,7 +359,7 @@ impl NovelApp {
     /// Run feasibility analysis for a project
     pub fn run_feasibility_analysis(&mut self, genre: NovelGenre) -> Result<String, String> {
         // Check if API key is configured
-        if self.config.llm.api_key.is_empty() {
+        if !crate::services::llm::is_configured(&self.config.llm) {
             return Ok(format!(
                 "可行性分析功能需要配置LLM API。\n\n\
                 类型: {:?}\n\n\
@@ -320,7 +385,7 @@ impl NovelApp {
 
     /// Start feasibility analysis in background (for GUI use)
     pub fn start_feasibility_analysis(&mut self, genre: NovelGenre) {
-        if self.config.llm.api_key.is_empty() {
+        if !crate::services::llm::is_configured(&self.config.llm) {
             self.analysis_result = Some("请先在设置页面配置API Key".to_string());
             return;
         }
@@ -363,7 +428,7 @@ impl NovelApp {
     /// Generate outline for a project
     pub fn generate_outline(&mut self, _project_id: Uuid, genre: NovelGenre, premise: String, theme: String, target_words: u64) -> Result<String, String> {
         // Check if API key is configured
-        if self.config.llm.api_key.is_empty() {
+        if !crate::services::llm::is_configured(&self.config.llm) {
             return Ok(format!(
                 "大纲生成功能需要配置LLM API。\n\n\
                 设定: {}\n\
@@ -397,7 +462,7 @@ impl NovelApp {
 
     /// Start outline generation in background (for GUI use)
     pub fn start_outline_generation(&mut self, project_id: Uuid, genre: NovelGenre, premise: String, theme: String, target_words: u64) {
-        if self.config.llm.api_key.is_empty() {
+        if !crate::services::llm::is_configured(&self.config.llm) {
             self.outline_result = Some("请先在设置页面配置API Key".to_string());
             return;
         }
@@ -411,7 +476,7 @@ impl NovelApp {
             let service = crate::services::OutlineService::new();
             match service.generate(project_id, genre, premise_clone, theme_clone, target_words).await {
                 Ok(outline) => {
-                    let mut summary = format!("大纲生成完成\n\n");
+                    let mut summary = "大纲生成完成\n\n".to_string();
                     summary.push_str(&format!("设定: {}\n", premise));
                     summary.push_str(&format!("主题: {}\n", theme));
                     summary.push_str("情节线:\n");
@@ -431,20 +496,303 @@ impl NovelApp {
         self.outline_result = Some("大纲生成已在后台开始运行...".to_string());
     }
 
-    /// Generate chapters for a project
-    pub fn generate_chapters(&mut self, project_id: Uuid, chapter_start: u32, chapter_end: u32) -> Result<String, String> {
-        // Return instructions to use CLI
-        Ok(format!(
-            "章节生成功能\n\n\
-            项目ID: {}\n\
-            章节范围: {} - {}\n\n\
-            请使用命令行工具生成章节:\n\n\
-            cargo run --release -- generate \\\n\
-              --project-id {} \\\n\
-              --chapters {}-{}",
-            project_id, chapter_start, chapter_end,
-            project_id, chapter_start, chapter_end
-        ))
+    /// Fetch the models installed on the configured Ollama server into `ollama_models`
+    pub fn refresh_ollama_models(&mut self) {
+        let provider = crate::services::llm::ollama_provider(&self.config.llm);
+        let models = self.ollama_models.clone();
+        *models.lock().unwrap() = None;
+
+        tokio::spawn(async move {
+            let result = provider
+                .list_models()
+                .await
+                .map(|list| list.into_iter().map(|m| m.name).collect())
+                .map_err(|e| e.to_string());
+            *models.lock().unwrap() = Some(result);
+        });
+    }
+
+    /// Start chapter generation in background, streaming text into `generation_output`
+    ///
+    /// Ignored while a generation is already running.
+    pub fn start_chapter_generation(&mut self, project_id: Uuid, chapter_start: u32, chapter_end: u32) {
+        if self.generation_output.lock().unwrap().is_running() {
+            return;
+        }
+        if !crate::services::llm::is_configured(&self.config.llm) {
+            self.chapter_result = Some("请先在设置页面配置API Key".to_string());
+            return;
+        }
+
+        let total = chapter_end - chapter_start + 1;
+        {
+            let mut output = self.generation_output.lock().unwrap();
+            output.text.clear();
+            output.state = Some(TaskState::Running {
+                progress: 0.0,
+                message: format!("正在生成第{}章...", chapter_start),
+            });
+        }
+
+        let llm = self.config.llm.clone();
+        let storage_root = self.storage_root.clone();
+        let output = self.generation_output.clone();
+        let cancel = CancellationToken::new();
+        self.generation_cancel = Some(cancel.clone());
+
+        tokio::spawn(async move {
+            let result: anyhow::Result<()> = async {
+                let storage = StorageService::new_project(&storage_root, project_id)?;
+                let llm = match storage.load::<NovelProject>()? {
+                    Some(project) => crate::services::llm::config_for_project(&llm, &project.model_config),
+                    None => llm,
+                };
+                let plan: crate::models::ChapterPlan = storage.load()?
+                    .ok_or_else(|| anyhow::anyhow!("项目还没有章节规划，请先生成规划"))?;
+                let outline: Option<crate::models::NovelOutline> = storage.load()?;
+                let mut previous = storage.load_chapter(chapter_start.saturating_sub(1))?;
+                let usage = crate::services::UsageTracker::open(&storage_root, project_id)?;
+                let service = crate::services::GenerationService::from_config(&llm)?
+                    .with_usage_tracker(std::sync::Arc::new(usage))
+                    .with_cancellation(cancel.clone())
+                    .with_segments(crate::services::SegmentOptions::default());
+
+                // Saved after every chapter, so `generate --resume` can pick the run up
+                let mut job = crate::models::GenerationJob::new(
+                    project_id,
+                    chapter_start,
+                    chapter_end,
+                    crate::models::ExistingChapterPolicy::Overwrite,
+                );
+                job.start();
+                storage.save(&job)?;
+
+                for (index, chapter_num) in (chapter_start..=chapter_end).enumerate() {
+                    let brief = crate::services::ChapterBrief::new(
+                        outline.as_ref(),
+                        &plan,
+                        chapter_num,
+                        previous.as_ref(),
+                    )?;
+                    {
+                        let mut out = output.lock().unwrap();
+                        out.text.push_str(&format!("\n=== {} ===\n", brief.title()));
+                        out.state = Some(TaskState::Running {
+                            progress: index as f32 / total as f32,
+                            message: format!("正在生成第{}章...", chapter_num),
+                        });
+                    }
+
+                    let result = service.generate_planned_chapter_streaming(
+                        project_id,
+                        &brief,
+                        &crate::services::llm::GenerationOptions::default(),
+                        |chunk| output.lock().unwrap().text.push_str(chunk),
+                    ).await;
+                    let chapter = match result {
+                        Ok(chapter) => chapter,
+                        Err(e) if LlmError::is_cancelled(&e) => return Err(e),
+                        Err(e) => {
+                            job.mark_failed(chapter_num, format!("{:#}", e));
+                            storage.save(&job)?;
+                            return Err(e);
+                        }
+                    };
+
+                    storage.save_chapter(&chapter, crate::models::RevisionSource::Generated)?;
+                    job.mark_done(chapter_num);
+                    storage.save(&job)?;
+                    previous = Some(chapter);
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }.await;
+
+            let mut out = output.lock().unwrap();
+            match result {
+                Ok(()) if cancel.is_cancelled() => {
+                    tracing::info!("Chapter generation cancelled");
+                    out.state = Some(TaskState::Cancelled);
+                }
+                Ok(()) => {
+                    tracing::info!("Chapter generation completed");
+                    out.state = Some(TaskState::Completed);
+                }
+                Err(e) if LlmError::is_cancelled(&e) => {
+                    tracing::info!("Chapter generation cancelled");
+                    out.state = Some(TaskState::Cancelled);
+                }
+                Err(e) => {
+                    tracing::error!("Chapter generation failed: {}", e);
+                    out.state = Some(TaskState::Failed { error: e.to_string() });
+                }
+            }
+        });
+
+        self.chapter_result = Some(String::new());
+    }
+
+    /// Stop the running chapter generation after saving finished chapters
+    pub fn cancel_chapter_generation(&mut self) {
+        if let Some(cancel) = self.generation_cancel.take() {
+            cancel.cancel();
+        }
+    }
+
+    /// Reload the saved chapter numbers of the selected project
+    pub fn load_chapter_list(&mut self) {
+        self.chapter_list.clear();
+        let Some(project_id) = self.selected_project_id else {
+            return;
+        };
+        match StorageService::new_project(&self.storage_root, project_id).and_then(|s| s.list_chapters()) {
+            Ok(chapters) => self.chapter_list = chapters,
+            Err(e) => self.set_error(format!("加载章节列表失败: {}", e)),
+        }
+    }
+
+    /// Load chapter `number` of the selected project into `current_chapter`,
+    /// with its revision history
+    pub fn open_chapter(&mut self, number: u32) {
+        self.selected_chapter_number = Some(number);
+        self.current_chapter = None;
+        self.chapter_revisions.clear();
+        self.revision_diff = None;
+        self.chapter_edit = None;
+        let Some(project_id) = self.selected_project_id else {
+            return;
+        };
+        let loaded = StorageService::new_project(&self.storage_root, project_id)
+            .and_then(|s| Ok((s.load_chapter(number)?, s.list_revisions(number)?)));
+        match loaded {
+            Ok((chapter, revisions)) => {
+                self.current_chapter = chapter;
+                self.chapter_revisions = revisions;
+            }
+            Err(e) => self.set_error(format!("加载第{}章失败: {}", number, e)),
+        }
+    }
+
+    /// Save the hand-edited text of the current chapter as a new revision
+    pub fn save_chapter_edit(&mut self) {
+        let (Some(project_id), Some(mut chapter), Some(content)) =
+            (self.selected_project_id, self.current_chapter.clone(), self.chapter_edit.clone())
+        else {
+            return;
+        };
+        chapter.set_content(content);
+        let saved = StorageService::new_project(&self.storage_root, project_id)
+            .and_then(|s| s.save_chapter(&chapter, RevisionSource::Manual));
+        match saved {
+            Ok(_) => self.open_chapter(chapter.chapter_number),
+            Err(e) => self.set_error(format!("保存第{}章失败: {}", chapter.chapter_number, e)),
+        }
+    }
+
+    /// Compare revision `from` of the current chapter with revision `to`
+    ///
+    /// The diff is kept in `revision_diff` until another pair is compared
+    /// or the chapter is reopened.
+    pub fn compare_revisions(&mut self, from: u32, to: u32) {
+        if matches!(&self.revision_diff, Some((pair, _)) if *pair == (from, to)) {
+            return;
+        }
+        let find = |n: u32| self.chapter_revisions.iter().find(|r| r.revision == n);
+        self.revision_diff = match (find(from), find(to)) {
+            (Some(old), Some(new)) => Some(((from, to), TextChanges::new(&old.content, &new.content))),
+            _ => None,
+        };
+    }
+
+    /// Make revision `revision` the current chapter's text again
+    pub fn restore_chapter_revision(&mut self, revision: u32) {
+        let (Some(project_id), Some(number)) = (self.selected_project_id, self.selected_chapter_number) else {
+            return;
+        };
+        let restored = StorageService::new_project(&self.storage_root, project_id)
+            .and_then(|s| s.restore_revision(number, revision));
+        match restored {
+            Ok(_) => self.open_chapter(number),
+            Err(e) => self.set_error(format!("恢复第{}章版本{}失败: {}", number, revision, e)),
+        }
+    }
+
+    /// Start revising a chapter in background, streaming the revised text
+    /// into `revision_output`
+    ///
+    /// The revised text is saved as a new revision; earlier ones stay in the
+    /// chapter's history. Ignored while a revision is already running.
+    pub fn start_chapter_revision(&mut self, project_id: Uuid, chapter_number: u32, instruction: String) {
+        if self.revision_output.lock().unwrap().is_running() {
+            return;
+        }
+        if !crate::services::llm::is_configured(&self.config.llm) {
+            self.set_error("请先在设置页面配置API Key".to_string());
+            return;
+        }
+
+        {
+            let mut output = self.revision_output.lock().unwrap();
+            output.text.clear();
+            output.state = Some(TaskState::Running {
+                progress: 0.0,
+                message: format!("正在修改第{}章...", chapter_number),
+            });
+        }
+
+        let llm = self.config.llm.clone();
+        let storage_root = self.storage_root.clone();
+        let output = self.revision_output.clone();
+
+        tokio::spawn(async move {
+            let result: anyhow::Result<()> = async {
+                let storage = StorageService::new_project(&storage_root, project_id)?;
+                let chapter = storage.load_chapter(chapter_number)?
+                    .ok_or_else(|| anyhow::anyhow!("第{}章不存在", chapter_number))?;
+                let llm = match storage.load::<NovelProject>()? {
+                    Some(project) => crate::services::llm::config_for_project(&llm, &project.model_config),
+                    None => llm,
+                };
+
+                let plan: Option<crate::models::ChapterPlan> = storage.load()?;
+                let outline: Option<crate::models::NovelOutline> = storage.load()?;
+                let previous = storage.load_chapter(chapter_number.saturating_sub(1))?;
+                let brief = plan.as_ref().and_then(|plan| {
+                    crate::services::ChapterBrief::new(outline.as_ref(), plan, chapter_number, previous.as_ref()).ok()
+                });
+
+                let usage = crate::services::UsageTracker::open(&storage_root, project_id)?;
+                let service = crate::services::GenerationService::from_config(&llm)?
+                    .with_usage_tracker(std::sync::Arc::new(usage));
+                let revised = service.revise_chapter(
+                    &chapter,
+                    &instruction,
+                    brief.as_ref(),
+                    &crate::services::llm::GenerationOptions::default(),
+                    |chunk| output.lock().unwrap().text.push_str(chunk),
+                ).await?;
+
+                let revision = crate::models::ChapterRevision::new(&revised, crate::models::RevisionSource::Revised)
+                    .with_instruction(instruction);
+                storage.save_chapter_revision(&revised, revision)?;
+                Ok(())
+            }.await;
+
+            let mut out = output.lock().unwrap();
+            match result {
+                Ok(()) => {
+                    tracing::info!("Chapter {} revised", chapter_number);
+                    out.state = Some(TaskState::Completed);
+                }
+                Err(e) => {
+                    tracing::error!("Chapter revision failed: {}", e);
+                    out.state = Some(TaskState::Failed { error: e.to_string() });
+                }
+            }
+        });
     }
 
     /// Run consistency check
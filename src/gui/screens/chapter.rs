@@ -1,13 +1,20 @@
 //! 章节查看页面
 
-use egui::{ScrollArea, Ui, RichText};
+use egui::text::LayoutJob;
+use egui::{Color32, ProgressBar, ScrollArea, Stroke, TextFormat, Ui, RichText};
 
-use crate::gui::app::{NovelApp, Screen};
+use crate::gui::app::{NovelApp, Screen, TaskState};
+use crate::models::RevisionSource;
+use crate::services::revision::collapse;
+use crate::services::{DiffKind, TextChanges};
+
+/// Characters of unchanged text shown on each side of an edit
+const DIFF_CONTEXT_CHARS: usize = 60;
 
 /// 显示章节查看页面
 pub fn show(ui: &mut Ui, app: &mut NovelApp) {
     // 获取选中的项目ID和章节号
-    let _project_id = app.selected_project_id;
+    let project_id = app.selected_project_id;
     let chapter_number = app.selected_chapter_number;
 
     egui::SidePanel::left("left_panel").min_width(200.0).show_inside(ui, |ui| {
@@ -22,79 +29,275 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
         ui.separator();
 
         // 章节列表
-        ui.label(RichText::new("章节列表").strong());
-
-        // 生成一些示例章节（实际应该从存储加载）
-        let total_chapters = 40; // 默认40章
-        for i in 1..=total_chapters {
-            let is_selected = chapter_number == Some(i);
-            if ui.selectable_label(is_selected, format!("第{}章", i)).clicked() {
-                app.selected_chapter_number = Some(i);
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("章节列表").strong());
+            if ui.small_button("刷新").clicked() {
+                app.load_chapter_list();
+            }
+        });
+
+        if app.chapter_list.is_empty() {
+            ui.label("暂无已生成的章节");
+        }
+        let mut clicked = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            for &i in &app.chapter_list {
+                let is_selected = chapter_number == Some(i);
+                if ui.selectable_label(is_selected, format!("第{}章", i)).clicked() {
+                    clicked = Some(i);
+                }
             }
+        });
+        if let Some(i) = clicked {
+            app.open_chapter(i);
         }
     });
 
+    // 同步后台修改任务的状态
+    let (revised_text, revision_state) = {
+        let output = app.revision_output.lock().unwrap();
+        (output.text.clone(), output.state.clone())
+    };
+    if let Some(state) = revision_state {
+        let was_running = matches!(app.running_tasks.get("revise"), Some(TaskState::Running { .. }));
+        if matches!(state, TaskState::Running { .. }) {
+            ui.ctx().request_repaint();
+        } else if was_running && matches!(state, TaskState::Completed) {
+            // 重新加载修改后的章节
+            if let Some(number) = chapter_number {
+                app.open_chapter(number);
+            }
+        }
+        app.running_tasks.insert("revise".to_string(), state);
+    }
+
     egui::CentralPanel::default().show_inside(ui, |ui| {
-        if let Some(chapter_num) = chapter_number {
-            // 显示章节标题
+        let Some(chapter_num) = chapter_number else {
+            ui.vertical_centered(|ui| {
+                ui.add_space(50.0);
+                ui.label(RichText::new("请选择章节").size(20.0));
+                ui.add_space(20.0);
+                ui.label("从左侧列表选择要查看的章节");
+            });
+            return;
+        };
+        let Some(chapter) = app.current_chapter.clone() else {
             ui.heading(format!("第{}章", chapter_num));
-            ui.add_space(10.0);
+            ui.label("章节不存在或加载失败");
+            return;
+        };
+
+        // 显示章节标题
+        ui.heading(&chapter.title);
+        ui.add_space(10.0);
 
-            // 章节元信息
-            egui::CollapsingHeader::new("章节信息")
-                .default_open(true)
-                .show(ui, |ui| {
-                    ui.horizontal(|ui| {
-                        ui.label("字数: ");
-                        ui.label("约 3000 字");
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("状态: ");
-                        ui.label("已完成");
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("创建时间: ");
-                        ui.label("2024-01-01 10:00");
-                    });
+        // 章节元信息
+        egui::CollapsingHeader::new("章节信息")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("字数: ");
+                    ui.label(format!("{} 字", chapter.word_count));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("状态: ");
+                    ui.label(format!("{:?}", chapter.status));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("创建时间: ");
+                    ui.label(chapter.created_at.format("%Y-%m-%d %H:%M").to_string());
                 });
+                ui.horizontal(|ui| {
+                    ui.label("修改时间: ");
+                    ui.label(chapter.updated_at.format("%Y-%m-%d %H:%M").to_string());
+                });
+            });
 
-            ui.add_space(10.0);
-            ui.separator();
-            ui.add_space(10.0);
+        ui.add_space(10.0);
+        ui.separator();
 
-            // 章节内容
-            ui.label(RichText::new("章节内容").strong());
-            ui.add_space(5.0);
-
-            ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
-                // 示例章节内容
-                let content = format!(
-                    "第{}章内容预览\n\n这是第{}章的正文内容。在实际系统中，章节内容会从项目目录中的chapters/文件夹加载。\n\n每个章节以JSON格式存储，包含：\n- 标题 (title)\n- 正文 (content)\n- 字数 (word_count)\n- 状态 (status)\n- 创建时间 (created_at)\n\n您可以在项目详情页面点击生成章节来创建新章节。",
-                    chapter_num, chapter_num
-                );
-                ui.label(content);
-            });
+        // AI 修改
+        ui.label(RichText::new("AI 修改").strong());
+        ui.label("修改要求 (如: 把打斗写得更长、删掉回忆、改为女主视角):");
+        ui.text_edit_multiline(&mut app.revision_instruction);
 
-            ui.add_space(10.0);
-            ui.separator();
-            ui.add_space(10.0);
+        let running = app.revision_output.lock().unwrap().is_running();
+        ui.add_enabled_ui(!running, |ui| {
+            if ui.button("开始修改").clicked() {
+                let instruction = app.revision_instruction.trim().to_string();
+                if instruction.is_empty() {
+                    app.set_error("请输入修改要求".to_string());
+                } else if let Some(project_id) = project_id {
+                    app.start_chapter_revision(project_id, chapter_num, instruction);
+                }
+            }
+        });
+
+        match app.running_tasks.get("revise") {
+            Some(TaskState::Running { progress, message }) => {
+                ui.label(message);
+                ui.add(ProgressBar::new(*progress));
+                ScrollArea::vertical().id_salt("revision").max_height(200.0).stick_to_bottom(true).show(ui, |ui| {
+                    ui.label(&revised_text);
+                });
+            }
+            Some(TaskState::Completed) => {
+                ui.label("修改完成，原文保留在修订历史中");
+            }
+            Some(TaskState::Failed { error }) => {
+                ui.label(format!("错误: {}", error));
+            }
+            _ => {}
+        }
+
+        ui.add_space(10.0);
+        ui.separator();
+        ui.add_space(10.0);
+
+        // 章节内容
+        ui.label(RichText::new("章节内容").strong());
+        ui.add_space(5.0);
 
-            // 编辑按钮
+        let mut save_edit = false;
+        let mut cancel_edit = false;
+        if let Some(text) = app.chapter_edit.as_mut() {
+            ScrollArea::vertical().id_salt("content").max_height(400.0).show(ui, |ui| {
+                ui.add(egui::TextEdit::multiline(text).desired_width(f32::INFINITY));
+            });
             ui.horizontal(|ui| {
-                if ui.button("编辑章节").clicked() {
-                    // TODO: 实现编辑功能
-                }
-                if ui.button("删除章节").clicked() {
-                    // TODO: 实现删除功能
-                }
+                save_edit = ui.button("保存修改").clicked();
+                cancel_edit = ui.button("取消").clicked();
             });
         } else {
-            ui.vertical_centered(|ui| {
-                ui.add_space(50.0);
-                ui.label(RichText::new("请选择章节").size(20.0));
-                ui.add_space(20.0);
-                ui.label("从左侧列表选择要查看的章节");
+            ScrollArea::vertical().id_salt("content").max_height(400.0).show(ui, |ui| {
+                ui.label(&chapter.content);
             });
         }
+        if save_edit {
+            app.save_chapter_edit();
+        } else if cancel_edit {
+            app.chapter_edit = None;
+        }
+
+        ui.add_space(10.0);
+        ui.separator();
+        ui.add_space(10.0);
+
+        // 编辑按钮
+        ui.horizontal(|ui| {
+            if ui.add_enabled(app.chapter_edit.is_none(), egui::Button::new("编辑章节")).clicked() {
+                app.chapter_edit = Some(chapter.content.clone());
+            }
+            if ui.button("删除章节").clicked() {
+                // TODO: 实现删除功能
+            }
+        });
+
+        ui.add_space(10.0);
+        ui.separator();
+
+        show_revisions(ui, app);
     });
 }
+
+/// Revision history of the current chapter, with a diff between two revisions
+fn show_revisions(ui: &mut Ui, app: &mut NovelApp) {
+    egui::CollapsingHeader::new(format!("修订历史 ({})", app.chapter_revisions.len()))
+        .default_open(false)
+        .show(ui, |ui| {
+            if app.chapter_revisions.is_empty() {
+                ui.label("暂无修订记录");
+                return;
+            }
+
+            let latest = app.chapter_revisions.last().map(|r| r.revision);
+            let mut restore = None;
+            let mut compare = None;
+            egui::Grid::new("revisions").striped(true).show(ui, |ui| {
+                for revision in app.chapter_revisions.iter().rev() {
+                    ui.label(format!("#{}", revision.revision));
+                    ui.label(source_label(revision.source));
+                    ui.label(revision.created_at.format("%Y-%m-%d %H:%M").to_string());
+                    ui.label(format!("{} 字", revision.word_count));
+                    let mut note = revision.instruction.clone().unwrap_or_default();
+                    if let Some(from) = revision.restored_from {
+                        note = format!("恢复自 #{} {}", from, note);
+                    }
+                    ui.label(note);
+                    if Some(revision.revision) != latest {
+                        if ui.small_button("对比").clicked() {
+                            compare = latest.map(|to| (revision.revision, to));
+                        }
+                        if ui.small_button("恢复此版本").clicked() {
+                            restore = Some(revision.revision);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(revision) = restore {
+                app.restore_chapter_revision(revision);
+                return;
+            }
+            if let Some((from, to)) = compare {
+                app.compare_revisions(from, to);
+            }
+
+            let Some(((from, to), changes)) = &app.revision_diff else {
+                return;
+            };
+
+            ui.add_space(10.0);
+            ui.label(format!(
+                "#{} → #{}：删除 {} 字，新增 {} 字",
+                from,
+                to,
+                changes.deleted(),
+                changes.inserted()
+            ));
+            ScrollArea::vertical().id_salt("revision_diff").max_height(300.0).show(ui, |ui| {
+                ui.label(diff_layout(ui, changes));
+            });
+        });
+}
+
+/// Deletions struck through in red, insertions in green
+fn diff_layout(ui: &Ui, changes: &TextChanges) -> LayoutJob {
+    let color = ui.visuals().text_color();
+    let mut job = LayoutJob::default();
+    for span in &changes.spans {
+        let (text, format) = match span.kind {
+            DiffKind::Equal => (
+                collapse(&span.text, DIFF_CONTEXT_CHARS),
+                TextFormat { color, ..Default::default() },
+            ),
+            DiffKind::Delete => (
+                span.text.clone(),
+                TextFormat {
+                    color: Color32::from_rgb(200, 60, 60),
+                    strikethrough: Stroke::new(1.0, Color32::from_rgb(200, 60, 60)),
+                    ..Default::default()
+                },
+            ),
+            DiffKind::Insert => (
+                span.text.clone(),
+                TextFormat {
+                    color: Color32::from_rgb(40, 150, 60),
+                    underline: Stroke::new(1.0, Color32::from_rgb(40, 150, 60)),
+                    ..Default::default()
+                },
+            ),
+        };
+        job.append(&text, 0.0, format);
+    }
+    job
+}
+
+fn source_label(source: RevisionSource) -> &'static str {
+    match source {
+        RevisionSource::Generated => "AI 生成",
+        RevisionSource::Revised => "AI 修改",
+        RevisionSource::Manual => "手动编辑",
+        RevisionSource::Imported => "导入",
+    }
+}
@@ -27,8 +27,9 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
 
         ui.separator();
 
-        // 生成按钮
-        if ui.button("生成").clicked() {
+        // 生成按钮，后台任务运行时不可用
+        let running = app.generation_output.lock().unwrap().is_running();
+        if ui.add_enabled(!running, egui::Button::new("生成")).clicked() {
             if app.generate_form.chapter_range.is_empty() {
                 app.set_error("请输入章节范围".to_string());
             } else {
@@ -48,23 +49,7 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
                 match (start, end) {
                     (Ok(s), Ok(e)) if s <= e => {
                         let project_id = app.selected_project_id.unwrap_or_default();
-                        match app.generate_chapters(project_id, s, e) {
-                            Ok(result) => {
-                                app.chapter_result = Some(result);
-                                app.running_tasks.insert(
-                                    "generate".to_string(),
-                                    TaskState::Completed,
-                                );
-                            }
-                            Err(e) => {
-                                let err_msg = e.clone();
-                                app.set_error(e);
-                                app.running_tasks.insert(
-                                    "generate".to_string(),
-                                    TaskState::Failed { error: err_msg },
-                                );
-                            }
-                        }
+                        app.start_chapter_generation(project_id, s, e);
                     }
                     _ => {
                         app.set_error("无效的章节范围".to_string());
@@ -73,13 +58,38 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
             }
         }
 
+        // 同步后台任务的流式输出
+        let (streamed_text, streamed_state) = {
+            let output = app.generation_output.lock().unwrap();
+            (output.text.clone(), output.state.clone())
+        };
+        if let Some(state) = streamed_state {
+            if matches!(state, TaskState::Running { .. }) {
+                ui.ctx().request_repaint();
+            }
+            app.running_tasks.insert("generate".to_string(), state);
+        }
+        if !streamed_text.is_empty() {
+            app.chapter_result = Some(streamed_text);
+        }
+
         // 显示进度
+        let mut cancel_clicked = false;
         if let Some(task_state) = app.running_tasks.get("generate") {
             match task_state {
                 TaskState::Running { progress, message } => {
                     ui.separator();
                     ui.label(message);
                     ui.add(ProgressBar::new(*progress));
+                    if app.generation_cancel.is_some() {
+                        cancel_clicked = ui.button("取消").clicked();
+                    } else {
+                        ui.label("正在取消...");
+                    }
+                }
+                TaskState::Cancelled => {
+                    ui.separator();
+                    ui.label("已取消，已完成的章节已保存");
                 }
                 TaskState::Failed { error } => {
                     ui.separator();
@@ -93,6 +103,10 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
             }
         }
 
+        if cancel_clicked {
+            app.cancel_chapter_generation();
+        }
+
         // 显示生成的内容
         ui.separator();
         ui.label("生成内容:");
@@ -206,6 +206,7 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
                 ui.add_space(10.0);
                 ui.horizontal(|ui| {
                     if ui.button("查看章节").clicked() {
+                        app.load_chapter_list();
                         app.navigate_to(Screen::Chapter);
                     }
                     if ui.button("生成章节").clicked() {
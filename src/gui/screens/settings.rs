@@ -21,7 +21,7 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
         ui.add_space(10.0);
 
         // 显示当前配置状态
-        let has_api_key = !app.config.llm.api_key.is_empty();
+        let has_api_key = crate::services::llm::is_configured(&app.config.llm);
         let config_status = if has_api_key {
             "✓ 已配置"
         } else {
@@ -56,6 +56,8 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
                         ui.selectable_value(&mut provider_selected, "qwen".to_string(), "Qwen (阿里)");
                         ui.selectable_value(&mut provider_selected, "openai".to_string(), "OpenAI");
                         ui.selectable_value(&mut provider_selected, "anthropic".to_string(), "Anthropic");
+                        ui.selectable_value(&mut provider_selected, "openai_compatible".to_string(), "OpenAI 兼容 (本地)");
+                        ui.selectable_value(&mut provider_selected, "ollama".to_string(), "Ollama (本地)");
                     });
 
                 // 如果提供商改变了，更新配置
@@ -73,12 +75,50 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
 
                 ui.add_space(10.0);
 
+                // API 地址
+                let mut base_url = app.config.llm.base_url.clone().unwrap_or_default();
+                ui.label("API 地址 (可选，本地服务必填):");
+                ui.text_edit_singleline(&mut base_url);
+                app.config.llm.base_url = if base_url.is_empty() { None } else { Some(base_url) };
+
+                ui.add_space(10.0);
+
                 // 模型选择
                 let mut model = app.config.llm.model.clone().unwrap_or_default();
                 ui.label("模型名称:");
                 ui.text_edit_singleline(&mut model);
                 app.config.llm.model = if model.is_empty() { None } else { Some(model) };
 
+                // Ollama 已安装模型
+                if app.config.llm.provider == "ollama" {
+                    ui.horizontal(|ui| {
+                        if ui.button("刷新模型列表").clicked() {
+                            app.refresh_ollama_models();
+                        }
+
+                        let models = app.ollama_models.lock().unwrap().clone();
+                        match models {
+                            Some(Ok(models)) => {
+                                let mut selected = app.config.llm.model.clone().unwrap_or_default();
+                                ComboBox::from_id_salt("ollama_model_selector")
+                                    .selected_text(if selected.is_empty() { "选择已安装模型" } else { selected.as_str() })
+                                    .show_ui(ui, |ui| {
+                                        for name in &models {
+                                            ui.selectable_value(&mut selected, name.clone(), name);
+                                        }
+                                    });
+                                if !selected.is_empty() {
+                                    app.config.llm.model = Some(selected);
+                                }
+                            }
+                            Some(Err(e)) => {
+                                ui.colored_label(egui::Color32::RED, format!("获取模型失败: {}", e));
+                            }
+                            None => {}
+                        }
+                    });
+                }
+
                 ui.add_space(10.0);
 
                 // Temperature
@@ -144,6 +184,8 @@ pub fn show(ui: &mut Ui, app: &mut NovelApp) {
                 ui.label("• Qwen (阿里通义千问)");
                 ui.label("• OpenAI (GPT系列)");
                 ui.label("• Anthropic (Claude系列)");
+                ui.label("• OpenAI 兼容服务 (vLLM / llama.cpp / LM Studio)");
+                ui.label("• Ollama (本地模型)");
             });
     });
 }
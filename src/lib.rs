@@ -7,6 +7,7 @@ pub mod services;
 pub mod cli;
 pub mod config;
 pub mod gui;
+pub mod text;
 
 /// Re-export commonly used types
 pub use anyhow::Result;
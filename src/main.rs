@@ -18,6 +18,18 @@ struct Cli {
     /// Enable verbose logging
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
+
+    /// Record LLM responses to a cassette file
+    #[arg(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay LLM responses from a cassette file instead of calling the API
+    #[arg(long, global = true, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Bypass the LLM response cache
+    #[arg(long, global = true)]
+    no_cache: bool,
 }
 
 #[derive(Subcommand)]
@@ -88,8 +100,58 @@ enum Commands {
         project_id: String,
 
         /// Chapter number (or range like "1-10")
-        #[arg(short = 'c', long = "chapters")]
-        chapters: String,
+        #[arg(short = 'c', long = "chapters", required_unless_present = "resume")]
+        chapters: Option<String>,
+
+        /// Continue the project's last generation job
+        #[arg(long, conflicts_with_all = ["chapters", "skip_existing"])]
+        resume: bool,
+
+        /// Keep chapters that already exist instead of regenerating them
+        #[arg(long)]
+        skip_existing: bool,
+
+        /// Chapters drafted at the same time (default: generation.concurrency)
+        #[arg(short = 'p', long)]
+        parallel: Option<u32>,
+
+        /// Drafts written for each plot-twist chapter; the best one is kept
+        #[arg(long, default_value_t = 1)]
+        candidates: u32,
+
+        /// Let the LLM judge candidate drafts as well
+        #[arg(long, requires = "candidates")]
+        judge: bool,
+    },
+
+    /// Revise a chapter following an editor's instruction
+    Revise {
+        /// Project ID
+        #[arg(short = 'i', long = "project-id")]
+        project_id: String,
+
+        /// Chapter number
+        #[arg(short = 'c', long = "chapter")]
+        chapter: u32,
+
+        /// What to change, e.g. "make the fight longer"
+        #[arg(short = 'n', long = "instruction")]
+        instruction: String,
+    },
+
+    /// List, compare and restore saved versions of a chapter
+    Revisions {
+        /// Project ID
+        #[arg(short = 'i', long = "project-id")]
+        project_id: String,
+
+        /// Chapter number
+        #[arg(short = 'c', long = "chapter")]
+        chapter: u32,
+
+        /// Subcommand (default: list)
+        #[command(subcommand)]
+        action: Option<RevisionAction>,
     },
 
     /// Publish to Fanqie platform
@@ -110,10 +172,50 @@ enum Commands {
         project_id: String,
     },
 
+    /// Show token usage and cost by stage
+    Usage {
+        /// Project ID
+        #[arg(short = 'i', long = "project-id")]
+        project_id: String,
+    },
+
+    /// Manage the LLM response cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+
     /// Launch GUI
     Gui,
 }
 
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Delete every cached LLM response
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum RevisionAction {
+    /// List every revision
+    List,
+
+    /// Show character changes between two revisions
+    Diff {
+        /// Older revision
+        from: u32,
+
+        /// Newer revision (default: the latest)
+        to: Option<u32>,
+    },
+
+    /// Make an older revision the chapter's text again
+    Restore {
+        /// Revision to restore
+        revision: u32,
+    },
+}
+
 #[derive(Subcommand)]
 enum PublishAction {
     /// Create novel on Fanqie
@@ -151,7 +253,25 @@ async fn main() -> Result<()> {
     tracing::info!("Starting AI Novel Agent");
 
     // Load config
-    let _config = ai_novel_agent::config::load_config(&cli.config)?;
+    let mut config = ai_novel_agent::config::load_config(&cli.config)?;
+
+    // Cassette flags override the config file
+    use ai_novel_agent::config::{CassetteConfig, CassetteMode};
+    if let Some(path) = &cli.record {
+        config.llm.cassette = Some(CassetteConfig {
+            path: path.to_string_lossy().to_string(),
+            mode: CassetteMode::Record,
+        });
+    } else if let Some(path) = &cli.replay {
+        config.llm.cassette = Some(CassetteConfig {
+            path: path.to_string_lossy().to_string(),
+            mode: CassetteMode::Replay,
+        });
+    }
+
+    if cli.no_cache {
+        config.llm.cache.enabled = false;
+    }
 
     // Execute command
     match cli.command {
@@ -247,9 +367,29 @@ async fn main() -> Result<()> {
             tracing::info!("Generating chapter plan for: {}", project_id);
             ai_novel_agent::cli::commands::plan::run(&project_id).await?;
         }
-        Commands::Generate { project_id, chapters } => {
-            tracing::info!("Generating chapters {} for: {}", chapters, project_id);
-            ai_novel_agent::cli::commands::generate::run(&project_id, &chapters).await?;
+        Commands::Generate { project_id, chapters, resume, skip_existing, parallel, candidates, judge } => {
+            tracing::info!("Generating chapters {:?} for: {}", chapters, project_id);
+            ai_novel_agent::cli::commands::generate::run(
+                &config,
+                &project_id,
+                chapters.as_deref(),
+                resume,
+                skip_existing,
+                parallel,
+                candidates,
+                judge,
+            ).await?;
+        }
+        Commands::Revise { project_id, chapter, instruction } => {
+            ai_novel_agent::cli::commands::revise::run(&config, &project_id, chapter, &instruction).await?;
+        }
+        Commands::Revisions { project_id, chapter, action } => {
+            use ai_novel_agent::cli::commands::revisions;
+            match action.unwrap_or(RevisionAction::List) {
+                RevisionAction::List => revisions::list(&project_id, chapter).await?,
+                RevisionAction::Diff { from, to } => revisions::diff(&project_id, chapter, from, to).await?,
+                RevisionAction::Restore { revision } => revisions::restore(&project_id, chapter, revision).await?,
+            }
         }
         Commands::Publish { project_id, action } => {
             tracing::info!("Publishing {} to Fanqie", project_id);
@@ -264,6 +404,12 @@ async fn main() -> Result<()> {
             tracing::info!("Checking consistency for: {}", project_id);
             ai_novel_agent::cli::commands::check::run(&project_id).await?;
         }
+        Commands::Usage { project_id } => {
+            ai_novel_agent::cli::commands::usage::run(&config, &project_id).await?;
+        }
+        Commands::Cache { action: CacheAction::Clear } => {
+            ai_novel_agent::cli::commands::cache::clear(&config).await?;
+        }
         Commands::Gui => {
             tracing::info!("Launching GUI");
             if let Err(e) = run_gui() {
@@ -310,7 +456,7 @@ fn run_gui() -> std::result::Result<(), eframe::Error> {
                     for handle in family.fonts() {
                         match handle {
                             font_kit::handle::Handle::Path { path, font_index: _ } => {
-                                if let Ok(font_data) = std::fs::read(&path) {
+                                if let Ok(font_data) = std::fs::read(path) {
                                     fonts.font_data.insert(
                                         "chinese".to_string(),
                                         egui::FontData::from_owned(font_data),
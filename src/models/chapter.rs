@@ -52,7 +52,7 @@ pub struct GeneratedChapter {
 }
 
 /// Generation parameters
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GenerationParams {
     /// Model used
     pub model: String,
@@ -62,6 +62,18 @@ pub struct GenerationParams {
 
     /// Max tokens
     pub max_tokens: u32,
+
+    /// Nucleus sampling threshold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
+    /// Stop sequences
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+
+    /// Sampling seed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
 impl GeneratedChapter {
@@ -91,6 +103,13 @@ impl GeneratedChapter {
         }
     }
 
+    /// Replace the text, updating the word count
+    pub fn set_content(&mut self, content: String) {
+        self.word_count = content.chars().count() as u32;
+        self.content = content;
+        self.updated_at = Utc::now();
+    }
+
     /// Approve chapter
     pub fn approve(&mut self) {
         self.status = ChapterStatus::Approved;
@@ -98,6 +117,126 @@ impl GeneratedChapter {
     }
 }
 
+/// Where a chapter revision came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionSource {
+    Generated,
+    Revised,
+    Manual,
+    Imported,
+}
+
+impl std::fmt::Display for RevisionSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RevisionSource::Generated => write!(f, "generated"),
+            RevisionSource::Revised => write!(f, "revised"),
+            RevisionSource::Manual => write!(f, "manual"),
+            RevisionSource::Imported => write!(f, "imported"),
+        }
+    }
+}
+
+/// One saved version of a chapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterRevision {
+    /// Revision number, from 1; assigned when saved
+    pub revision: u32,
+
+    /// Chapter number
+    pub chapter_number: u32,
+
+    /// Where the text came from
+    pub source: RevisionSource,
+
+    /// Editor instruction, for revised text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<String>,
+
+    /// Revision this one restores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<u32>,
+
+    /// Title
+    pub title: String,
+
+    /// Content
+    pub content: String,
+
+    /// Word count
+    pub word_count: u32,
+
+    /// Generation parameters
+    pub generation_params: GenerationParams,
+
+    /// Created timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+impl ChapterRevision {
+    /// Revision holding the current text of `chapter`
+    pub fn new(chapter: &GeneratedChapter, source: RevisionSource) -> Self {
+        Self {
+            revision: 0,
+            chapter_number: chapter.chapter_number,
+            source,
+            instruction: None,
+            restored_from: None,
+            title: chapter.title.clone(),
+            content: chapter.content.clone(),
+            word_count: chapter.word_count,
+            generation_params: chapter.generation_params.clone(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Record the editor instruction the text was revised with
+    pub fn with_instruction(mut self, instruction: impl Into<String>) -> Self {
+        self.instruction = Some(instruction.into());
+        self
+    }
+}
+
+/// How a candidate draft scored; every metric is between 0 and 1
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CandidateScore {
+    /// Closeness to the planned length, 1 when on target
+    pub length_fit: f32,
+
+    /// Share of the text in repeated sentences, 0 when nothing repeats
+    pub repetition: f32,
+
+    /// Share of the plan's key events the text covers
+    pub coverage: f32,
+
+    /// Whether the content filter passed the text
+    pub content_passed: bool,
+
+    /// LLM judge score, if the judge ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judge: Option<f32>,
+
+    /// Combined score candidates are ranked by
+    pub total: f32,
+}
+
+/// One of several drafts written for a chapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterCandidate {
+    /// Draft number, from 1, in the order drafts were requested
+    pub candidate: u32,
+
+    /// Position in the ranking, 1 for the best draft
+    pub rank: u32,
+
+    /// Score
+    pub score: CandidateScore,
+
+    /// The draft
+    pub chapter: GeneratedChapter,
+}
+
 /// Chapter plan summary
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChapterSummary {
@@ -184,6 +323,7 @@ mod tests {
             model: "qwen2.5".to_string(),
             temperature: 0.8,
             max_tokens: 4096,
+            ..Default::default()
         };
 
         let chapter = GeneratedChapter::new(
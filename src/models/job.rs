@@ -0,0 +1,213 @@
+//! Generation Job Models
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::ProgressTracker;
+
+/// What to do with chapters that already exist
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingChapterPolicy {
+    /// Keep the saved chapter and move on
+    Skip,
+    /// Generate the chapter again and replace it
+    #[default]
+    Overwrite,
+}
+
+/// State of one chapter in a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobChapterState {
+    Pending,
+    Done,
+    Skipped,
+    Failed,
+}
+
+/// One chapter of a job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobChapter {
+    /// Chapter number
+    pub number: u32,
+
+    /// State
+    pub state: JobChapterState,
+
+    /// Generation attempts so far
+    pub attempts: u32,
+
+    /// Error of the last failed attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A batch generation run, saved after every chapter so it can be resumed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationJob {
+    /// Unique identifier
+    pub id: Uuid,
+
+    /// Project ID
+    pub project_id: Uuid,
+
+    /// First chapter requested
+    pub start: u32,
+
+    /// Last chapter requested
+    pub end: u32,
+
+    /// What to do with chapters that already exist
+    pub policy: ExistingChapterPolicy,
+
+    /// Every chapter of the range, in order
+    pub chapters: Vec<JobChapter>,
+
+    /// Progress over the range
+    pub progress: ProgressTracker,
+
+    /// Created timestamp
+    pub created_at: DateTime<Utc>,
+
+    /// Updated timestamp
+    pub updated_at: DateTime<Utc>,
+}
+
+impl GenerationJob {
+    /// Create a job for chapters `start..=end`
+    pub fn new(project_id: Uuid, start: u32, end: u32, policy: ExistingChapterPolicy) -> Self {
+        let chapters: Vec<JobChapter> = (start..=end)
+            .map(|number| JobChapter {
+                number,
+                state: JobChapterState::Pending,
+                attempts: 0,
+                error: None,
+            })
+            .collect();
+        let now = Utc::now();
+
+        Self {
+            id: Uuid::new_v4(),
+            project_id,
+            start,
+            end,
+            policy,
+            progress: ProgressTracker::new(chapters.len() as u32),
+            chapters,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Chapters still to generate, in order: pending and failed ones
+    pub fn remaining(&self) -> Vec<u32> {
+        self.chapters
+            .iter()
+            .filter(|c| matches!(c.state, JobChapterState::Pending | JobChapterState::Failed))
+            .map(|c| c.number)
+            .collect()
+    }
+
+    /// Whether every chapter is done or skipped
+    pub fn is_finished(&self) -> bool {
+        self.remaining().is_empty()
+    }
+
+    /// Chapters whose last attempt failed
+    pub fn failures(&self) -> impl Iterator<Item = &JobChapter> {
+        self.chapters.iter().filter(|c| c.state == JobChapterState::Failed)
+    }
+
+    /// Mark the job as running
+    pub fn start(&mut self) {
+        self.progress.start();
+        self.updated_at = Utc::now();
+    }
+
+    /// Record that `number` was generated and saved
+    pub fn mark_done(&mut self, number: u32) {
+        self.finish(number, JobChapterState::Done);
+    }
+
+    /// Record that `number` was kept as it was
+    pub fn mark_skipped(&mut self, number: u32) {
+        self.finish(number, JobChapterState::Skipped);
+    }
+
+    /// Record a failed attempt at `number`
+    pub fn mark_failed(&mut self, number: u32, error: impl Into<String>) {
+        if let Some(chapter) = self.chapter_mut(number) {
+            chapter.state = JobChapterState::Failed;
+            chapter.attempts += 1;
+            chapter.error = Some(error.into());
+            self.progress.fail();
+        }
+        self.update_progress();
+    }
+
+    /// Marking a chapter done or skipped again changes nothing
+    fn finish(&mut self, number: u32, state: JobChapterState) {
+        if let Some(chapter) = self.chapter_mut(number).filter(|c| c.state != state) {
+            if state == JobChapterState::Done {
+                chapter.attempts += 1;
+            }
+            chapter.state = state;
+            chapter.error = None;
+        }
+        self.update_progress();
+    }
+
+    /// Count chapters by state, so a chapter is never counted twice
+    fn update_progress(&mut self) {
+        let count = |states: &[JobChapterState]| {
+            self.chapters.iter().filter(|c| states.contains(&c.state)).count() as u32
+        };
+        let finished = count(&[JobChapterState::Done, JobChapterState::Skipped]);
+        let failed = count(&[JobChapterState::Failed]);
+        self.progress.set_counts(finished, failed);
+        self.updated_at = Utc::now();
+    }
+
+    fn chapter_mut(&mut self, number: u32) -> Option<&mut JobChapter> {
+        self.chapters.iter_mut().find(|c| c.number == number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ProgressStatus;
+
+    #[test]
+    fn test_job_tracks_chapters_and_failures() {
+        let mut job = GenerationJob::new(Uuid::new_v4(), 3, 6, ExistingChapterPolicy::Skip);
+        job.start();
+        job.mark_skipped(3);
+        job.mark_done(4);
+        job.mark_failed(5, "timeout");
+        assert_eq!(job.remaining(), vec![5, 6]);
+        assert_eq!(job.progress.status, ProgressStatus::Failed);
+        assert_eq!((job.progress.completed, job.progress.failed), (2, 1));
+
+        // Saved and loaded as part of the project
+        let json = serde_json::to_string(&job).unwrap();
+        let mut job: GenerationJob = serde_json::from_str(&json).unwrap();
+        assert_eq!(job.failures().map(|c| c.number).collect::<Vec<_>>(), vec![5]);
+
+        job.start();
+        job.mark_done(5);
+        job.mark_done(6);
+        // Repeated marks are not counted again
+        job.mark_done(6);
+        job.mark_skipped(3);
+        assert!(job.is_finished());
+        assert_eq!((job.progress.completed, job.progress.failed), (4, 0));
+        assert_eq!(job.progress.progress_percentage(), 100.0);
+        assert_eq!(job.chapters[3].attempts, 1);
+        assert_eq!(job.progress.status, ProgressStatus::Completed);
+        assert_eq!(job.chapters[2].attempts, 2);
+        assert!(job.chapters[2].error.is_none());
+    }
+}
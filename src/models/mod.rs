@@ -6,6 +6,9 @@ pub mod outline;
 pub mod feasibility;
 pub mod fanqie;
 pub mod validation;
+pub mod usage;
+pub mod job;
+pub mod progress;
 
 pub use novel::*;
 pub use chapter::*;
@@ -13,3 +16,6 @@ pub use outline::*;
 pub use feasibility::*;
 pub use fanqie::*;
 pub use validation::*;
+pub use usage::*;
+pub use job::*;
+pub use progress::*;
@@ -13,6 +13,82 @@ pub struct ProjectModelConfig {
     pub provider: Option<String>,
     /// Model name
     pub model: Option<String>,
+    /// Fallback chain replacing the global one (empty keeps the global chain)
+    #[serde(default)]
+    pub fallbacks: Vec<EndpointConfig>,
+    /// Task routes; each route set here replaces the global one
+    #[serde(default)]
+    pub routes: TaskRoutes,
+}
+
+/// One provider in a fallback chain or task route
+///
+/// Every field is optional; unset fields are filled in from the global
+/// `[llm]` config when the endpoint is resolved.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointConfig {
+    /// Provider name; defaults to the `[llm]` provider
+    #[serde(default)]
+    pub provider: Option<String>,
+
+    /// Model name
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// API key
+    #[serde(default)]
+    pub api_key: Option<String>,
+
+    /// Base URL for API
+    #[serde(default)]
+    pub base_url: Option<String>,
+
+    /// Group ID (MiniMax)
+    #[serde(default)]
+    pub group_id: Option<String>,
+}
+
+/// Provider overrides by task type; unset tasks use `[llm]`
+///
+/// Only tasks that call the LLM have a route.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskRoutes {
+    /// Chapter prose
+    #[serde(default)]
+    pub prose: Option<EndpointConfig>,
+
+    /// Condensing story material that does not fit the context window
+    #[serde(default)]
+    pub summary: Option<EndpointConfig>,
+
+    /// Candidate judging and the continuity pass
+    #[serde(default)]
+    pub consistency: Option<EndpointConfig>,
+}
+
+impl TaskRoutes {
+    /// Configured routes
+    pub fn iter(&self) -> impl Iterator<Item = &EndpointConfig> {
+        [&self.prose, &self.summary, &self.consistency].into_iter().flatten()
+    }
+
+    /// Routes with `f` applied to each configured endpoint
+    pub fn map(&self, f: impl Fn(&EndpointConfig) -> EndpointConfig) -> TaskRoutes {
+        TaskRoutes {
+            prose: self.prose.as_ref().map(&f),
+            summary: self.summary.as_ref().map(&f),
+            consistency: self.consistency.as_ref().map(&f),
+        }
+    }
+
+    /// Routes of `self`, with every route set in `other` taking precedence
+    pub fn merged(&self, other: &TaskRoutes) -> TaskRoutes {
+        TaskRoutes {
+            prose: other.prose.clone().or_else(|| self.prose.clone()),
+            summary: other.summary.clone().or_else(|| self.summary.clone()),
+            consistency: other.consistency.clone().or_else(|| self.consistency.clone()),
+        }
+    }
 }
 
 /// Genre of the novel
@@ -1,6 +1,5 @@
-//! Progress Tracking Service
+//! Progress Tracking Models
 
-#[allow(unused_imports)]
 use serde::{Deserialize, Serialize};
 
 /// Progress tracker for long-running operations
@@ -46,6 +45,19 @@ impl ProgressTracker {
         self.failed += 1;
     }
 
+    /// Set the counters outright, for callers that know which items finished
+    pub fn set_counts(&mut self, completed: u32, failed: u32) {
+        self.completed = completed;
+        self.failed = failed;
+        if self.completed >= self.total {
+            self.status = ProgressStatus::Completed;
+        }
+    }
+
+    pub fn fail(&mut self) {
+        self.status = ProgressStatus::Failed;
+    }
+
     pub fn progress_percentage(&self) -> f32 {
         if self.total == 0 {
             return 100.0;
@@ -0,0 +1,132 @@
+//! Token Usage Models
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Tokens consumed by a single LLM call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Tokens in the request
+    pub prompt_tokens: u32,
+
+    /// Tokens in the response
+    pub completion_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Self { prompt_tokens, completion_tokens }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+/// Pipeline stage an LLM call belongs to
+///
+/// Outline and plan are built without an LLM so far; their stages stay
+/// empty until they make calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageStage {
+    Outline,
+    Plan,
+    /// Chapter drafts, segments and the continuity pass
+    Generate,
+    /// Judging candidate drafts
+    Judge,
+    /// Revising chapters on request
+    Revise,
+    Check,
+}
+
+impl UsageStage {
+    pub const ALL: [UsageStage; 6] = [
+        UsageStage::Outline,
+        UsageStage::Plan,
+        UsageStage::Generate,
+        UsageStage::Judge,
+        UsageStage::Revise,
+        UsageStage::Check,
+    ];
+}
+
+impl std::fmt::Display for UsageStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageStage::Outline => write!(f, "outline"),
+            UsageStage::Plan => write!(f, "plan"),
+            UsageStage::Generate => write!(f, "generate"),
+            UsageStage::Judge => write!(f, "judge"),
+            UsageStage::Revise => write!(f, "revise"),
+            UsageStage::Check => write!(f, "check"),
+        }
+    }
+}
+
+/// One recorded LLM call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Stage that made the call
+    pub stage: UsageStage,
+
+    /// Provider name
+    pub provider: String,
+
+    /// Model name
+    pub model: String,
+
+    /// Tokens consumed
+    pub usage: TokenUsage,
+
+    /// Recorded timestamp
+    pub created_at: DateTime<Utc>,
+}
+
+/// Per-project log of LLM token usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageLedger {
+    /// Project ID
+    pub project_id: Uuid,
+
+    /// Recorded calls, oldest first
+    pub records: Vec<UsageRecord>,
+}
+
+impl UsageLedger {
+    pub fn new(project_id: Uuid) -> Self {
+        Self {
+            project_id,
+            records: Vec::new(),
+        }
+    }
+
+    /// Append a call to the ledger
+    pub fn record(&mut self, stage: UsageStage, provider: &str, model: &str, usage: TokenUsage) {
+        self.records.push(UsageRecord {
+            stage,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            usage,
+            created_at: Utc::now(),
+        });
+    }
+
+    /// Total usage across all stages
+    pub fn total(&self) -> TokenUsage {
+        let mut total = TokenUsage::default();
+        for record in &self.records {
+            total += record.usage;
+        }
+        total
+    }
+}
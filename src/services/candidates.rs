@@ -0,0 +1,189 @@
+//! Candidate drafts
+//!
+//! Key chapters, such as plot twists, can be drafted several times and the
+//! best draft kept. Drafts are ranked by automatic metrics: closeness to the
+//! planned length, repeated sentences, coverage of the plan's key events and
+//! the content filter. An LLM judge's score can be blended in on top.
+
+use std::collections::HashSet;
+
+use crate::models::{CandidateScore, ChapterCandidate, ChapterSummary};
+use crate::services::content_filter::ContentFilter;
+use crate::text::{is_sentence_break, MIN_REPEATED_SENTENCE_CHARS};
+
+/// Weight of the length fit in the automatic score
+const LENGTH_WEIGHT: f32 = 0.25;
+
+/// Weight of the absence of repetition in the automatic score
+const REPETITION_WEIGHT: f32 = 0.25;
+
+/// Weight of key-event coverage in the automatic score
+const COVERAGE_WEIGHT: f32 = 0.5;
+
+/// Automatic score kept by a draft the content filter rejects
+const FILTER_PENALTY: f32 = 0.5;
+
+/// Weight of the judge score when the judge ran; the rest is the automatic score
+const JUDGE_WEIGHT: f32 = 0.5;
+
+/// Score `content` as a draft of the chapter planned in `summary`
+///
+/// `judge` is the LLM judge's score between 0 and 1, if it ran.
+pub fn score_draft(summary: &ChapterSummary, content: &str, judge: Option<f32>) -> CandidateScore {
+    let mut score = CandidateScore {
+        length_fit: length_fit(content, summary.word_count_estimate),
+        repetition: repetition(content),
+        coverage: coverage(content, &beats(summary)),
+        content_passed: ContentFilter::new().check(content).passed,
+        judge,
+        total: 0.0,
+    };
+
+    let mut total = LENGTH_WEIGHT * score.length_fit
+        + REPETITION_WEIGHT * (1.0 - score.repetition)
+        + COVERAGE_WEIGHT * score.coverage;
+    if !score.content_passed {
+        total *= FILTER_PENALTY;
+    }
+    if let Some(judge) = score.judge {
+        total = (1.0 - JUDGE_WEIGHT) * total + JUDGE_WEIGHT * judge.clamp(0.0, 1.0);
+    }
+    score.total = total;
+    score
+}
+
+/// Sort `candidates` best first and number their ranks from 1
+///
+/// Ties keep the earlier draft first.
+pub fn rank(candidates: &mut [ChapterCandidate]) {
+    candidates.sort_by(|a, b| {
+        b.score
+            .total
+            .total_cmp(&a.score.total)
+            .then(a.candidate.cmp(&b.candidate))
+    });
+    for (index, candidate) in candidates.iter_mut().enumerate() {
+        candidate.rank = index as u32 + 1;
+    }
+}
+
+/// Plan items a draft should cover: the key events, then the twist
+fn beats(summary: &ChapterSummary) -> Vec<&str> {
+    let mut beats: Vec<&str> = summary.key_events.iter().map(String::as_str).filter(|e| !e.is_empty()).collect();
+    if summary.is_plot_twist_chapter {
+        if let Some(twist) = summary.plot_twist_description.as_deref().filter(|t| !t.is_empty()) {
+            beats.push(twist);
+        }
+    }
+    beats
+}
+
+/// 1 at the target length, falling linearly to 0 at twice or none of it
+fn length_fit(content: &str, target: u32) -> f32 {
+    let target = target.max(1) as f32;
+    let written = content.chars().count() as f32;
+    (1.0 - (written - target).abs() / target).max(0.0)
+}
+
+/// Share of characters in sentences that already appeared earlier
+fn repetition(content: &str) -> f32 {
+    let mut seen = HashSet::new();
+    let (mut total, mut repeated) = (0, 0);
+    for sentence in content.split(is_sentence_break).map(str::trim) {
+        let chars = sentence.chars().count();
+        total += chars;
+        if chars >= MIN_REPEATED_SENTENCE_CHARS && !seen.insert(sentence) {
+            repeated += chars;
+        }
+    }
+    match total {
+        0 => 0.0,
+        _ => repeated as f32 / total as f32,
+    }
+}
+
+/// Mean share of each beat's character pairs found in `content`
+///
+/// Chinese has no word boundaries, so a beat counts as covered in
+/// proportion to how many of its two-character pairs the text contains:
+/// "在山下遇到伏击" covers "山下遇袭" only in part.
+fn coverage(content: &str, beats: &[&str]) -> f32 {
+    if beats.is_empty() {
+        return 1.0;
+    }
+    let text = bigrams(content);
+    let covered: f32 = beats
+        .iter()
+        .map(|beat| {
+            let pairs = bigrams(beat);
+            match pairs.len() {
+                0 => 1.0,
+                n => pairs.iter().filter(|p| text.contains(*p)).count() as f32 / n as f32,
+            }
+        })
+        .sum();
+    covered / beats.len() as f32
+}
+
+/// Pairs of adjacent characters, skipping punctuation and spaces
+fn bigrams(text: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = text.chars().filter(|c| c.is_alphanumeric()).collect();
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{GeneratedChapter, GenerationParams};
+    use uuid::Uuid;
+
+    fn summary() -> ChapterSummary {
+        ChapterSummary {
+            number: 5,
+            title: "第5章 真相".to_string(),
+            summary: "林风发现师父的秘密".to_string(),
+            key_events: vec!["夜探藏经阁".to_string(), "找到密信".to_string()],
+            protagonist_development: String::new(),
+            word_count_estimate: 40,
+            is_plot_twist_chapter: true,
+            plot_twist_description: Some("师父竟是魔尊".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_draft_scores() {
+        let summary = summary();
+        let good = "林风夜探藏经阁，在书架深处找到密信。信上的字迹让他明白，师父竟是魔尊。";
+        let score = score_draft(&summary, good, None);
+        assert_eq!(score.coverage, 1.0);
+        assert_eq!(score.repetition, 0.0);
+        assert!(score.content_passed && score.length_fit > 0.8);
+
+        // Misses the twist and repeats itself
+        let weak = "林风夜探藏经阁，四下无人。林风夜探藏经阁，四下无人。";
+        let weak_score = score_draft(&summary, weak, None);
+        assert!(weak_score.coverage < 0.5);
+        assert!((weak_score.repetition - 0.5).abs() < 0.01);
+        assert!(weak_score.total < score.total);
+
+        // The content filter halves the automatic score; the judge is blended in
+        let flagged = score_draft(&summary, &format!("{}血腥", good), None);
+        assert!(!flagged.content_passed && flagged.total < score.total * 0.6);
+        let judged = score_draft(&summary, good, Some(0.0));
+        assert!((judged.total - score.total / 2.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_rank_orders_best_first() {
+        let candidate = |number: u32, total: f32| ChapterCandidate {
+            candidate: number,
+            rank: 0,
+            score: CandidateScore { total, ..Default::default() },
+            chapter: GeneratedChapter::new(Uuid::new_v4(), 5, String::new(), String::new(), GenerationParams::default()),
+        };
+        let mut candidates = vec![candidate(1, 0.4), candidate(2, 0.9), candidate(3, 0.4)];
+        rank(&mut candidates);
+        let order: Vec<(u32, u32)> = candidates.iter().map(|c| (c.candidate, c.rank)).collect();
+        assert_eq!(order, [(2, 1), (1, 2), (3, 3)]);
+    }
+}
@@ -1,33 +1,433 @@
 //! Context Management Service
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 
-/// Context manager for long novels
-pub struct ContextService;
+use crate::models::{CharacterArc, CharacterRole, ChapterPlan, ChapterSummary, GeneratedChapter, NovelOutline};
+use crate::services::llm::EmbeddingProvider;
+use crate::services::vector_store::{SimpleVectorStore, VectorStore};
+use crate::text::tail;
+
+/// Characters of the previous chapter carried into the next prompt
+pub const PREVIOUS_TAIL_CHARS: usize = 1500;
+
+/// Paragraphs are merged into passages of about this many characters for retrieval
+const PASSAGE_CHARS: usize = 300;
+
+/// Supporting characters listed when the plan names none of them
+const FALLBACK_CHARACTERS: usize = 3;
+
+/// Everything known about a chapter before it is written
+///
+/// Built from the chapter plan, the outline and the previous chapter; the
+/// sections are rendered separately so a
+/// [`PromptBudget`](crate::services::llm::PromptBudget) can cut them
+/// independently.
+#[derive(Debug, Clone)]
+pub struct ChapterBrief {
+    /// The plan entry for the chapter
+    pub summary: ChapterSummary,
+
+    /// Premise and theme of the novel
+    pub premise: Option<String>,
+
+    /// World description, rules and locations
+    pub world: Option<String>,
+
+    /// Characters relevant to the chapter, protagonist first
+    pub characters: Vec<CharacterArc>,
+
+    /// End of the previous chapter's text
+    pub previous_tail: Option<String>,
+
+    /// Plan entry of the previous chapter, for when its text is not available
+    pub previous_summary: Option<String>,
+
+    /// Passages of earlier chapters related to this one, best first; see
+    /// [`ContextService`]
+    pub related: Vec<String>,
+
+    /// Asks one of several drafts of the chapter to take its own approach;
+    /// see [`GenerationService::generate_candidates`](crate::services::GenerationService::generate_candidates)
+    pub draft_note: Option<String>,
+}
+
+impl ChapterBrief {
+    /// Brief for `chapter_number` of `plan`
+    ///
+    /// `previous` is used only if it is the chapter right before this one.
+    pub fn new(
+        outline: Option<&NovelOutline>,
+        plan: &ChapterPlan,
+        chapter_number: u32,
+        previous: Option<&GeneratedChapter>,
+    ) -> Result<Self> {
+        let summary = plan
+            .chapters
+            .iter()
+            .find(|c| c.number == chapter_number)
+            .cloned()
+            .with_context(|| format!("Chapter {} is not in the chapter plan ({} chapters)", chapter_number, plan.total_chapters))?;
+
+        let previous_tail = previous
+            .filter(|p| p.chapter_number + 1 == chapter_number)
+            .map(|p| tail(&p.content, PREVIOUS_TAIL_CHARS).to_string());
+        let previous_summary = chapter_number
+            .checked_sub(1)
+            .and_then(|n| plan.chapters.iter().find(|c| c.number == n))
+            .map(|c| format!("{}：{}", c.title, c.summary));
+
+        Ok(Self {
+            premise: outline.map(|o| format!("{}\n主题：{}", o.premise, o.theme)),
+            world: outline.map(render_world),
+            characters: outline.map(|o| relevant_characters(o, &summary)).unwrap_or_default(),
+            previous_tail,
+            previous_summary,
+            related: Vec::new(),
+            draft_note: None,
+            summary,
+        })
+    }
+
+    /// Chapter title from the plan
+    pub fn title(&self) -> &str {
+        &self.summary.title
+    }
+
+    /// The writing instruction: what must happen in this chapter
+    pub fn instruction(&self) -> String {
+        let summary = &self.summary;
+        let mut text = format!("请创作{}。\n本章概要：{}\n", summary.title, summary.summary);
+        let events: Vec<&String> = summary.key_events.iter().filter(|e| !e.is_empty()).collect();
+        if !events.is_empty() {
+            text.push_str("关键事件：\n");
+            for event in events {
+                text.push_str(&format!("- {}\n", event));
+            }
+        }
+        if !summary.protagonist_development.is_empty() {
+            text.push_str(&format!("主角成长：{}\n", summary.protagonist_development));
+        }
+        if summary.is_plot_twist_chapter {
+            if let Some(twist) = &summary.plot_twist_description {
+                text.push_str(&format!("本章转折：{}\n", twist));
+            }
+        }
+        text.push_str(&format!("目标字数：约{}字。\n", summary.word_count_estimate));
+        text.push_str("要求：只输出正文，不要输出章节标题");
+        text.push_str(self.continuity_requirement());
+        text.push_str(&self.draft_requirement());
+        text.push('。');
+        text
+    }
+
+    /// How the chapter should follow from the previous one, as a requirement
+    /// clause; empty for the first chapter
+    pub fn continuity_requirement(&self) -> &'static str {
+        if self.previous_tail.is_some() {
+            "；紧接上一章结尾继续，不要重复上一章的内容"
+        } else if self.previous_summary.is_some() {
+            "；承接上一章概要中的情节，不要重复上一章的内容"
+        } else {
+            ""
+        }
+    }
+
+    /// The draft note as a requirement clause; empty for a single draft
+    pub fn draft_requirement(&self) -> String {
+        self.draft_note.as_ref().map(|note| format!("；{}", note)).unwrap_or_default()
+    }
+
+    /// Characters rendered one per line
+    pub fn characters_text(&self) -> String {
+        self.characters.iter().map(render_character).collect::<Vec<_>>().join("\n")
+    }
+
+    /// What to look up earlier passages by: the plan entry, the characters
+    /// and those of `locations` the plan mentions
+    fn retrieval_query(&self, locations: &[String]) -> String {
+        let summary = &self.summary;
+        let mut query = format!("{}\n{}", summary.summary, summary.key_events.join("；"));
+        let names: Vec<&str> = self.characters.iter().map(|c| c.name.as_str()).collect();
+        if !names.is_empty() {
+            query.push_str(&format!("\n人物：{}", names.join("、")));
+        }
+        let places: Vec<&str> = locations
+            .iter()
+            .filter(|l| !l.is_empty() && query.contains(l.as_str()))
+            .map(String::as_str)
+            .collect();
+        if !places.is_empty() {
+            query.push_str(&format!("\n地点：{}", places.join("、")));
+        }
+        query
+    }
+}
+
+fn render_world(outline: &NovelOutline) -> String {
+    let world = &outline.world_settings;
+    let mut text = format!("{}：{}", world.name, world.description);
+    if !world.rules.is_empty() {
+        text.push_str("\n规则：");
+        text.push_str(&world.rules.join("；"));
+    }
+    for location in &world.locations {
+        text.push_str(&format!("\n地点 {}：{}", location.name, location.description));
+    }
+    text
+}
+
+fn render_character(character: &CharacterArc) -> String {
+    let role = match character.role {
+        CharacterRole::Protagonist => "主角",
+        CharacterRole::Supporting => "配角",
+        CharacterRole::Antagonist => "反派",
+    };
+    let mut text = format!("{}（{}）：{}", character.name, role, character.description);
+    if !character.personality_traits.is_empty() {
+        text.push_str(&format!("；性格：{}", character.personality_traits.join("、")));
+    }
+    if !character.arc_description.is_empty() {
+        text.push_str(&format!("；成长线：{}", character.arc_description));
+    }
+    text
+}
+
+/// Protagonist plus the supporting characters the chapter involves
+///
+/// A character is involved when the plan mentions their name or they have
+/// a key moment in the chapter. If none are, the first few are listed so
+/// the model still knows who is around.
+fn relevant_characters(outline: &NovelOutline, summary: &ChapterSummary) -> Vec<CharacterArc> {
+    let plan_text = format!(
+        "{} {} {} {} {}",
+        summary.title,
+        summary.summary,
+        summary.key_events.join(" "),
+        summary.protagonist_development,
+        summary.plot_twist_description.as_deref().unwrap_or_default()
+    );
+
+    let involved: Vec<&CharacterArc> = outline
+        .supporting_characters
+        .iter()
+        .filter(|c| {
+            (!c.name.is_empty() && plan_text.contains(&c.name))
+                || c.key_moments.iter().any(|m| m.chapter == summary.number)
+        })
+        .collect();
+    let supporting = if involved.is_empty() {
+        outline.supporting_characters.iter().take(FALLBACK_CHARACTERS).collect()
+    } else {
+        involved
+    };
+
+    std::iter::once(&outline.protagonist)
+        .filter(|p| !p.name.is_empty())
+        .chain(supporting)
+        .cloned()
+        .collect()
+}
+
+/// Retrieval of earlier passages for long novels
+///
+/// Chapters are split into passages and embedded as they are indexed; a
+/// chapter's brief then looks up the passages about its characters and
+/// places, which the previous chapter's tail alone does not reach.
+pub struct ContextService {
+    embedder: Box<dyn EmbeddingProvider>,
+    store: SimpleVectorStore,
+    /// World locations, added to every query
+    locations: Vec<String>,
+    fitted: bool,
+}
 
 impl ContextService {
-    pub fn new() -> Self {
-        Self
+    pub fn new(embedder: Box<dyn EmbeddingProvider>, outline: Option<&NovelOutline>) -> Self {
+        Self {
+            embedder,
+            store: SimpleVectorStore::new(),
+            locations: outline
+                .map(|o| o.world_settings.locations.iter().map(|l| l.name.clone()).collect())
+                .unwrap_or_default(),
+            fitted: false,
+        }
     }
 
-    /// Get relevant context for a chapter
-    pub async fn get_context(&self, project_id: &str, chapter_number: u32) -> Result<String> {
-        tracing::debug!("Getting context for chapter {} in project {}", chapter_number, project_id);
+    /// Add the passages of `chapters`
+    ///
+    /// The first call also fits the embedder on them; later chapters are
+    /// embedded with the same weights so all vectors stay comparable.
+    pub async fn index_chapters(&mut self, chapters: &[GeneratedChapter]) -> Result<()> {
+        let passages: Vec<(String, String)> = chapters
+            .iter()
+            .flat_map(|chapter| {
+                passages(&chapter.content)
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(index, text)| (format!("{}:{}", chapter.chapter_number, index), text))
+            })
+            .collect();
+        let texts: Vec<String> = passages.iter().map(|(_, text)| text.clone()).collect();
+        if !self.fitted {
+            self.embedder.fit(&texts);
+            self.fitted = true;
+        }
+
+        for ((id, text), vector) in passages.iter().zip(self.embedder.embed(&texts).await?) {
+            self.store.add(id, &vector, text)?;
+        }
+        Ok(())
+    }
 
-        // TODO: Implement semantic retrieval
-        // For now, return empty context
-        Ok(String::new())
+    /// Up to `limit` indexed passages related to the chapter of `brief`, best first
+    pub async fn get_context(&self, brief: &ChapterBrief, limit: usize) -> Result<Vec<String>> {
+        let query = self.embedder.embed_one(&brief.retrieval_query(&self.locations)).await?;
+        Ok(self
+            .store
+            .search(&query, limit)?
+            .into_iter()
+            .filter(|result| result.score > 0.0)
+            .map(|result| result.payload)
+            .collect())
     }
+}
 
-    /// Compress old context
-    pub async fn compress_context(&self, chapters: &[String]) -> Result<String> {
-        // TODO: Implement context compression using LLM
-        Ok(chapters.join("\n\n"))
+/// Paragraphs of `text` merged into passages of about [`PASSAGE_CHARS`]
+fn passages(text: &str) -> Vec<String> {
+    let mut passages = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.lines().map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() > PASSAGE_CHARS {
+            passages.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(paragraph);
     }
+    if !current.is_empty() {
+        passages.push(current);
+    }
+    passages
 }
 
-impl Default for ContextService {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CharacterMoment, GenerationParams};
+    use uuid::Uuid;
+
+    fn character(name: &str, moment: Option<u32>) -> CharacterArc {
+        CharacterArc {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            role: CharacterRole::Supporting,
+            description: format!("{}的描述", name),
+            personality_traits: vec!["冷静".to_string()],
+            arc_description: String::new(),
+            key_moments: moment
+                .map(|chapter| CharacterMoment {
+                    chapter,
+                    description: String::new(),
+                    development: String::new(),
+                })
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn fixture() -> (NovelOutline, ChapterPlan) {
+        let project_id = Uuid::new_v4();
+        let mut outline = NovelOutline::new(project_id, "少年修仙".to_string(), "成长".to_string(), 100_000);
+        outline.protagonist.name = "林风".to_string();
+        outline.world_settings.name = "修仙界".to_string();
+        outline.world_settings.rules = vec!["灵气修炼".to_string(), "天劫考验".to_string()];
+        outline.supporting_characters = vec![
+            character("苏婉儿", None),
+            character("萧炎", Some(2)),
+            character("魔尊", None),
+        ];
+
+        let mut plan = ChapterPlan::new(project_id, 10);
+        plan.chapters.push(ChapterSummary {
+            number: 2,
+            title: "第2章 青云试炼".to_string(),
+            summary: "林风与苏婉儿参加青云宗试炼".to_string(),
+            key_events: vec!["通过试炼".to_string()],
+            protagonist_development: "第一次独自面对强敌".to_string(),
+            word_count_estimate: 10_000,
+            is_plot_twist_chapter: false,
+            plot_twist_description: None,
+        });
+        (outline, plan)
+    }
+
+    #[test]
+    fn test_brief_from_plan_and_outline() {
+        let (outline, plan) = fixture();
+        let previous = GeneratedChapter::new(
+            plan.project_id,
+            1,
+            "第1章".to_string(),
+            format!("{}林风握紧了剑。", "前文".repeat(1000)),
+            GenerationParams::default(),
+        );
+
+        let brief = ChapterBrief::new(Some(&outline), &plan, 2, Some(&previous)).unwrap();
+        assert_eq!(brief.title(), "第2章 青云试炼");
+        let instruction = brief.instruction();
+        assert!(instruction.contains("青云宗试炼") && instruction.contains("- 通过试炼"));
+        assert!(instruction.contains("紧接上一章"));
+        assert!(brief.world.as_deref().unwrap().contains("灵气修炼；天劫考验"));
+
+        // Named in the plan or with a moment in the chapter; not the others
+        let names: Vec<&str> = brief.characters.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["林风", "苏婉儿", "萧炎"]);
+
+        let tail = brief.previous_tail.unwrap();
+        assert_eq!(tail.chars().count(), PREVIOUS_TAIL_CHARS);
+        assert!(tail.ends_with("林风握紧了剑。"));
+    }
+
+    #[test]
+    fn test_brief_without_outline_or_previous_chapter() {
+        let (_, plan) = fixture();
+        let unrelated = GeneratedChapter::new(plan.project_id, 7, String::new(), "无关".to_string(), GenerationParams::default());
+
+        let brief = ChapterBrief::new(None, &plan, 2, Some(&unrelated)).unwrap();
+        assert!(brief.world.is_none() && brief.characters.is_empty() && brief.previous_tail.is_none());
+        assert!(brief.previous_summary.is_none());
+        assert!(!brief.instruction().contains("上一章"));
+        assert!(ChapterBrief::new(None, &plan, 3, None).is_err());
+    }
+
+    /// Passages about the chapter's characters and places are retrieved first
+    #[tokio::test]
+    async fn test_retrieves_related_passages() {
+        use crate::models::{Location, LocationImportance};
+        use crate::services::llm::LocalEmbeddingProvider;
+
+        let (mut outline, plan) = fixture();
+        outline.world_settings.locations = vec![Location {
+            name: "青云宗".to_string(),
+            description: String::new(),
+            importance: LocationImportance::Major,
+        }];
+        let chapter = |number: u32, paragraphs: &[&str]| {
+            GeneratedChapter::new(plan.project_id, number, String::new(), paragraphs.join("\n"), GenerationParams::default())
+        };
+        let earlier = [
+            chapter(1, &["集市上人来人往，卖糖葫芦的老人在街角吆喝。", &"雨一直下。".repeat(70)]),
+            chapter(2, &["苏婉儿在青云宗山门前等林风，说试炼三日后开始。"]),
+        ];
+
+        let mut retrieval = ContextService::new(Box::new(LocalEmbeddingProvider::default()), Some(&outline));
+        retrieval.index_chapters(&earlier).await.unwrap();
+        let brief = ChapterBrief::new(Some(&outline), &plan, 2, None).unwrap();
+        let related = retrieval.get_context(&brief, 1).await.unwrap();
+        assert_eq!(related, ["苏婉儿在青云宗山门前等林风，说试炼三日后开始。"]);
+
+        // Long paragraphs are not merged with their neighbours
+        assert_eq!(passages(&earlier[0].content).len(), 2);
     }
 }
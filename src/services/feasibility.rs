@@ -1,7 +1,7 @@
 //! Feasibility Analysis Service
 
 use anyhow::Result;
-use crate::models::{CompetitionLevel, DataSource, FeasibilityReport, NovelGenre, CompetitiveWork};
+use crate::models::{CompetitionLevel, FeasibilityReport, NovelGenre, CompetitiveWork};
 use crate::services::scraping::ScrapingService;
 
 /// Feasibility analysis service
@@ -1,17 +1,176 @@
 //! Generation Service
 
+use std::sync::Arc;
+
 use anyhow::Result;
+use futures::StreamExt;
+use serde::Deserialize;
 use uuid::Uuid;
-use crate::models::{GeneratedChapter, GenerationParams};
+use crate::models::{
+    ChapterCandidate, ChapterPlan, GeneratedChapter, GenerationParams, NovelOutline, TokenUsage, UsageStage,
+};
+use crate::services::candidates;
+use crate::services::context::ChapterBrief;
+use crate::config::LlmConfig;
+use crate::services::llm::{
+    create_client_for_task, CancellationToken, ChatMessage, FittedPrompt, GenerationOptions, LlmClient, LlmError,
+    LlmTask, Overflow, PromptBudget,
+};
+use crate::services::segment::{self, SegmentOptions, SegmentPlan, SEGMENT_TAIL_CHARS};
+use crate::services::usage::UsageTracker;
+use crate::text::tail;
+
+/// System instruction for chapter writing
+const WRITER_PROMPT: &str = "你是小说作家，根据以下上下文背景创作小说内容。";
+
+/// Reply limit assumed for segment sizing when none is configured
+const FALLBACK_MAX_TOKENS: u32 = 2048;
+
+/// Continuation characters buffered before the seam is smoothed and the
+/// text passed on
+const SEAM_BUFFER_CHARS: usize = 300;
+
+/// A segment adding less than this means the model has nothing left to write
+const MIN_SEGMENT_CHARS: u32 = 50;
+
+/// System instruction for revising a chapter
+const REVISER_PROMPT: &str = "你是小说编辑，按照修改要求改写章节，保持与上下文背景一致。";
+
+/// System instruction for the continuity pass
+const EDITOR_PROMPT: &str = "你是小说编辑，负责让相邻章节自然衔接。";
+
+/// Instruction recorded on chapter revisions made by the continuity pass
+pub const CONTINUITY_INSTRUCTION: &str = "continuity pass";
+
+/// Characters at the start of a chapter the continuity pass may rewrite
+const OPENING_CHARS: u32 = 600;
+
+/// Characters of the previous chapter shown to the continuity pass
+const CONTINUITY_TAIL_CHARS: usize = 1000;
+
+/// System instruction for judging candidate drafts
+const JUDGE_PROMPT: &str = "你是资深网文编辑，负责比较同一章节的多个候选稿，选出最好的一稿。";
+
+/// Characters from each end of a candidate shown to the judge
+const JUDGE_EXCERPT_CHARS: usize = 800;
+
+/// Reply format asked of the judge
+const JUDGE_SCHEMA: &str = r#"{"scores": [{"candidate": 1, "score": 8, "reason": "转折铺垫充分，节奏紧凑"}]}"#;
+
+#[derive(Deserialize)]
+struct JudgeReply {
+    scores: Vec<JudgeScore>,
+}
+
+#[derive(Deserialize)]
+struct JudgeScore {
+    candidate: u32,
+    score: f32,
+}
+
+/// Outcome of [`GenerationService::generate_parallel`]
+#[derive(Debug)]
+pub struct ParallelDrafts {
+    /// Drafts written, in chapter order, with the continuity pass applied
+    pub chapters: Vec<GeneratedChapter>,
+
+    /// Chapters whose draft failed, with the error
+    pub failed: Vec<(u32, anyhow::Error)>,
+
+    /// Chapters after the first of the book whose opening was not
+    /// smoothed: the chapter before is missing, its rewrite was discarded or
+    /// the pass was cancelled
+    pub unsmoothed: Vec<u32>,
+}
 
 /// Chapter generation service
 pub struct GenerationService {
-    llm_client: crate::services::llm::LlmClient,
+    llm_client: LlmClient,
+    summary_client: Option<LlmClient>,
+    consistency_client: Option<LlmClient>,
+    usage: Option<Arc<UsageTracker>>,
+    cancel: Option<CancellationToken>,
+    segments: Option<SegmentOptions>,
 }
 
 impl GenerationService {
-    pub fn new(llm_client: crate::services::llm::LlmClient) -> Self {
-        Self { llm_client }
+    pub fn new(llm_client: LlmClient) -> Self {
+        Self { llm_client, summary_client: None, consistency_client: None, usage: None, cancel: None, segments: None }
+    }
+
+    /// Service with a client per task routed in `config`
+    ///
+    /// Tasks without a route of their own use the prose client.
+    pub fn from_config(config: &LlmConfig) -> Result<Self> {
+        let routed = |task: LlmTask| -> Result<Option<LlmClient>> {
+            match task.route(&config.routes) {
+                Some(_) => Ok(Some(create_client_for_task(config, task)?)),
+                None => Ok(None),
+            }
+        };
+        let mut service = Self::new(create_client_for_task(config, LlmTask::Prose)?);
+        service.summary_client = routed(LlmTask::Summary)?;
+        service.consistency_client = routed(LlmTask::Consistency)?;
+        Ok(service)
+    }
+
+    /// Condense story material that does not fit the window with `client`
+    /// instead of the prose model
+    pub fn with_summary_client(mut self, client: LlmClient) -> Self {
+        self.summary_client = Some(self.cancellable(client));
+        self
+    }
+
+    /// Judge candidate drafts and smooth chapter openings with `client`
+    /// instead of the prose model
+    pub fn with_consistency_client(mut self, client: LlmClient) -> Self {
+        self.consistency_client = Some(self.cancellable(client));
+        self
+    }
+
+    /// Write planned chapters longer than one reply as a series of segments
+    ///
+    /// Without this every chapter is a single call, however long its target.
+    pub fn with_segments(mut self, options: SegmentOptions) -> Self {
+        self.segments = Some(options);
+        self
+    }
+
+    /// Record token usage of every call in a project ledger
+    pub fn with_usage_tracker(mut self, tracker: Arc<UsageTracker>) -> Self {
+        self.usage = Some(tracker);
+        self
+    }
+
+    /// Stop generating once `token` is cancelled
+    ///
+    /// The request in flight fails with [`LlmError::Cancelled`] and its
+    /// partial text is discarded; chapters already returned are unaffected.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.llm_client = self.llm_client.with_cancellation(token.clone());
+        self.summary_client = self.summary_client.map(|c| c.with_cancellation(token.clone()));
+        self.consistency_client = self.consistency_client.map(|c| c.with_cancellation(token.clone()));
+        self.cancel = Some(token);
+        self
+    }
+
+    fn cancellable(&self, client: LlmClient) -> LlmClient {
+        match &self.cancel {
+            Some(token) => client.with_cancellation(token.clone()),
+            None => client,
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    fn summary_client(&self) -> &LlmClient {
+        self.summary_client.as_ref().unwrap_or(&self.llm_client)
+    }
+
+    fn consistency_client(&self) -> &LlmClient {
+        self.consistency_client.as_ref().unwrap_or(&self.llm_client)
     }
 
     /// Generate a chapter
@@ -21,44 +180,689 @@ impl GenerationService {
         chapter_number: u32,
         context: &str,
         prompt: &str,
+        options: &GenerationOptions,
     ) -> Result<GeneratedChapter> {
         tracing::info!("Generating chapter {} for project {}", chapter_number, project_id);
 
-        let params = GenerationParams {
-            model: "qwen2.5".to_string(),
-            temperature: 0.8,
-            max_tokens: 4096,
-        };
+        let messages = self.messages(context, prompt, options)?;
+        let title = format!("第{}章", chapter_number);
+        self.write(project_id, chapter_number, title, &messages, options).await
+    }
 
-        // Generate content using LLM
-        let content = self.llm_client.generate(context, prompt).await?;
+    /// Generate a chapter, passing each text chunk to `on_chunk` as it arrives
+    pub async fn generate_chapter_streaming<F>(
+        &self,
+        project_id: Uuid,
+        chapter_number: u32,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+        on_chunk: F,
+    ) -> Result<GeneratedChapter>
+    where
+        F: FnMut(&str),
+    {
+        tracing::info!("Streaming chapter {} for project {}", chapter_number, project_id);
 
-        let chapter = GeneratedChapter::new(
-            project_id,
-            chapter_number,
-            format!("第{}章", chapter_number),
-            content,
-            params,
-        );
+        let messages = self.messages(context, prompt, options)?;
+        let title = format!("第{}章", chapter_number);
+        self.write_streaming(project_id, chapter_number, title, &messages, options, on_chunk).await
+    }
+
+    /// Generate the chapter described by `brief`, titled as in the plan
+    pub async fn generate_planned_chapter(
+        &self,
+        project_id: Uuid,
+        brief: &ChapterBrief,
+        options: &GenerationOptions,
+    ) -> Result<GeneratedChapter> {
+        let number = brief.summary.number;
+        tracing::info!("Generating planned chapter {} for project {}", number, project_id);
 
-        Ok(chapter)
+        if let Some(plan) = self.segment_plan(brief, options) {
+            return self.write_segmented(project_id, brief, &plan, options, None::<fn(&str)>).await;
+        }
+        let messages = self.brief_messages(brief, &brief.instruction(), None, options).await?;
+        self.write(project_id, number, brief.title().to_string(), &messages, options).await
     }
 
-    /// Generate multiple chapters in batch
+    /// Generate the chapter described by `brief`, passing each text chunk
+    /// to `on_chunk` as it arrives
+    pub async fn generate_planned_chapter_streaming<F>(
+        &self,
+        project_id: Uuid,
+        brief: &ChapterBrief,
+        options: &GenerationOptions,
+        on_chunk: F,
+    ) -> Result<GeneratedChapter>
+    where
+        F: FnMut(&str),
+    {
+        let number = brief.summary.number;
+        tracing::info!("Streaming planned chapter {} for project {}", number, project_id);
+
+        if let Some(plan) = self.segment_plan(brief, options) {
+            return self.write_segmented(project_id, brief, &plan, options, Some(on_chunk)).await;
+        }
+        let messages = self.brief_messages(brief, &brief.instruction(), None, options).await?;
+        self.write_streaming(project_id, number, brief.title().to_string(), &messages, options, on_chunk).await
+    }
+
+    /// Generate multiple chapters of `plan` in order
+    ///
+    /// Each chapter continues from the one before it; `previous` is the
+    /// chapter preceding the first one, if already written. When cancelled,
+    /// stops and returns the chapters finished so far.
     pub async fn generate_batch(
         &self,
         project_id: Uuid,
+        outline: Option<&NovelOutline>,
+        plan: &ChapterPlan,
         chapter_numbers: &[u32],
-        context: &str,
+        mut previous: Option<GeneratedChapter>,
+        options: &GenerationOptions,
     ) -> Result<Vec<GeneratedChapter>> {
         let mut chapters = Vec::new();
 
         for &num in chapter_numbers {
-            let prompt = format!("Generate chapter {}", num);
-            let chapter = self.generate_chapter(project_id, num, context, &prompt).await?;
-            chapters.push(chapter);
+            if self.is_cancelled() {
+                break;
+            }
+            let brief = ChapterBrief::new(outline, plan, num, previous.as_ref())?;
+            match self.generate_planned_chapter(project_id, &brief, options).await {
+                Ok(chapter) => {
+                    previous = Some(chapter.clone());
+                    chapters.push(chapter);
+                }
+                Err(e) if LlmError::is_cancelled(&e) => break,
+                Err(e) => return Err(e),
+            }
         }
 
+        if self.is_cancelled() {
+            tracing::info!("Batch cancelled after {} of {} chapters", chapters.len(), chapter_numbers.len());
+        }
         Ok(chapters)
     }
+
+    /// Draft chapters of `plan` concurrently, at most `concurrency` at a time
+    ///
+    /// Each chapter is written from the plan, including the previous
+    /// chapter's plan entry, rather than the previous chapter's text, so
+    /// drafts do not wait on each other; only the first chapter continues
+    /// from `previous`. Drafts are passed to `on_draft` in chapter order as
+    /// soon as all earlier ones are done; a failed draft is reported in the
+    /// result and the others carry on. A continuity pass then rewrites
+    /// chapter openings with [`GenerationService::smooth_transitions`]; the
+    /// returned chapters include those changes.
+    ///
+    /// When cancelled, stops and returns the drafts passed on so far; a
+    /// continuity pass cut short keeps the openings it already rewrote.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_parallel<F>(
+        &self,
+        project_id: Uuid,
+        outline: Option<&NovelOutline>,
+        plan: &ChapterPlan,
+        chapter_numbers: &[u32],
+        previous: Option<&GeneratedChapter>,
+        concurrency: usize,
+        options: &GenerationOptions,
+        mut on_draft: F,
+    ) -> Result<ParallelDrafts>
+    where
+        F: FnMut(&GeneratedChapter) -> Result<()>,
+    {
+        let briefs = chapter_numbers
+            .iter()
+            .map(|&num| ChapterBrief::new(outline, plan, num, previous))
+            .collect::<Result<Vec<_>>>()?;
+        tracing::info!("Drafting {} chapters, {} at a time", briefs.len(), concurrency.max(1));
+
+        let mut chapters = Vec::new();
+        let mut failed = Vec::new();
+        {
+            let mut drafts = futures::stream::iter(&briefs)
+                .map(|brief| async move { (brief.summary.number, self.generate_planned_chapter(project_id, brief, options).await) })
+                .buffered(concurrency.max(1));
+            while let Some((number, result)) = drafts.next().await {
+                match result {
+                    Ok(chapter) => {
+                        on_draft(&chapter)?;
+                        chapters.push(chapter);
+                    }
+                    Err(e) if LlmError::is_cancelled(&e) => break,
+                    Err(e) => {
+                        tracing::warn!("Draft of chapter {} failed: {:#}", number, e);
+                        failed.push((number, e));
+                    }
+                }
+            }
+        }
+
+        // Every chapter after the first of the book has a seam to smooth
+        let seams: Vec<u32> = chapters.iter().map(|c| c.chapter_number).filter(|&n| n > 1).collect();
+        let smoothed = match self.is_cancelled() {
+            true => Vec::new(),
+            false => self.smooth_transitions(previous, &mut chapters, concurrency, options).await?,
+        };
+        let unsmoothed = seams.into_iter().filter(|n| !smoothed.contains(n)).collect();
+        if self.is_cancelled() {
+            tracing::info!("Parallel generation cancelled after {} of {} chapters", chapters.len(), chapter_numbers.len());
+        }
+        Ok(ParallelDrafts { chapters, failed, unsmoothed })
+    }
+
+    /// Draft the chapter of `brief` `count` times, at most `concurrency` at
+    /// a time, and rank the drafts best first
+    ///
+    /// Each draft is requested with its own seed, counting up from the
+    /// configured one or from 0, so a recorded run replays. Not every
+    /// provider takes a seed, so each draft's prompt also carries its own
+    /// note, which keeps drafts apart and cached separately. Each draft's
+    /// parameters record its seed. Drafts are scored with
+    /// [`candidates::score_draft`]; with `judge`, an LLM judge also compares
+    /// them, and if it fails the metrics alone decide.
+    pub async fn generate_candidates(
+        &self,
+        project_id: Uuid,
+        brief: &ChapterBrief,
+        count: u32,
+        concurrency: usize,
+        judge: bool,
+        options: &GenerationOptions,
+    ) -> Result<Vec<ChapterCandidate>> {
+        let count = count.max(1);
+        tracing::info!("Drafting {} candidates of chapter {}", count, brief.summary.number);
+
+        let seed = self.llm_client.resolve_options(options).seed.unwrap_or(0);
+        let drafts = futures::stream::iter(0..count)
+            .map(|index| {
+                let options = options.clone().seed(seed.wrapping_add(index as u64));
+                let mut brief = brief.clone();
+                if count > 1 {
+                    brief.draft_note = Some(format!("这是本章的第{}稿，情节安排和细节描写要有自己的处理", index + 1));
+                }
+                async move { self.generate_planned_chapter(project_id, &brief, &options).await }
+            })
+            .buffered(concurrency.max(1))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        let scores = match judge {
+            true if drafts.len() > 1 => match self.judge_drafts(brief, &drafts, options).await {
+                Ok(scores) => scores,
+                Err(e) if LlmError::is_cancelled(&e) => return Err(e),
+                Err(e) => {
+                    tracing::warn!("Judge failed for chapter {}; ranking by metrics: {:#}", brief.summary.number, e);
+                    vec![None; drafts.len()]
+                }
+            },
+            _ => vec![None; drafts.len()],
+        };
+
+        let mut ranked: Vec<ChapterCandidate> = drafts
+            .into_iter()
+            .zip(scores)
+            .enumerate()
+            .map(|(index, (chapter, judge))| ChapterCandidate {
+                candidate: index as u32 + 1,
+                rank: 0,
+                score: candidates::score_draft(&brief.summary, &chapter.content, judge),
+                chapter,
+            })
+            .collect();
+        candidates::rank(&mut ranked);
+        Ok(ranked)
+    }
+
+    /// Have the model score each of `drafts` from 0 to 10, returned as 0 to 1
+    ///
+    /// The judge sees the plan and the start and end of every draft. Drafts
+    /// it leaves out get `None`.
+    async fn judge_drafts(
+        &self,
+        brief: &ChapterBrief,
+        drafts: &[GeneratedChapter],
+        options: &GenerationOptions,
+    ) -> Result<Vec<Option<f32>>> {
+        let summary = &brief.summary;
+        let mut prompt = format!("章节：{}\n本章概要：{}\n", summary.title, summary.summary);
+        if !summary.key_events.is_empty() {
+            prompt.push_str(&format!("关键事件：{}\n", summary.key_events.join("；")));
+        }
+        if let Some(twist) = summary.plot_twist_description.as_deref().filter(|_| summary.is_plot_twist_chapter) {
+            prompt.push_str(&format!("本章转折：{}\n", twist));
+        }
+        for (index, draft) in drafts.iter().enumerate() {
+            let mut opening = draft.content.clone();
+            segment::trim_to_length(&mut opening, JUDGE_EXCERPT_CHARS as u32);
+            prompt.push_str(&format!(
+                "\n候选稿{}（{}字）开头：\n{}\n……\n结尾：\n{}\n",
+                index + 1,
+                draft.word_count,
+                opening,
+                tail(&draft.content, JUDGE_EXCERPT_CHARS)
+            ));
+        }
+        prompt.push_str("\n请按是否写出关键事件与转折、转折是否有铺垫、节奏与文笔，为每个候选稿打 0-10 分。");
+
+        let messages = vec![ChatMessage::system(JUDGE_PROMPT), ChatMessage::user(prompt)];
+        let reply = self.consistency_client().chat_json::<JudgeReply>(&messages, JUDGE_SCHEMA, options).await?;
+        let completion = &reply.completion;
+        self.record_usage(UsageStage::Judge, &completion.provider, &completion.model, completion.usage)?;
+
+        let mut scores = vec![None; drafts.len()];
+        for score in reply.value.scores {
+            if let Some(slot) = score.candidate.checked_sub(1).and_then(|i| scores.get_mut(i as usize)) {
+                *slot = Some((score.score / 10.0).clamp(0.0, 1.0));
+            }
+        }
+        Ok(scores)
+    }
+
+    /// Continuity pass: rewrite the opening of each chapter to follow on
+    /// from the end of the chapter before it, returning the numbers of the
+    /// chapters rewritten
+    ///
+    /// `previous` precedes `chapters[0]`; chapters whose predecessor is
+    /// missing are left alone. Pairs are independent and run `concurrency`
+    /// at a time. A rewrite that fails, or comes back empty or far from the
+    /// original length, is discarded. When cancelled, stops and returns the
+    /// openings rewritten so far.
+    pub async fn smooth_transitions(
+        &self,
+        previous: Option<&GeneratedChapter>,
+        chapters: &mut [GeneratedChapter],
+        concurrency: usize,
+        options: &GenerationOptions,
+    ) -> Result<Vec<u32>> {
+        let mut pairs = Vec::new();
+        for index in 0..chapters.len() {
+            let before = match index {
+                0 => previous,
+                _ => Some(&chapters[index - 1]),
+            };
+            let chapter = &chapters[index];
+            if let Some(before) = before.filter(|b| b.chapter_number + 1 == chapter.chapter_number) {
+                let mut opening = chapter.content.clone();
+                segment::trim_to_length(&mut opening, OPENING_CHARS);
+                let messages = vec![
+                    ChatMessage::system(EDITOR_PROMPT),
+                    ChatMessage::user(format!(
+                        "上一章结尾：\n{}\n\n{}开头：\n{}\n\n请改写本章开头，使它紧接上一章结尾：不重复上一章已经写过的内容，时间、地点和人物状态前后一致。保留原有情节，长度与原文相近，只输出改写后的开头。",
+                        tail(&before.content, CONTINUITY_TAIL_CHARS),
+                        chapter.title,
+                        opening
+                    )),
+                ];
+                pairs.push((index, opening, messages));
+            }
+        }
+
+        let mut rewrites = futures::stream::iter(pairs)
+            .map(|(index, opening, messages)| async move {
+                let completion = self.consistency_client().chat_with(&messages, options).await;
+                (index, opening, completion)
+            })
+            .buffered(concurrency.max(1));
+        let mut smoothed = Vec::new();
+        while let Some((index, opening, completion)) = rewrites.next().await {
+            let chapter = &mut chapters[index];
+            let completion = match completion {
+                Ok(completion) => completion,
+                Err(e) if LlmError::is_cancelled(&e) => break,
+                Err(e) => {
+                    tracing::warn!("Continuity pass failed for chapter {}: {:#}", chapter.chapter_number, e);
+                    continue;
+                }
+            };
+            self.record_usage(UsageStage::Generate, &completion.provider, &completion.model, completion.usage)?;
+
+            let rewrite = completion.text.trim();
+            let (original, rewritten) = (opening.chars().count(), rewrite.chars().count());
+            if rewritten < original / 2 || rewritten > original * 2 {
+                tracing::warn!(
+                    "Kept the opening of chapter {}: rewrite is {} characters, original {}",
+                    chapter.chapter_number, rewritten, original
+                );
+                continue;
+            }
+            let content = format!("{}{}", rewrite, &chapter.content[opening.len()..]);
+            chapter.set_content(content);
+            smoothed.push(chapter.chapter_number);
+        }
+        Ok(smoothed)
+    }
+
+    /// Revise `chapter` following an editor's `instruction`, passing each
+    /// text chunk to `on_chunk` as it arrives
+    ///
+    /// The model gets the full text, the instruction and the story context
+    /// of `brief`, if any, and returns the whole revised chapter, so the
+    /// reply limit is raised to fit it, up to the model's most. A chapter
+    /// longer than that fails before anything is sent. The result keeps the
+    /// id, number and title of `chapter`; its parameters are those of the
+    /// revision.
+    pub async fn revise_chapter<F>(
+        &self,
+        chapter: &GeneratedChapter,
+        instruction: &str,
+        brief: Option<&ChapterBrief>,
+        options: &GenerationOptions,
+        mut on_chunk: F,
+    ) -> Result<GeneratedChapter>
+    where
+        F: FnMut(&str),
+    {
+        tracing::info!("Revising chapter {} of project {}: {}", chapter.chapter_number, chapter.project_id, instruction);
+
+        // Leave room for the revision to grow, but not past what the model can write
+        let tokens = self.llm_client.token_estimator().count(&chapter.content);
+        let limit = self.llm_client.max_output_tokens();
+        anyhow::ensure!(
+            tokens < limit,
+            "Chapter {} is about {} tokens, more than {} can write in one reply ({} tokens); \
+             split the chapter or set llm.max_output_tokens if the model allows more",
+            chapter.chapter_number, tokens, self.llm_client.model(), limit
+        );
+        let mut options = options.clone();
+        let needed = (tokens * 3 / 2).min(limit);
+        if self.llm_client.resolve_options(&options).max_tokens.is_some_and(|max| max < needed) {
+            options.max_tokens = Some(needed);
+        }
+
+        let prompt = format!(
+            "修改要求：{}\n\n{}原文：\n{}\n\n请按修改要求改写本章。没有要求修改的部分尽量保持原样。只输出修改后的完整正文，不要输出标题或说明。",
+            instruction, chapter.title, chapter.content
+        );
+        let messages = self.story_messages(REVISER_PROMPT, brief, &prompt, None, &options).await?;
+        let mut params = self.params_for(&options);
+
+        let mut stream = self.llm_client.chat_stream_with(&messages, &options).await?;
+        let mut content = String::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            on_chunk(&chunk);
+            content.push_str(&chunk);
+        }
+        self.record_usage(UsageStage::Revise, stream.provider(), stream.model(), stream.usage())?;
+        params.model = stream.model().to_string();
+
+        let content = content.trim();
+        anyhow::ensure!(!content.is_empty(), "The revision of chapter {} came back empty", chapter.chapter_number);
+
+        let mut revised = chapter.clone();
+        revised.set_content(content.to_string());
+        revised.generation_params = params;
+        Ok(revised)
+    }
+
+    async fn write(
+        &self,
+        project_id: Uuid,
+        chapter_number: u32,
+        title: String,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<GeneratedChapter> {
+        let mut params = self.params_for(options);
+
+        let completion = self.llm_client.chat_with(messages, options).await?;
+        self.record_usage(UsageStage::Generate, &completion.provider, &completion.model, completion.usage)?;
+        params.model = completion.model;
+
+        Ok(GeneratedChapter::new(project_id, chapter_number, title, completion.text, params))
+    }
+
+    async fn write_streaming<F>(
+        &self,
+        project_id: Uuid,
+        chapter_number: u32,
+        title: String,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        mut on_chunk: F,
+    ) -> Result<GeneratedChapter>
+    where
+        F: FnMut(&str),
+    {
+        let mut params = self.params_for(options);
+
+        let mut stream = self.llm_client.chat_stream_with(messages, options).await?;
+        let mut content = String::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            on_chunk(&chunk);
+            content.push_str(&chunk);
+        }
+        self.record_usage(UsageStage::Generate, stream.provider(), stream.model(), stream.usage())?;
+        params.model = stream.model().to_string();
+
+        Ok(GeneratedChapter::new(project_id, chapter_number, title, content, params))
+    }
+
+    /// Segment plan for `brief`, if segmenting is on and one reply is too short
+    fn segment_plan(&self, brief: &ChapterBrief, options: &GenerationOptions) -> Option<SegmentPlan> {
+        let segments = self.segments.as_ref()?;
+        let segment_chars = segments.segment_chars.unwrap_or_else(|| {
+            let max_tokens = self.llm_client.resolve_options(options).max_tokens.unwrap_or(FALLBACK_MAX_TOKENS);
+            // Models tend to stop short of the limit
+            self.llm_client.token_estimator().chars_for(max_tokens) * 3 / 4
+        });
+        let plan = SegmentPlan::new(brief, segment_chars, segments);
+        (plan.segments() > 1).then_some(plan)
+    }
+
+    /// Write the chapter of `brief` segment by segment until it is within
+    /// the plan's length range
+    async fn write_segmented<F>(
+        &self,
+        project_id: Uuid,
+        brief: &ChapterBrief,
+        plan: &SegmentPlan,
+        options: &GenerationOptions,
+        mut on_chunk: Option<F>,
+    ) -> Result<GeneratedChapter>
+    where
+        F: FnMut(&str),
+    {
+        let number = brief.summary.number;
+        let mut params = self.params_for(options);
+        let mut content = String::new();
+
+        for index in 0..plan.limit() {
+            let written = content.chars().count() as u32;
+            if plan.is_done(written) {
+                break;
+            }
+            tracing::debug!("Chapter {}: segment {} at {} of {} characters", number, index + 1, written, plan.target());
+
+            let instruction = plan.instruction(brief, index, written);
+            let so_far = (index > 0).then(|| tail(&content, SEGMENT_TAIL_CHARS));
+            let messages = self.brief_messages(brief, &instruction, so_far, options).await?;
+            params.model = self.segment(&messages, options, brief.title(), &mut content, on_chunk.as_mut()).await?;
+
+            if (content.chars().count() as u32) < written + MIN_SEGMENT_CHARS {
+                tracing::warn!("Chapter {}: segment {} added almost nothing; stopping", number, index + 1);
+                break;
+            }
+        }
+
+        let written = content.chars().count() as u32;
+        if written < plan.min() {
+            tracing::warn!("Chapter {} is {} characters, short of {}", number, written, plan.min());
+        }
+        segment::trim_to_length(&mut content, plan.max());
+
+        Ok(GeneratedChapter::new(project_id, number, brief.title().to_string(), content, params))
+    }
+
+    /// Write one segment onto `content`, returning the model that wrote it
+    ///
+    /// When streaming, the start of the segment is held back until its seam
+    /// has been smoothed.
+    async fn segment<F>(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        title: &str,
+        content: &mut String,
+        on_chunk: Option<&mut F>,
+    ) -> Result<String>
+    where
+        F: FnMut(&str),
+    {
+        let Some(on_chunk) = on_chunk else {
+            let completion = self.llm_client.chat_with(messages, options).await?;
+            self.record_usage(UsageStage::Generate, &completion.provider, &completion.model, completion.usage)?;
+            segment::append_segment(content, title, &completion.text);
+            return Ok(completion.model);
+        };
+
+        let mut stream = self.llm_client.chat_stream_with(messages, options).await?;
+        let mut pending = Some(String::new());
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            match pending.as_mut() {
+                Some(buffer) => {
+                    buffer.push_str(&chunk);
+                    if buffer.chars().count() >= SEAM_BUFFER_CHARS {
+                        on_chunk(&segment::append_segment(content, title, buffer));
+                        pending = None;
+                    }
+                }
+                None => {
+                    on_chunk(&chunk);
+                    content.push_str(&chunk);
+                }
+            }
+        }
+        if let Some(buffer) = pending {
+            on_chunk(&segment::append_segment(content, title, &buffer));
+        }
+        self.record_usage(UsageStage::Generate, stream.provider(), stream.model(), stream.usage())?;
+        Ok(stream.model().to_string())
+    }
+
+    fn record_usage(&self, stage: UsageStage, provider: &str, model: &str, usage: TokenUsage) -> Result<()> {
+        match &self.usage {
+            Some(tracker) => tracker.record(stage, provider, model, usage),
+            None => Ok(()),
+        }
+    }
+
+    /// Chapter request: writer instruction plus context, then the prompt
+    ///
+    /// The context is truncated to what fits the model's window next to the
+    /// reply.
+    fn messages(&self, context: &str, prompt: &str, options: &GenerationOptions) -> Result<Vec<ChatMessage>> {
+        let fitted = self.fit(
+            PromptBudget::for_client(&self.llm_client, options)
+                .section("instructions", WRITER_PROMPT, 100, Overflow::Keep)
+                .section("prompt", prompt, 90, Overflow::Keep)
+                .section("context", context, 50, Overflow::KeepStart),
+        )?;
+
+        Ok(vec![
+            ChatMessage::system(format!(
+                "{}\n\n上下文背景:\n{}",
+                WRITER_PROMPT,
+                fitted.get("context").unwrap_or_default()
+            )),
+            ChatMessage::user(prompt),
+        ])
+    }
+
+    /// Planned chapter request: story material in the system message,
+    /// `instruction` as the prompt
+    ///
+    /// `so_far` is the end of the chapter written so far, for continuation
+    /// segments; it replaces the previous chapter's tail, which in turn is
+    /// replaced by the previous chapter's plan entry when the text is not
+    /// available. When the window is
+    /// tight the world description goes first, then the related earlier
+    /// passages, condensed by the summary model, the premise, the
+    /// characters and finally the start of the preceding text.
+    async fn brief_messages(
+        &self,
+        brief: &ChapterBrief,
+        instruction: &str,
+        so_far: Option<&str>,
+        options: &GenerationOptions,
+    ) -> Result<Vec<ChatMessage>> {
+        self.story_messages(WRITER_PROMPT, Some(brief), instruction, so_far, options).await
+    }
+
+    /// `role` and the story material of `brief` in the system message,
+    /// `prompt` as the user message; see [`GenerationService::brief_messages`]
+    async fn story_messages(
+        &self,
+        role: &str,
+        brief: Option<&ChapterBrief>,
+        prompt: &str,
+        so_far: Option<&str>,
+        options: &GenerationOptions,
+    ) -> Result<Vec<ChatMessage>> {
+        let (previous, previous_heading) = match (so_far, brief) {
+            (Some(text), _) => (text.to_string(), "本章已写内容结尾"),
+            (None, Some(ChapterBrief { previous_tail: Some(tail), .. })) => (tail.clone(), "上一章结尾"),
+            (None, Some(ChapterBrief { previous_summary: Some(summary), .. })) => (summary.clone(), "上一章概要"),
+            _ => (String::new(), "上一章结尾"),
+        };
+        let budget = PromptBudget::for_client(&self.llm_client, options)
+            .section("instructions", role, 100, Overflow::Keep)
+            .section("prompt", prompt, 90, Overflow::Keep)
+            .section("previous", previous, 80, Overflow::KeepEnd)
+            .section("characters", brief.map(ChapterBrief::characters_text).unwrap_or_default(), 75, Overflow::KeepStart)
+            .section("premise", brief.and_then(|b| b.premise.clone()).unwrap_or_default(), 70, Overflow::KeepStart)
+            .section("related", brief.map(|b| b.related.join("\n\n")).unwrap_or_default(), 65, Overflow::Summarize)
+            .section("world", brief.and_then(|b| b.world.clone()).unwrap_or_default(), 60, Overflow::KeepStart);
+        let fitted = budget.fit_with(self.summary_client()).await?;
+        self.warn_if_cut(&fitted);
+
+        let mut system = role.to_string();
+        let sections = [
+            ("premise", "故事梗概"),
+            ("world", "世界观"),
+            ("characters", "人物"),
+            ("related", "前文相关片段"),
+            ("previous", previous_heading),
+        ];
+        for (name, heading) in sections {
+            let text = fitted.get(name).unwrap_or_default();
+            if !text.is_empty() {
+                system.push_str(&format!("\n\n{}:\n{}", heading, text));
+            }
+        }
+
+        Ok(vec![ChatMessage::system(system), ChatMessage::user(prompt)])
+    }
+
+    fn fit(&self, budget: PromptBudget) -> Result<FittedPrompt> {
+        let fitted = budget.fit()?;
+        self.warn_if_cut(&fitted);
+        Ok(fitted)
+    }
+
+    fn warn_if_cut(&self, fitted: &FittedPrompt) {
+        if fitted.sections().iter().any(|s| s.is_cut()) {
+            tracing::warn!("Chapter context truncated to fit the context window of {}", self.llm_client.model());
+        }
+    }
+
+    /// Record the parameters that will actually be sent for `options`
+    fn params_for(&self, options: &GenerationOptions) -> GenerationParams {
+        let sent = self.llm_client.resolve_options(options);
+        GenerationParams {
+            model: self.llm_client.model().to_string(),
+            temperature: sent.temperature.unwrap_or_default(),
+            max_tokens: sent.max_tokens.unwrap_or_default(),
+            top_p: sent.top_p,
+            stop: sent.stop,
+            seed: sent.seed,
+        }
+    }
 }
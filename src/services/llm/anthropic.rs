@@ -0,0 +1,364 @@
+//! Anthropic Messages API
+//!
+//! System text goes in a top-level `system` field rather than a message,
+//! content is a list of blocks, and `max_tokens` is required.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error, sse, ChatMessage, Completion, EventStream, GenerationOptions, LlmError, LlmProvider,
+    Role, StreamEvent,
+};
+use crate::models::TokenUsage;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Sent when the request leaves `max_tokens` unset, since the API requires it
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Serialize)]
+struct AnthropicRequest {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicMessage>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct AnthropicMessage {
+    role: &'static str,
+    content: Vec<AnthropicContent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContent {
+    Text { text: String },
+    /// Blocks other than text, such as tool use, are ignored
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    #[serde(default)]
+    content: Vec<AnthropicContent>,
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: AnthropicUsage,
+}
+
+#[derive(Deserialize, Default)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+impl From<AnthropicUsage> for TokenUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        TokenUsage::new(usage.input_tokens, usage.output_tokens)
+    }
+}
+
+/// Error body: `{"type": "error", "error": {"type": "...", "message": "..."}}`
+#[derive(Deserialize)]
+struct AnthropicErrorBody {
+    error: AnthropicErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct AnthropicErrorDetail {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStreamEvent {
+    MessageStart { message: AnthropicStreamMessage },
+    ContentBlockDelta { delta: AnthropicDelta },
+    MessageDelta {
+        #[serde(default)]
+        usage: AnthropicUsage,
+    },
+    Error { error: AnthropicErrorDetail },
+    MessageStop,
+    /// ping, content_block_start, content_block_stop
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AnthropicStreamMessage {
+    #[serde(default)]
+    usage: AnthropicUsage,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicDelta {
+    TextDelta { text: String },
+    #[serde(other)]
+    Other,
+}
+
+/// Classify an Anthropic error by its `error.type`
+///
+/// Types with no direct HTTP equivalent are mapped first; the rest go
+/// through the shared status classification.
+fn classify_error(status: StatusCode, body: &str, retry_after: Option<std::time::Duration>) -> LlmError {
+    match serde_json::from_str::<AnthropicErrorBody>(body) {
+        Ok(parsed) => classify_detail(status, parsed.error, retry_after),
+        Err(_) => LlmError::from_status("anthropic", status, body, retry_after),
+    }
+}
+
+fn classify_detail(status: StatusCode, detail: AnthropicErrorDetail, retry_after: Option<std::time::Duration>) -> LlmError {
+    let AnthropicErrorDetail { kind, message } = detail;
+    let provider = "anthropic".to_string();
+
+    match kind.as_str() {
+        "overloaded_error" | "api_error" => LlmError::Server {
+            provider,
+            status: status.as_u16(),
+            message,
+        },
+        "rate_limit_error" => LlmError::RateLimited {
+            provider,
+            message,
+            retry_after,
+        },
+        "authentication_error" | "permission_error" => LlmError::Auth { provider, message },
+        "request_too_large" => LlmError::ContextTooLong { provider, message },
+        "invalid_request_error" if message.to_lowercase().contains("prompt is too long") => {
+            LlmError::ContextTooLong { provider, message }
+        }
+        _ => LlmError::from_status("anthropic", status, &message, retry_after),
+    }
+}
+
+async fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let retry_after = error::retry_after(&response);
+    let body = response.text().await.unwrap_or_default();
+    Err(classify_error(status, &body, retry_after).into())
+}
+
+/// Provider for the Anthropic Messages API
+pub struct AnthropicProvider {
+    client: Client,
+    api_key: String,
+    model: String,
+    base_url: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(api_key: String, model: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            model: model.unwrap_or_else(|| "claude-3-5-sonnet-latest".to_string()),
+            base_url: "https://api.anthropic.com/v1".to_string(),
+        }
+    }
+
+    /// Override the API base URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    fn build_request(&self, messages: &[ChatMessage], options: &GenerationOptions, stream: bool) -> AnthropicRequest {
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == Role::System)
+            .map(|m| m.content.as_str())
+            .collect();
+
+        // Consecutive messages of the same role are merged, as the API
+        // expects turns to alternate
+        let mut turns: Vec<AnthropicMessage> = Vec::new();
+        for message in messages.iter().filter(|m| m.role != Role::System) {
+            let block = AnthropicContent::Text { text: message.content.clone() };
+            match turns.last_mut() {
+                Some(last) if last.role == message.role.as_str() => last.content.push(block),
+                _ => turns.push(AnthropicMessage {
+                    role: message.role.as_str(),
+                    content: vec![block],
+                }),
+            }
+        }
+
+        AnthropicRequest {
+            model: self.model.clone(),
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages: turns,
+            max_tokens: options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: options.stop.clone(),
+            stream,
+        }
+    }
+
+    async fn post(&self, request: &AnthropicRequest) -> Result<Response> {
+        let response = self.client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network("anthropic", e))
+            .context("Failed to call Anthropic API")?;
+        check_status(response).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        tracing::info!("Calling Anthropic API with model: {}", self.model);
+
+        let response = self.post(&self.build_request(messages, options, false)).await?;
+        let api_response: AnthropicResponse = response.json().await
+            .context("Failed to parse Anthropic response")?;
+
+        if api_response.stop_reason.as_deref() == Some("refusal") {
+            return Err(LlmError::ContentBlocked {
+                provider: "anthropic".to_string(),
+                message: "model refused the request".to_string(),
+            }.into());
+        }
+
+        let text: String = api_response
+            .content
+            .into_iter()
+            .filter_map(|block| match block {
+                AnthropicContent::Text { text } => Some(text),
+                AnthropicContent::Other => None,
+            })
+            .collect();
+        if text.is_empty() {
+            return Err(LlmError::empty_response("anthropic").into());
+        }
+
+        Ok(Completion::new(text, api_response.usage.into()))
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        tracing::info!("Streaming from Anthropic API with model: {}", self.model);
+
+        let response = self.post(&self.build_request(messages, options, true)).await?;
+
+        // Input tokens arrive with message_start, output tokens with message_delta
+        let input_tokens = AtomicU32::new(0);
+        Ok(sse::event_stream(response, move |data| {
+            let event: AnthropicStreamEvent = serde_json::from_str(data)
+                .context("Failed to parse Anthropic stream event")?;
+            Ok(match event {
+                AnthropicStreamEvent::MessageStart { message } => {
+                    input_tokens.store(message.usage.input_tokens, Ordering::Relaxed);
+                    vec![StreamEvent::Usage(message.usage.into())]
+                }
+                AnthropicStreamEvent::ContentBlockDelta { delta: AnthropicDelta::TextDelta { text } } => {
+                    vec![StreamEvent::Text(text)]
+                }
+                AnthropicStreamEvent::MessageDelta { usage } => vec![StreamEvent::Usage(TokenUsage::new(
+                    input_tokens.load(Ordering::Relaxed),
+                    usage.output_tokens,
+                ))],
+                AnthropicStreamEvent::MessageStop => vec![StreamEvent::Done],
+                // Errors after the response has started carry no status of their own
+                AnthropicStreamEvent::Error { error } => {
+                    return Err(classify_detail(StatusCode::INTERNAL_SERVER_ERROR, error, None).into());
+                }
+                _ => Vec::new(),
+            })
+        }))
+    }
+
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn supported_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        GenerationOptions { seed: None, ..options.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_moves_system_out_of_messages() {
+        let provider = AnthropicProvider::new("key".to_string(), None);
+        let messages = vec![
+            ChatMessage::system("你是小说作家"),
+            ChatMessage::user("第一段"),
+            ChatMessage::user("第二段"),
+            ChatMessage::assistant("好"),
+        ];
+
+        let json = serde_json::to_value(provider.build_request(&messages, &GenerationOptions::default(), false)).unwrap();
+        assert_eq!(json["system"], "你是小说作家");
+        assert_eq!(json["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(json["messages"], serde_json::json!([
+            { "role": "user", "content": [{ "type": "text", "text": "第一段" }, { "type": "text", "text": "第二段" }] },
+            { "role": "assistant", "content": [{ "type": "text", "text": "好" }] },
+        ]));
+        assert!(json.get("stream").is_none());
+    }
+
+    #[test]
+    fn test_classify_error_shape() {
+        let overloaded = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let err = classify_error(StatusCode::from_u16(529).unwrap(), overloaded, None);
+        assert!(matches!(err, LlmError::Server { status: 529, .. }));
+        assert!(err.is_retryable());
+
+        let too_long = r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#;
+        assert!(matches!(classify_error(StatusCode::BAD_REQUEST, too_long, None), LlmError::ContextTooLong { .. }));
+
+        let invalid = r#"{"type":"error","error":{"type":"invalid_request_error","message":"bad field"}}"#;
+        match classify_error(StatusCode::BAD_REQUEST, invalid, None) {
+            LlmError::InvalidRequest { message, .. } => assert_eq!(message, "bad field"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Fitting prompt sections into a model's context window
+
+use anyhow::Result;
+
+use super::{ChatMessage, GenerationOptions, LlmClient, LlmError, TokenEstimator, DEFAULT_CONTEXT_WINDOW};
+use crate::text::is_sentence_break;
+
+/// How a section gives up tokens when the prompt is over budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Never cut; fitting fails if these sections alone are too long
+    Keep,
+    /// Keep the beginning, e.g. retrieved memories ranked best first
+    KeepStart,
+    /// Keep the end, e.g. the tail of the previous chapter
+    KeepEnd,
+    /// Condense with the LLM in [`PromptBudget::fit_with`]; otherwise and
+    /// if that fails, keep the beginning
+    Summarize,
+    /// Remove the whole section
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+    name: String,
+    text: String,
+    priority: u8,
+    overflow: Overflow,
+}
+
+/// Builder fitting prioritized prompt sections into a token budget
+///
+/// The budget is the context window minus the tokens reserved for the
+/// reply. When the sections exceed it, the lowest-priority sections are cut
+/// first; among equal priorities the one added last goes first.
+#[derive(Debug, Clone)]
+pub struct PromptBudget {
+    window: u32,
+    reserved: u32,
+    estimator: TokenEstimator,
+    sections: Vec<Section>,
+}
+
+impl PromptBudget {
+    /// Budget of `window` tokens, counted with the default estimator
+    pub fn new(window: u32) -> Self {
+        Self {
+            window,
+            reserved: 0,
+            estimator: TokenEstimator::default(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Budget for a request to `client`: its context window, its tokenizer
+    /// and room for the reply allowed by `options`
+    ///
+    /// The reply gets at most half the window, so a `max_tokens` as large
+    /// as a small window still leaves room for the prompt.
+    pub fn for_client(client: &LlmClient, options: &GenerationOptions) -> Self {
+        let window = client.context_window();
+        let reply = client.resolve_options(options).max_tokens.unwrap_or(0).min(window / 2);
+        Self::new(window)
+            .estimator(client.token_estimator())
+            .reserve(reply)
+    }
+
+    /// Count tokens with `estimator`
+    pub fn estimator(mut self, estimator: TokenEstimator) -> Self {
+        self.estimator = estimator;
+        self
+    }
+
+    /// Keep `tokens` of the window free, in addition to earlier reservations
+    pub fn reserve(mut self, tokens: u32) -> Self {
+        self.reserved = self.reserved.saturating_add(tokens);
+        self
+    }
+
+    /// Add a section; higher `priority` is kept longer
+    pub fn section(mut self, name: impl Into<String>, text: impl Into<String>, priority: u8, overflow: Overflow) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            text: text.into(),
+            priority,
+            overflow,
+        });
+        self
+    }
+
+    /// Tokens left for the sections
+    pub fn available(&self) -> u32 {
+        self.window.saturating_sub(self.reserved)
+    }
+
+    /// Fit by truncating and dropping sections, without any LLM calls
+    pub fn fit(&self) -> Result<FittedPrompt> {
+        let targets = self.targets()?;
+        Ok(FittedPrompt {
+            sections: self
+                .sections
+                .iter()
+                .zip(targets)
+                .map(|(section, target)| self.cut(section, target))
+                .collect(),
+        })
+    }
+
+    /// Fit like [`PromptBudget::fit`], but condense [`Overflow::Summarize`]
+    /// sections with `client` instead of truncating them
+    pub async fn fit_with(&self, client: &LlmClient) -> Result<FittedPrompt> {
+        let targets = self.targets()?;
+        let mut sections = Vec::with_capacity(self.sections.len());
+        for (section, target) in self.sections.iter().zip(targets) {
+            let original = self.estimator.count(&section.text);
+            if section.overflow != Overflow::Summarize || target >= original || target == 0 {
+                sections.push(self.cut(section, target));
+                continue;
+            }
+
+            match self.summarize(client, &section.text, target).await {
+                Ok(summary) => {
+                    let condensed = Section { text: summary, ..section.clone() };
+                    let mut fitted = self.cut(&condensed, target);
+                    fitted.original_tokens = original;
+                    sections.push(fitted);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to summarize prompt section {}, truncating: {}", section.name, e);
+                    sections.push(self.cut(section, target));
+                }
+            }
+        }
+        Ok(FittedPrompt { sections })
+    }
+
+    /// Tokens each section may keep
+    fn targets(&self) -> Result<Vec<u32>> {
+        let counts: Vec<u32> = self.sections.iter().map(|s| self.estimator.count(&s.text)).collect();
+        let mut targets = counts.clone();
+        let total: u32 = counts.iter().sum();
+        let mut over = total.saturating_sub(self.available());
+        if over == 0 {
+            return Ok(targets);
+        }
+
+        let mut order: Vec<usize> = (0..self.sections.len()).collect();
+        order.sort_by_key(|&i| (self.sections[i].priority, std::cmp::Reverse(i)));
+        for i in order {
+            if over == 0 {
+                break;
+            }
+            let cut = match self.sections[i].overflow {
+                Overflow::Keep => continue,
+                Overflow::Drop => counts[i],
+                _ => counts[i].min(over),
+            };
+            targets[i] -= cut;
+            over = over.saturating_sub(cut);
+        }
+
+        if over > 0 {
+            return Err(LlmError::ContextTooLong {
+                provider: "prompt".to_string(),
+                message: format!(
+                    "sections that cannot be cut need {} tokens but only {} are available",
+                    targets.iter().sum::<u32>(),
+                    self.available()
+                ),
+            }
+            .into());
+        }
+        Ok(targets)
+    }
+
+    /// Section cut down to `target` tokens
+    fn cut(&self, section: &Section, target: u32) -> FittedSection {
+        let original_tokens = self.estimator.count(&section.text);
+        let text = if target >= original_tokens {
+            section.text.clone()
+        } else {
+            match section.overflow {
+                Overflow::KeepEnd => truncate_end(&self.estimator, &section.text, target),
+                Overflow::Drop => String::new(),
+                _ => truncate_start(&self.estimator, &section.text, target),
+            }
+        };
+        FittedSection {
+            name: section.name.clone(),
+            tokens: self.estimator.count(&text),
+            text,
+            original_tokens,
+        }
+    }
+
+    async fn summarize(&self, client: &LlmClient, text: &str, target: u32) -> Result<String> {
+        let chars = self.estimator.chars_for(target);
+        let messages = [
+            ChatMessage::system("你是小说编辑，负责压缩背景资料，保留人物、地点、关键事件和伏笔，不添加新内容。"),
+            ChatMessage::user(format!("请将以下内容压缩到{}字以内：\n\n{}", chars, text)),
+        ];
+        let options = GenerationOptions::new().temperature(0.3).max_tokens(target.max(64));
+        Ok(client.chat_with(&messages, &options).await?.text)
+    }
+}
+
+impl Default for PromptBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONTEXT_WINDOW)
+    }
+}
+
+/// A section after fitting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FittedSection {
+    pub name: String,
+    pub text: String,
+    /// Estimated tokens of `text`
+    pub tokens: u32,
+    /// Estimated tokens before fitting
+    pub original_tokens: u32,
+}
+
+impl FittedSection {
+    /// Whether the section was truncated, summarized or dropped
+    pub fn is_cut(&self) -> bool {
+        self.tokens < self.original_tokens
+    }
+}
+
+/// Sections of a prompt that fits its budget, in the order they were added
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FittedPrompt {
+    sections: Vec<FittedSection>,
+}
+
+impl FittedPrompt {
+    /// Text of the section called `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.sections.iter().find(|s| s.name == name).map(|s| s.text.as_str())
+    }
+
+    pub fn sections(&self) -> &[FittedSection] {
+        &self.sections
+    }
+
+    /// Estimated tokens of all sections
+    pub fn tokens(&self) -> u32 {
+        self.sections.iter().map(|s| s.tokens).sum()
+    }
+}
+
+/// Largest prefix of `text` within `tokens`, ending at a sentence if one
+/// ends in its last fifth
+fn truncate_start(estimator: &TokenEstimator, text: &str, tokens: u32) -> String {
+    let mut used = 0.0;
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        used += estimator.cost(c);
+        if used.ceil() as u32 > tokens {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+
+    let kept = &text[..end];
+    let floor = end - end / 5;
+    match kept.rfind(is_sentence_break) {
+        Some(i) if i >= floor => {
+            let boundary = i + kept[i..].chars().next().map_or(0, char::len_utf8);
+            kept[..boundary].to_string()
+        }
+        _ => kept.to_string(),
+    }
+}
+
+/// Largest suffix of `text` within `tokens`, starting after a sentence end
+/// if one is in its first fifth
+fn truncate_end(estimator: &TokenEstimator, text: &str, tokens: u32) -> String {
+    let mut used = 0.0;
+    let mut start = text.len();
+    for (i, c) in text.char_indices().rev() {
+        used += estimator.cost(c);
+        if used.ceil() as u32 > tokens {
+            break;
+        }
+        start = i;
+    }
+
+    let kept = &text[start..];
+    let ceiling = kept.len() / 5;
+    match kept.find(is_sentence_break) {
+        Some(i) if i < ceiling => {
+            let boundary = i + kept[i..].chars().next().map_or(0, char::len_utf8);
+            kept[boundary..].trim_start().to_string()
+        }
+        _ => kept.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::llm::{Completion, LlmProvider};
+
+    fn budget(window: u32) -> PromptBudget {
+        PromptBudget::new(window).estimator(TokenEstimator::for_provider("openai"))
+    }
+
+    #[test]
+    fn test_fits_unchanged_when_under_budget() {
+        let prompt = budget(100)
+            .section("instructions", "写第一章", 100, Overflow::Keep)
+            .section("memory", "林风是青云宗弟子", 40, Overflow::KeepStart)
+            .fit()
+            .unwrap();
+        assert_eq!(prompt.get("memory"), Some("林风是青云宗弟子"));
+        assert!(prompt.sections().iter().all(|s| !s.is_cut()));
+        assert_eq!(prompt.tokens(), 12);
+    }
+
+    #[test]
+    fn test_cuts_lowest_priority_first() {
+        let recent = "第一句。第二句。第三句。第四句。第五句。";
+        let prompt = budget(30)
+            .reserve(10)
+            .section("instructions", "写第二章", 100, Overflow::Keep)
+            .section("recent", recent, 60, Overflow::KeepEnd)
+            .section("memory", "旧事".repeat(10), 40, Overflow::KeepStart)
+            .section("style", "文风参考", 40, Overflow::Drop)
+            .fit()
+            .unwrap();
+
+        // Dropped first (added last among the lowest), then memory emptied,
+        // then the head of the recent text
+        assert_eq!(prompt.get("style"), Some(""));
+        assert_eq!(prompt.get("memory"), Some(""));
+        assert_eq!(prompt.get("instructions"), Some("写第二章"));
+        let kept = prompt.get("recent").unwrap();
+        assert!(recent.ends_with(kept) && kept.starts_with("第"), "kept: {}", kept);
+        assert!(prompt.tokens() <= 20);
+    }
+
+    #[test]
+    fn test_truncation_prefers_sentence_boundaries() {
+        let estimator = TokenEstimator::for_provider("openai");
+        assert_eq!(truncate_start(&estimator, "林风出剑。剑光如雪照亮山门", 12), "林风出剑。剑光如雪照亮山");
+        assert_eq!(truncate_start(&estimator, "林风拔剑而起，剑光如雪。山门", 13), "林风拔剑而起，剑光如雪。");
+        assert_eq!(truncate_end(&estimator, "前情。林风回到青云宗", 8), "林风回到青云宗");
+    }
+
+    struct Summarizer;
+
+    #[async_trait::async_trait]
+    impl LlmProvider for Summarizer {
+        async fn chat(&self, messages: &[ChatMessage], _options: &GenerationOptions) -> Result<Completion> {
+            assert!(messages[1].content.contains("压缩"));
+            Ok(Completion::new("林风入青云宗。".to_string(), Default::default()))
+        }
+
+        fn name(&self) -> &str {
+            "openai"
+        }
+
+        fn model(&self) -> &str {
+            "gpt-4o"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarizes_instead_of_truncating() {
+        let client = LlmClient::new(Box::new(Summarizer)).with_context_window(30);
+        let options = GenerationOptions::new().max_tokens(10);
+        let prompt = PromptBudget::for_client(&client, &options)
+            .section("instructions", "写第二章", 100, Overflow::Keep)
+            .section("memory", "林风在第一章里拜入了青云宗，成为外门弟子。", 40, Overflow::Summarize)
+            .fit_with(&client)
+            .await
+            .unwrap();
+
+        let memory = &prompt.sections()[1];
+        assert_eq!(memory.text, "林风入青云宗。");
+        assert!(memory.is_cut());
+        assert!(prompt.tokens() <= 20);
+    }
+
+    #[test]
+    fn test_reply_reservation_capped_at_half_the_window() {
+        let client = LlmClient::new(Box::new(Summarizer)).with_context_window(4096);
+        let budget = PromptBudget::for_client(&client, &GenerationOptions::new().max_tokens(4096));
+        assert_eq!(budget.available(), 2048);
+        let budget = PromptBudget::for_client(&client, &GenerationOptions::new().max_tokens(1000));
+        assert_eq!(budget.available(), 3096);
+    }
+
+    #[test]
+    fn test_required_sections_over_budget_is_error() {
+        let err = budget(10)
+            .section("instructions", "很长的指令".repeat(5), 100, Overflow::Keep)
+            .section("memory", "记忆", 40, Overflow::KeepStart)
+            .fit()
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<LlmError>(), Some(LlmError::ContextTooLong { .. })));
+    }
+}
@@ -0,0 +1,386 @@
+//! On-disk response cache
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{ChatMessage, Completion, EventStream, GenerationOptions, LlmProvider, StreamEvent};
+use crate::config::ResponseCacheConfig;
+use crate::models::TokenUsage;
+
+/// A cached response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    cached_at_secs: u64,
+    provider: String,
+    model: String,
+    text: String,
+    /// Usage of the original call
+    usage: TokenUsage,
+}
+
+/// Directory of responses keyed by request hash, one file per entry
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_bytes: u64,
+}
+
+impl ResponseCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+            max_bytes,
+        }
+    }
+
+    pub fn from_config(config: &ResponseCacheConfig) -> Self {
+        Self::new(
+            &config.path,
+            Duration::from_secs(config.ttl_hours as u64 * 3600),
+            config.max_size_mb * 1024 * 1024,
+        )
+    }
+
+    /// Key of a request to `provider`/`model`
+    pub fn key(provider: &str, model: &str, messages: &[ChatMessage], options: &GenerationOptions) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(&(provider, model, messages, options)).unwrap_or_default());
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Cached response for `key`, unless missing or expired
+    ///
+    /// Hits report zero usage since nothing was spent on them.
+    pub fn get(&self, key: &str) -> Option<Completion> {
+        let content = fs::read_to_string(self.path(key)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&content).ok()?;
+        if now_secs().saturating_sub(entry.cached_at_secs) > self.ttl.as_secs() {
+            return None;
+        }
+
+        let mut completion = Completion::new(entry.text, TokenUsage::default());
+        completion.fill_source(&entry.provider, &entry.model);
+        Some(completion)
+    }
+
+    /// Store a response and evict entries over the size cap
+    pub fn put(&self, key: &str, provider: &str, model: &str, text: &str, usage: TokenUsage) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create cache directory {:?}", self.dir))?;
+        let entry = CacheEntry {
+            cached_at_secs: now_secs(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            text: text.to_string(),
+            usage,
+        };
+        fs::write(self.path(key), serde_json::to_string(&entry)?)
+            .with_context(|| format!("Failed to write cache entry {}", key))?;
+        self.prune()
+    }
+
+    /// Remove expired entries, then the oldest ones until under the size cap
+    fn prune(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            if modified.elapsed().unwrap_or_default() > self.ttl {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            entries.push((modified, metadata.len(), path));
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            let _ = fs::remove_file(&path);
+            total -= len;
+        }
+        Ok(())
+    }
+
+    /// Delete every entry, returning how many were removed
+    pub fn clear(&self) -> Result<usize> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove cache entry {:?}", path))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// What a stream has produced so far
+#[derive(Default)]
+struct StreamRecord {
+    text: String,
+    usage: TokenUsage,
+    done: bool,
+    failed: bool,
+}
+
+/// Provider serving repeated requests from a [`ResponseCache`]
+pub struct CachedProvider {
+    inner: Box<dyn LlmProvider>,
+    cache: ResponseCache,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Box<dyn LlmProvider>, cache: ResponseCache) -> Self {
+        Self { inner, cache }
+    }
+
+    fn key(&self, messages: &[ChatMessage], options: &GenerationOptions) -> String {
+        ResponseCache::key(self.inner.name(), self.inner.model(), messages, options)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CachedProvider {
+    async fn chat(&self, messages: &[ChatMessage], options: &GenerationOptions) -> Result<Completion> {
+        let key = self.key(messages, options);
+        if let Some(completion) = self.cache.get(&key) {
+            tracing::debug!("LLM cache hit {}", key);
+            return Ok(completion);
+        }
+
+        let mut completion = self.inner.chat(messages, options).await?;
+        completion.fill_source(self.inner.name(), self.inner.model());
+        if let Err(e) = self.cache.put(&key, &completion.provider, &completion.model, &completion.text, completion.usage) {
+            tracing::warn!("Failed to cache LLM response: {}", e);
+        }
+        Ok(completion)
+    }
+
+    async fn chat_stream(&self, messages: &[ChatMessage], options: &GenerationOptions) -> Result<EventStream> {
+        let key = self.key(messages, options);
+        if let Some(completion) = self.cache.get(&key) {
+            tracing::debug!("LLM cache hit {}", key);
+            return Ok(completion.into_events());
+        }
+
+        // Cache the stream only once the provider has finished it without errors
+        let stream = self.inner.chat_stream(messages, options).await?;
+        let seen = Arc::new(Mutex::new(StreamRecord::default()));
+        let recorder = seen.clone();
+        let recorded = stream.inspect(move |event| {
+            let mut seen = recorder.lock().unwrap();
+            match event {
+                Ok(StreamEvent::Text(text)) => seen.text.push_str(text),
+                Ok(StreamEvent::Usage(usage)) => seen.usage = *usage,
+                Ok(StreamEvent::Done) => seen.done = true,
+                Err(_) => seen.failed = true,
+            }
+        });
+
+        let cache = self.cache.clone();
+        let (provider, model) = (self.inner.name().to_string(), self.inner.model().to_string());
+        let finish = futures::stream::once(async move {
+            let StreamRecord { text, usage, done, failed } = &*seen.lock().unwrap();
+            if *done && !failed && !text.is_empty() {
+                if let Err(e) = cache.put(&key, &provider, &model, text, *usage) {
+                    tracing::warn!("Failed to cache LLM response: {}", e);
+                }
+            }
+            None
+        })
+        .filter_map(futures::future::ready);
+
+        Ok(Box::pin(recorded.chain(finish)))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn supported_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        self.inner.supported_options(options)
+    }
+
+    fn cached(&self, messages: &[ChatMessage], options: &GenerationOptions) -> Option<Completion> {
+        self.cache.get(&self.key(messages, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tempfile::tempdir;
+
+    struct CountingProvider {
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for CountingProvider {
+        async fn chat(&self, messages: &[ChatMessage], _options: &GenerationOptions) -> Result<Completion> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Completion::new(format!("回复：{}", messages[0].content), TokenUsage::new(5, 7)))
+        }
+
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn model(&self) -> &str {
+            "counting-1"
+        }
+    }
+
+    fn cached(dir: &std::path::Path, calls: &Arc<AtomicU32>) -> CachedProvider {
+        let cache = ResponseCache::new(dir, Duration::from_secs(3600), 1024 * 1024);
+        CachedProvider::new(Box::new(CountingProvider { calls: calls.clone() }), cache)
+    }
+
+    #[tokio::test]
+    async fn test_repeated_request_served_from_cache() {
+        let dir = tempdir().unwrap();
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = cached(dir.path(), &calls);
+        let options = GenerationOptions::default();
+
+        let first = provider.chat(&[ChatMessage::user("大纲")], &options).await.unwrap();
+        let second = provider.chat(&[ChatMessage::user("大纲")], &options).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first.text, second.text);
+        assert_eq!(second.provider, "counting");
+        assert_eq!(second.usage, TokenUsage::default());
+
+        // Different options are a different request
+        provider.chat(&[ChatMessage::user("大纲")], &options.clone().temperature(0.1)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Streams share the cache
+        let mut stream = provider.chat_stream(&[ChatMessage::user("大纲")], &options).await.unwrap();
+        let mut text = String::new();
+        while let Some(event) = stream.next().await {
+            if let StreamEvent::Text(chunk) = event.unwrap() {
+                text.push_str(&chunk);
+            }
+        }
+        assert_eq!(text, first.text);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Provider whose stream is cut off before the end
+    struct TruncatedProvider;
+
+    #[async_trait]
+    impl LlmProvider for TruncatedProvider {
+        async fn chat(&self, _messages: &[ChatMessage], _options: &GenerationOptions) -> Result<Completion> {
+            unreachable!("only streams")
+        }
+
+        async fn chat_stream(&self, _messages: &[ChatMessage], _options: &GenerationOptions) -> Result<EventStream> {
+            Ok(Box::pin(futures::stream::iter([
+                Ok(StreamEvent::Text("林风".to_string())),
+                Ok(StreamEvent::Usage(TokenUsage::new(5, 2))),
+            ])))
+        }
+
+        fn name(&self) -> &str {
+            "truncated"
+        }
+
+        fn model(&self) -> &str {
+            "truncated-1"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_stream_not_cached() {
+        let dir = tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path(), Duration::from_secs(3600), 1024 * 1024);
+        let provider = CachedProvider::new(Box::new(TruncatedProvider), cache);
+        let messages = [ChatMessage::user("正文")];
+        let options = GenerationOptions::default();
+
+        let mut stream = provider.chat_stream(&messages, &options).await.unwrap();
+        while stream.next().await.is_some() {}
+        assert!(provider.cached(&messages, &options).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_and_clear() {
+        let dir = tempdir().unwrap();
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = cached(dir.path(), &calls);
+        let messages = [ChatMessage::user("计划")];
+        let options = GenerationOptions::default();
+
+        provider.chat(&messages, &options).await.unwrap();
+        let key = provider.key(&messages, &options);
+        let path = provider.cache.path(&key);
+        let mut entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        entry.cached_at_secs -= 7200;
+        fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+
+        provider.chat(&messages, &options).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        assert_eq!(provider.cache.clear().unwrap(), 1);
+        assert!(provider.cache.get(&key).is_none());
+    }
+
+    #[test]
+    fn test_size_cap_evicts_oldest() {
+        let dir = tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path(), Duration::from_secs(3600), 1_000);
+        let text = "字".repeat(100);
+
+        cache.put("old", "p", "m", &text, TokenUsage::default()).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("mid", "p", "m", &text, TokenUsage::default()).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("new", "p", "m", &text, TokenUsage::default()).unwrap();
+
+        assert!(cache.get("old").is_none());
+        assert!(cache.get("new").is_some());
+    }
+}
@@ -0,0 +1,148 @@
+//! Provider responses and their token usage
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use futures::Stream;
+
+use crate::models::TokenUsage;
+
+/// A finished response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub text: String,
+
+    /// Token usage reported by the provider (zero if it reported none)
+    pub usage: TokenUsage,
+
+    /// Provider that produced the response
+    pub provider: String,
+
+    /// Model that produced the response
+    pub model: String,
+}
+
+impl Completion {
+    /// A response whose source is filled in by the client that returns it
+    pub fn new(text: impl Into<String>, usage: TokenUsage) -> Self {
+        Self {
+            text: text.into(),
+            usage,
+            provider: String::new(),
+            model: String::new(),
+        }
+    }
+
+    /// Set the provider and model unless already known
+    pub(crate) fn fill_source(&mut self, provider: &str, model: &str) {
+        if self.provider.is_empty() {
+            self.provider = provider.to_string();
+        }
+        if self.model.is_empty() {
+            self.model = model.to_string();
+        }
+    }
+
+    /// The response as a finished stream: its text, usage and end
+    pub fn into_events(self) -> EventStream {
+        Box::pin(futures::stream::iter([
+            Ok(StreamEvent::Text(self.text)),
+            Ok(StreamEvent::Usage(self.usage)),
+            Ok(StreamEvent::Done),
+        ]))
+    }
+}
+
+/// Item of a provider stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// A chunk of generated text
+    Text(String),
+    /// Usage so far; a later event replaces an earlier one
+    Usage(TokenUsage),
+    /// The provider marked the response as finished; a stream cut off
+    /// early ends without it
+    Done,
+}
+
+/// Stream of events produced while a response is being generated
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>;
+
+/// Text chunks of a streaming response
+///
+/// Yields only text; usage events are collected and available from
+/// [`CompletionStream::usage`] once the stream has ended.
+pub struct CompletionStream {
+    inner: EventStream,
+    usage: TokenUsage,
+    provider: String,
+    model: String,
+}
+
+impl CompletionStream {
+    pub fn new(inner: EventStream, provider: &str, model: &str) -> Self {
+        Self {
+            inner,
+            usage: TokenUsage::default(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+        }
+    }
+
+    /// Usage reported so far
+    pub fn usage(&self) -> TokenUsage {
+        self.usage
+    }
+
+    /// Provider producing the stream
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    /// Model producing the stream
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+impl Stream for CompletionStream {
+    type Item = Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(StreamEvent::Usage(usage)))) => self.usage = usage,
+                Poll::Ready(Some(Ok(StreamEvent::Text(text)))) => return Poll::Ready(Some(Ok(text))),
+                Poll::Ready(Some(Ok(StreamEvent::Done))) => {}
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_completion_stream_collects_usage() {
+        let events = vec![
+            Ok(StreamEvent::Text("第一".to_string())),
+            Ok(StreamEvent::Usage(TokenUsage::new(10, 1))),
+            Ok(StreamEvent::Text("段".to_string())),
+            Ok(StreamEvent::Usage(TokenUsage::new(10, 2))),
+        ];
+        let mut stream = CompletionStream::new(Box::pin(futures::stream::iter(events)), "test", "test-1");
+
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            text.push_str(&chunk.unwrap());
+        }
+        assert_eq!(text, "第一段");
+        assert_eq!(stream.usage(), TokenUsage::new(10, 2));
+    }
+}
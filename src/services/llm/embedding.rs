@@ -0,0 +1,398 @@
+//! Text embeddings for retrieval
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{error, LlmError, LlmProviderType, LocalEmbeddingProvider, LOCAL_EMBEDDING_DIMENSIONS};
+use crate::config::LlmConfig;
+
+/// Turns text into vectors for a [`VectorStore`](crate::services::VectorStore)
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embed one batch of texts, returning one vector per text in order
+    ///
+    /// Callers should not send more than [`EmbeddingProvider::batch_size`]
+    /// texts; use [`EmbeddingProvider::embed`] for arbitrary input.
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// Embed any number of texts, split into batches
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size().max(1)) {
+            let embedded = self.embed_batch(batch).await?;
+            if embedded.len() != batch.len() {
+                anyhow::bail!(
+                    "{} returned {} embeddings for {} texts",
+                    self.name(),
+                    embedded.len(),
+                    batch.len()
+                );
+            }
+            vectors.extend(embedded);
+        }
+        Ok(vectors)
+    }
+
+    /// Embed a single text
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| LlmError::empty_response(self.name()).into())
+    }
+
+    /// Adapt to the corpus that will be searched, such as IDF weights
+    ///
+    /// Call before embedding any of it. Providers backed by a trained model
+    /// ignore the corpus.
+    fn fit(&mut self, _corpus: &[String]) {}
+
+    /// Most texts accepted per request
+    fn batch_size(&self) -> usize;
+
+    fn name(&self) -> &str;
+
+    /// Embedding model name
+    fn model(&self) -> &str;
+}
+
+// ============ OpenAI-compatible /embeddings ============
+
+#[derive(Serialize)]
+struct OpenAIEmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbeddingResponse {
+    data: Vec<OpenAIEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbedding {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Embeddings from an OpenAI-compatible `/embeddings` endpoint
+pub struct OpenAiEmbeddingProvider {
+    client: Client,
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    batch_size: usize,
+}
+
+impl OpenAiEmbeddingProvider {
+    /// `base_url` is the API root, e.g. `https://api.openai.com/v1`
+    pub fn new(base_url: impl Into<String>, api_key: Option<String>, model: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            name: "openai".to_string(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|key| !key.is_empty()),
+            model: model.into(),
+            batch_size: 64,
+        }
+    }
+
+    /// Name reported in logs and errors
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    fn post(&self) -> RequestBuilder {
+        let request = self.client.post(format!("{}/embeddings", self.base_url));
+        match &self.api_key {
+            Some(key) => request.header("Authorization", format!("Bearer {}", key)),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiEmbeddingProvider {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        tracing::debug!("Embedding {} texts with {} model {}", texts.len(), self.name, self.model);
+
+        let response = self.post()
+            .json(&OpenAIEmbeddingRequest { model: &self.model, input: texts })
+            .send()
+            .await
+            .map_err(|e| LlmError::network(&self.name, e))
+            .with_context(|| format!("Failed to call {} embeddings API", self.name))?;
+        let response = error::check_status(&self.name, response).await?;
+
+        let mut api_response: OpenAIEmbeddingResponse = response.json().await
+            .with_context(|| format!("Failed to parse {} embeddings response", self.name))?;
+        api_response.data.sort_by_key(|e| e.index);
+        Ok(api_response.data.into_iter().map(|e| e.embedding).collect())
+    }
+
+    fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+// ============ DashScope text-embedding ============
+
+#[derive(Serialize)]
+struct DashScopeEmbeddingRequest<'a> {
+    model: &'a str,
+    input: DashScopeEmbeddingInput<'a>,
+    parameters: DashScopeEmbeddingParameters,
+}
+
+#[derive(Serialize)]
+struct DashScopeEmbeddingInput<'a> {
+    texts: &'a [String],
+}
+
+#[derive(Serialize)]
+struct DashScopeEmbeddingParameters {
+    text_type: &'static str,
+}
+
+#[derive(Deserialize)]
+struct DashScopeEmbeddingResponse {
+    output: DashScopeEmbeddingOutput,
+}
+
+#[derive(Deserialize)]
+struct DashScopeEmbeddingOutput {
+    embeddings: Vec<DashScopeEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct DashScopeEmbedding {
+    #[serde(default)]
+    text_index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Embeddings from DashScope's text-embedding models
+pub struct DashScopeEmbeddingProvider {
+    client: Client,
+    api_key: String,
+    model: String,
+    base_url: String,
+    batch_size: usize,
+}
+
+impl DashScopeEmbeddingProvider {
+    pub fn new(api_key: String, model: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            model: model.unwrap_or_else(|| "text-embedding-v2".to_string()),
+            base_url: "https://dashscope.aliyuncs.com/api/v1".to_string(),
+            // text-embedding-v2 accepts up to 25 texts per request
+            batch_size: 25,
+        }
+    }
+
+    /// Override the DashScope API base URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for DashScopeEmbeddingProvider {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        tracing::debug!("Embedding {} texts with DashScope model {}", texts.len(), self.model);
+
+        let request = DashScopeEmbeddingRequest {
+            model: &self.model,
+            input: DashScopeEmbeddingInput { texts },
+            parameters: DashScopeEmbeddingParameters { text_type: "document" },
+        };
+        let response = self.client
+            .post(format!("{}/services/embeddings/text-embedding/text-embedding", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network("qwen", e))
+            .context("Failed to call DashScope embeddings API")?;
+        let response = error::check_status("qwen", response).await?;
+
+        let mut api_response: DashScopeEmbeddingResponse = response.json().await
+            .context("Failed to parse DashScope embeddings response")?;
+        api_response.output.embeddings.sort_by_key(|e| e.text_index);
+        Ok(api_response.output.embeddings.into_iter().map(|e| e.embedding).collect())
+    }
+
+    fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn name(&self) -> &str {
+        "qwen"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Create the embedding provider described by `[llm.embedding]`
+///
+/// Without that section, Qwen, OpenAI and OpenAI-compatible setups embed with
+/// the same provider and credentials as `[llm]`.
+pub fn create_embedding_provider(config: &LlmConfig) -> Result<Box<dyn EmbeddingProvider>> {
+    let embedding = config.embedding.clone().unwrap_or_default();
+    let provider = embedding.provider.clone().unwrap_or_else(|| config.provider.clone());
+    if provider.eq_ignore_ascii_case("local") && embedding.provider.is_some() {
+        let dimensions = embedding.dimensions.unwrap_or(LOCAL_EMBEDDING_DIMENSIONS);
+        return Ok(Box::new(LocalEmbeddingProvider::new(dimensions)));
+    }
+    let same_provider = provider.eq_ignore_ascii_case(&config.provider);
+
+    // Credentials fall back to [llm] only for the provider configured there
+    let api_key = embedding.api_key.clone()
+        .or_else(|| same_provider.then(|| config.api_key.clone()))
+        .unwrap_or_default();
+    let base_url = embedding.base_url.clone()
+        .or_else(|| if same_provider { config.base_url.clone() } else { None })
+        .filter(|url| !url.is_empty());
+
+    let embedder: Box<dyn EmbeddingProvider> = match LlmProviderType::from_str(&provider)? {
+        LlmProviderType::Qwen => {
+            let embedder = DashScopeEmbeddingProvider::new(api_key, embedding.model.clone());
+            let embedder = match base_url {
+                Some(url) => embedder.with_base_url(url),
+                None => embedder,
+            };
+            Box::new(match embedding.batch_size {
+                Some(size) => embedder.with_batch_size(size),
+                None => embedder,
+            })
+        }
+        LlmProviderType::OpenAI => {
+            let base_url = base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string());
+            let model = embedding.model.clone().unwrap_or_else(|| "text-embedding-3-small".to_string());
+            let embedder = OpenAiEmbeddingProvider::new(base_url, Some(api_key), model);
+            Box::new(match embedding.batch_size {
+                Some(size) => embedder.with_batch_size(size),
+                None => embedder,
+            })
+        }
+        LlmProviderType::OpenAICompatible => {
+            let base_url = base_url
+                .context("llm.embedding.base_url is required for the openai_compatible provider")?;
+            let model = embedding.model.clone()
+                .context("llm.embedding.model is required for the openai_compatible provider")?;
+            let embedder = OpenAiEmbeddingProvider::new(base_url, Some(api_key), model)
+                .with_name("openai_compatible");
+            Box::new(match embedding.batch_size {
+                Some(size) => embedder.with_batch_size(size),
+                None => embedder,
+            })
+        }
+        _ => anyhow::bail!(
+            "Provider '{}' has no embedding API; set [llm.embedding] to qwen, openai, openai_compatible or local",
+            provider
+        ),
+    };
+
+    Ok(embedder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EmbeddingConfig;
+    use std::sync::Mutex;
+
+    /// Provider recording the size of every batch it is sent
+    struct BatchRecorder {
+        batches: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for BatchRecorder {
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            self.batches.lock().unwrap().push(texts.len());
+            Ok(texts.iter().map(|t| vec![t.chars().count() as f32]).collect())
+        }
+
+        fn batch_size(&self) -> usize {
+            3
+        }
+
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn model(&self) -> &str {
+            "recorder-1"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_splits_into_batches() {
+        let provider = BatchRecorder { batches: Mutex::new(Vec::new()) };
+        let texts: Vec<String> = (1..=7).map(|n| "字".repeat(n)).collect();
+
+        let vectors = provider.embed(&texts).await.unwrap();
+        assert_eq!(*provider.batches.lock().unwrap(), vec![3, 3, 1]);
+        assert_eq!(vectors.len(), 7);
+        assert_eq!(vectors[6], vec![7.0]);
+        assert_eq!(provider.embed_one("四个字啊").await.unwrap(), vec![4.0]);
+    }
+
+    #[test]
+    fn test_embedding_provider_from_config() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "qwen".to_string();
+        config.api_key = "key".to_string();
+        let embedder = create_embedding_provider(&config).unwrap();
+        assert_eq!(embedder.model(), "text-embedding-v2");
+        assert_eq!(embedder.batch_size(), 25);
+
+        config.provider = "minimax".to_string();
+        assert!(create_embedding_provider(&config).is_err());
+
+        config.embedding = Some(EmbeddingConfig {
+            provider: Some("openai".to_string()),
+            api_key: Some("sk".to_string()),
+            batch_size: Some(8),
+            ..Default::default()
+        });
+        let embedder = create_embedding_provider(&config).unwrap();
+        assert_eq!(embedder.model(), "text-embedding-3-small");
+        assert_eq!(embedder.batch_size(), 8);
+
+        config.embedding = Some(EmbeddingConfig {
+            provider: Some("local".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(create_embedding_provider(&config).unwrap().name(), "local");
+    }
+}
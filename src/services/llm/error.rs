@@ -0,0 +1,220 @@
+//! Typed LLM errors
+
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::{Response, StatusCode};
+
+/// Errors returned by LLM providers
+///
+/// Providers return these wrapped in `anyhow::Error`; use
+/// `err.downcast_ref::<LlmError>()` to inspect them.
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("{provider} rate limit exceeded: {message}")]
+    RateLimited {
+        provider: String,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("{provider} authentication failed: {message}")]
+    Auth { provider: String, message: String },
+
+    #[error("{provider} context too long: {message}")]
+    ContextTooLong { provider: String, message: String },
+
+    #[error("{provider} server error ({status}): {message}")]
+    Server {
+        provider: String,
+        status: u16,
+        message: String,
+    },
+
+    #[error("{provider} returned an empty response")]
+    EmptyResponse { provider: String },
+
+    #[error("{provider} blocked the content: {message}")]
+    ContentBlocked { provider: String, message: String },
+
+    #[error("{provider} rejected the request ({status}): {message}")]
+    InvalidRequest {
+        provider: String,
+        status: u16,
+        message: String,
+    },
+
+    #[error("{provider} request failed: {message}")]
+    Network { provider: String, message: String },
+
+    /// The request was abandoned because its cancellation token fired
+    #[error("{provider} request cancelled")]
+    Cancelled { provider: String },
+
+    /// The reply could not be parsed as the requested JSON, even after repair
+    #[error("{provider} returned invalid JSON: {message}")]
+    InvalidJson {
+        provider: String,
+        message: String,
+        /// Text of the last reply
+        raw: String,
+    },
+}
+
+impl LlmError {
+    /// Whether the request may succeed if sent again
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            LlmError::RateLimited { .. }
+                | LlmError::Server { .. }
+                | LlmError::EmptyResponse { .. }
+                | LlmError::Network { .. }
+        )
+    }
+
+    /// Whether `err` is, or wraps, a [`LlmError::Cancelled`]
+    pub fn is_cancelled(err: &anyhow::Error) -> bool {
+        matches!(err.downcast_ref::<LlmError>(), Some(LlmError::Cancelled { .. }))
+    }
+
+    /// Delay requested by the provider before retrying, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            LlmError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    pub fn empty_response(provider: &str) -> Self {
+        LlmError::EmptyResponse {
+            provider: provider.to_string(),
+        }
+    }
+
+    pub fn network(provider: &str, err: reqwest::Error) -> Self {
+        LlmError::Network {
+            provider: provider.to_string(),
+            message: err.to_string(),
+        }
+    }
+
+    /// Classify a non-2xx HTTP response
+    pub fn from_status(
+        provider: &str,
+        status: StatusCode,
+        body: &str,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        let provider = provider.to_string();
+        let message = body.trim().chars().take(500).collect::<String>();
+        let lower = body.to_lowercase();
+
+        match status.as_u16() {
+            401 | 403 => LlmError::Auth { provider, message },
+            429 => LlmError::RateLimited {
+                provider,
+                message,
+                retry_after,
+            },
+            413 => LlmError::ContextTooLong { provider, message },
+            400 if is_context_length_message(&lower) => {
+                LlmError::ContextTooLong { provider, message }
+            }
+            400 if is_content_blocked_message(&lower) => {
+                LlmError::ContentBlocked { provider, message }
+            }
+            code if status.is_server_error() => LlmError::Server {
+                provider,
+                status: code,
+                message,
+            },
+            code => LlmError::InvalidRequest {
+                provider,
+                status: code,
+                message,
+            },
+        }
+    }
+}
+
+fn is_context_length_message(lower: &str) -> bool {
+    ["context_length_exceeded", "maximum context length", "range of input length", "too many tokens"]
+        .iter()
+        .any(|marker| lower.contains(marker))
+}
+
+fn is_content_blocked_message(lower: &str) -> bool {
+    ["content_filter", "datainspectionfailed", "data_inspection_failed", "inappropriate content"]
+        .iter()
+        .any(|marker| lower.contains(marker))
+}
+
+/// Pass successful responses through and turn anything else into an `LlmError`
+pub(crate) async fn check_status(provider: &str, response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let retry_after = retry_after(&response);
+    let body = response.text().await.unwrap_or_default();
+
+    Err(LlmError::from_status(provider, status, &body, retry_after).into())
+}
+
+/// Delay from a `Retry-After` header given in seconds
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_status() {
+        let err = LlmError::from_status("openai", StatusCode::UNAUTHORIZED, "bad key", None);
+        assert!(matches!(err, LlmError::Auth { .. }));
+        assert!(!err.is_retryable());
+
+        let err = LlmError::from_status(
+            "openai",
+            StatusCode::TOO_MANY_REQUESTS,
+            "slow down",
+            Some(Duration::from_secs(3)),
+        );
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(3)));
+
+        let err = LlmError::from_status("qwen", StatusCode::BAD_GATEWAY, "", None);
+        assert!(matches!(err, LlmError::Server { status: 502, .. }));
+    }
+
+    #[test]
+    fn test_classify_bad_request_body() {
+        let err = LlmError::from_status(
+            "openai",
+            StatusCode::BAD_REQUEST,
+            r#"{"error":{"code":"context_length_exceeded"}}"#,
+            None,
+        );
+        assert!(matches!(err, LlmError::ContextTooLong { .. }));
+
+        let err = LlmError::from_status(
+            "qwen",
+            StatusCode::BAD_REQUEST,
+            r#"{"code":"DataInspectionFailed"}"#,
+            None,
+        );
+        assert!(matches!(err, LlmError::ContentBlocked { .. }));
+
+        let err = LlmError::from_status("qwen", StatusCode::BAD_REQUEST, "oops", None);
+        assert!(matches!(err, LlmError::InvalidRequest { status: 400, .. }));
+    }
+}
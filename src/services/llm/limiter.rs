@@ -0,0 +1,309 @@
+//! Client-side rate limiting
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::{LlmConfig, RateLimitConfig};
+
+use super::{ChatMessage, GenerationOptions, TokenEstimator};
+
+/// Pause applied after a 429 that did not say how long to wait
+const DEFAULT_THROTTLE: Duration = Duration::from_secs(1);
+
+/// Token buckets for requests and tokens per minute, plus a concurrency cap
+///
+/// Clients of the same provider share one limiter, see [`shared_limiter`].
+pub struct RateLimiter {
+    slots: Option<Arc<Semaphore>>,
+    state: Mutex<LimiterState>,
+}
+
+struct LimiterState {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+    paused_until: Option<Instant>,
+}
+
+/// Bucket holding up to one minute's allowance, refilled continuously
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn per_minute(limit: u32, now: Instant) -> Self {
+        Self {
+            capacity: limit as f64,
+            available: limit as f64,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// Time until `amount` is available
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing * 60.0 / self.capacity)
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            slots: config
+                .max_concurrent
+                .map(|n| Arc::new(Semaphore::new(n.max(1) as usize))),
+            state: Mutex::new(LimiterState {
+                requests: config.requests_per_minute.map(|n| Bucket::per_minute(n, now)),
+                tokens: config.tokens_per_minute.map(|n| Bucket::per_minute(n, now)),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait for a concurrency slot and enough budget for a request of about
+    /// `tokens` tokens
+    ///
+    /// The permit keeps its slot until dropped; report the real usage with
+    /// [`RatePermit::set_usage`] so the token bucket is corrected.
+    pub async fn acquire(self: &Arc<Self>, tokens: u32) -> RatePermit {
+        let slot = match &self.slots {
+            Some(slots) => Some(slots.clone().acquire_owned().await.expect("limiter semaphore closed")),
+            None => None,
+        };
+
+        loop {
+            match self.try_take(tokens, Instant::now()) {
+                Ok(()) => break,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+
+        RatePermit {
+            limiter: self.clone(),
+            reserved: tokens,
+            used: None,
+            _slot: slot,
+        }
+    }
+
+    /// Take budget for one request, or return how long to wait for it
+    fn try_take(&self, tokens: u32, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(until) = state.paused_until {
+            if until > now {
+                return Err(until - now);
+            }
+            state.paused_until = None;
+        }
+
+        let mut wait = Duration::ZERO;
+        if let Some(bucket) = &mut state.requests {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_for(1.0));
+        }
+        if let Some(bucket) = &mut state.tokens {
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_for(tokens as f64));
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        if let Some(bucket) = &mut state.requests {
+            bucket.available -= 1.0;
+        }
+        if let Some(bucket) = &mut state.tokens {
+            bucket.available -= (tokens as f64).min(bucket.capacity);
+        }
+        Ok(())
+    }
+
+    /// Back off after the provider answered 429
+    ///
+    /// Holds every request until `retry_after` (one second if unknown) and
+    /// empties the request bucket so traffic resumes gradually.
+    pub fn throttle(&self, retry_after: Option<Duration>) {
+        let until = Instant::now() + retry_after.unwrap_or(DEFAULT_THROTTLE);
+        let mut state = self.state.lock().unwrap();
+        state.paused_until = Some(state.paused_until.map_or(until, |current| current.max(until)));
+        if let Some(bucket) = &mut state.requests {
+            bucket.available = bucket.available.min(0.0);
+        }
+    }
+
+    /// Give back or charge the difference between reserved and used tokens
+    fn settle(&self, reserved: u32, used: u32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(bucket) = &mut state.tokens {
+            let reserved = (reserved as f64).min(bucket.capacity);
+            bucket.available = (bucket.available + reserved - used as f64).min(bucket.capacity);
+        }
+    }
+}
+
+/// Budget taken for one request
+pub struct RatePermit {
+    limiter: Arc<RateLimiter>,
+    reserved: u32,
+    used: Option<u32>,
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+impl RatePermit {
+    /// Tokens the request actually used
+    pub fn set_usage(&mut self, tokens: u32) {
+        self.used = Some(tokens);
+    }
+}
+
+impl Drop for RatePermit {
+    fn drop(&mut self) {
+        // Without reported usage the reservation stands
+        if let Some(used) = self.used {
+            self.limiter.settle(self.reserved, used);
+        }
+    }
+}
+
+/// Tokens to reserve for a request before its usage is known
+///
+/// The prompt counted with the provider's `estimator`, plus the completion
+/// limit.
+pub fn estimate_tokens(estimator: &TokenEstimator, messages: &[ChatMessage], options: &GenerationOptions) -> u32 {
+    estimator
+        .count_messages(messages)
+        .saturating_add(options.max_tokens.unwrap_or(0))
+}
+
+/// Limiter for the provider of `config`, shared by the whole process
+///
+/// Returns `None` when no `[[llm.rate_limits]]` entry names the provider.
+pub fn shared_limiter(config: &LlmConfig) -> Option<Arc<RateLimiter>> {
+    static LIMITERS: OnceLock<Mutex<HashMap<RateLimitConfig, Arc<RateLimiter>>>> = OnceLock::new();
+
+    let limits = config
+        .rate_limits
+        .iter()
+        .find(|l| l.provider.eq_ignore_ascii_case(&config.provider))?;
+    let key = RateLimitConfig {
+        provider: limits.provider.to_lowercase(),
+        ..limits.clone()
+    };
+
+    let mut limiters = LIMITERS.get_or_init(Default::default).lock().unwrap();
+    Some(
+        limiters
+            .entry(key)
+            .or_insert_with_key(|key| Arc::new(RateLimiter::new(key)))
+            .clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(rpm: Option<u32>, tpm: Option<u32>, concurrent: Option<u32>) -> RateLimitConfig {
+        RateLimitConfig {
+            provider: "test".to_string(),
+            requests_per_minute: rpm,
+            tokens_per_minute: tpm,
+            max_concurrent: concurrent,
+        }
+    }
+
+    #[test]
+    fn test_request_bucket_refills() {
+        let limiter = RateLimiter::new(&limits(Some(60), None, None));
+        let start = Instant::now();
+        for _ in 0..60 {
+            limiter.try_take(0, start).unwrap();
+        }
+        let wait = limiter.try_take(0, start).unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+
+        limiter.try_take(0, start + Duration::from_secs(1)).unwrap();
+    }
+
+    #[test]
+    fn test_token_bucket_settles_usage() {
+        let limiter = Arc::new(RateLimiter::new(&limits(None, Some(1_000), None)));
+        let start = Instant::now();
+        limiter.try_take(800, start).unwrap();
+        assert!(limiter.try_take(800, start).is_err());
+
+        // Only 100 of the 800 reserved tokens were used
+        limiter.settle(800, 100);
+        limiter.try_take(800, start).unwrap();
+
+        // Oversized requests wait for a full bucket instead of forever
+        let wait = limiter.try_take(5_000, start).unwrap_err();
+        assert!(wait <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_estimate_uses_provider_tokenizer() {
+        let messages = [ChatMessage::user("林风拔剑而起")];
+        let options = GenerationOptions::new().max_tokens(100);
+        let qwen = estimate_tokens(&TokenEstimator::for_provider("qwen"), &messages, &options);
+        let claude = estimate_tokens(&TokenEstimator::for_provider("anthropic"), &messages, &options);
+        assert_eq!(qwen, 5 + 4 + 100);
+        assert!(claude > qwen);
+    }
+
+    #[test]
+    fn test_throttle_pauses_requests() {
+        let limiter = RateLimiter::new(&limits(None, None, None));
+        limiter.throttle(Some(Duration::from_secs(5)));
+        let wait = limiter.try_take(0, Instant::now()).unwrap_err();
+        assert!(wait > Duration::from_secs(4));
+        limiter.try_take(0, Instant::now() + Duration::from_secs(6)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_cap() {
+        let limiter = Arc::new(RateLimiter::new(&limits(None, None, Some(1))));
+        let first = limiter.acquire(0).await;
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(0).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_shared_per_provider() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "Limited-Test".to_string();
+        assert!(shared_limiter(&config).is_none());
+
+        config.rate_limits.push(RateLimitConfig {
+            provider: "limited-test".to_string(),
+            ..limits(Some(10), None, Some(2))
+        });
+        let a = shared_limiter(&config).unwrap();
+        let b = shared_limiter(&config).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+    }
+}
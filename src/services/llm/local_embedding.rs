@@ -0,0 +1,229 @@
+//! Offline embeddings from hashed character n-grams
+//!
+//! Chinese has no word boundaries, so text is cut into overlapping character
+//! bigrams and trigrams, which catch most names and places. Each n-gram is
+//! hashed into a fixed number of dimensions with a random sign, weighted by
+//! TF-IDF and the vector is L2-normalized. Everything is deterministic; no
+//! model files or network access are needed.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::EmbeddingProvider;
+use crate::text::is_cjk;
+
+/// Default vector size
+pub const LOCAL_EMBEDDING_DIMENSIONS: usize = 512;
+
+/// Embedding provider that runs entirely in-process
+///
+/// Without [`LocalEmbeddingProvider::fit`] every n-gram has the same weight;
+/// fitting on the corpus being searched (e.g. all chapters) down-weights
+/// n-grams that appear everywhere, such as "他的" or "一个".
+#[derive(Debug, Clone)]
+pub struct LocalEmbeddingProvider {
+    dimensions: usize,
+    /// Documents seen by `fit`
+    documents: u32,
+    /// Documents containing each n-gram, by n-gram hash
+    document_frequency: HashMap<u64, u32>,
+}
+
+impl LocalEmbeddingProvider {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+            documents: 0,
+            document_frequency: HashMap::new(),
+        }
+    }
+
+    /// Learn n-gram document frequencies from `corpus` for IDF weighting
+    ///
+    /// Frequencies add up across calls. Vectors built before and after a fit
+    /// are not comparable, so fit before embedding what will be searched.
+    pub fn fit<S: AsRef<str>>(&mut self, corpus: &[S]) {
+        for document in corpus {
+            let mut seen: Vec<u64> = ngrams(document.as_ref()).iter().map(|g| fnv1a(g)).collect();
+            seen.sort_unstable();
+            seen.dedup();
+            for hash in seen {
+                *self.document_frequency.entry(hash).or_insert(0) += 1;
+            }
+            self.documents += 1;
+        }
+    }
+
+    /// Smoothed inverse document frequency; 1.0 for every n-gram before any fit
+    fn idf(&self, hash: u64) -> f32 {
+        if self.documents == 0 {
+            return 1.0;
+        }
+        let df = self.document_frequency.get(&hash).copied().unwrap_or(0);
+        ((1.0 + self.documents as f32) / (1.0 + df as f32)).ln() + 1.0
+    }
+
+    /// Embed one text
+    pub fn vector(&self, text: &str) -> Vec<f32> {
+        let mut counts: HashMap<u64, u32> = HashMap::new();
+        for gram in ngrams(text) {
+            *counts.entry(fnv1a(&gram)).or_insert(0) += 1;
+        }
+
+        let mut vector = vec![0.0f32; self.dimensions];
+        for (hash, count) in counts {
+            // Sublinear term frequency keeps repeated filler from dominating
+            let weight = (1.0 + (count as f32).ln()) * self.idf(hash);
+            let index = (hash % self.dimensions as u64) as usize;
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[index] += sign * weight;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+}
+
+impl Default for LocalEmbeddingProvider {
+    fn default() -> Self {
+        Self::new(LOCAL_EMBEDDING_DIMENSIONS)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalEmbeddingProvider {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.vector(text)).collect())
+    }
+
+    fn fit(&mut self, corpus: &[String]) {
+        LocalEmbeddingProvider::fit(self, corpus)
+    }
+
+    fn batch_size(&self) -> usize {
+        usize::MAX
+    }
+
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn model(&self) -> &str {
+        "hashed-ngram"
+    }
+}
+
+/// Features of a text
+///
+/// Runs of CJK characters yield their bigrams and trigrams (or the single
+/// character of a one-character run); runs of letters and digits yield the
+/// lowercased word. Punctuation and whitespace separate runs.
+fn ngrams(text: &str) -> Vec<String> {
+    let mut features = Vec::new();
+    let mut run: Vec<char> = Vec::new();
+    let mut word = String::new();
+
+    let flush_run = |run: &mut Vec<char>, features: &mut Vec<String>| {
+        if run.len() == 1 {
+            features.push(run[0].to_string());
+        }
+        for n in 2..=3 {
+            for window in run.windows(n) {
+                features.push(window.iter().collect());
+            }
+        }
+        run.clear();
+    };
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                features.push(std::mem::take(&mut word));
+            }
+            run.push(c);
+        } else if c.is_alphanumeric() {
+            flush_run(&mut run, &mut features);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_run(&mut run, &mut features);
+            if !word.is_empty() {
+                features.push(std::mem::take(&mut word));
+            }
+        }
+    }
+    flush_run(&mut run, &mut features);
+    if !word.is_empty() {
+        features.push(word);
+    }
+    features
+}
+
+/// 64-bit FNV-1a, stable across platforms and releases unlike `DefaultHasher`
+///
+/// Finished with the SplitMix64 mixer so both the low bits (the index) and
+/// the top bit (the sign) are well spread for short inputs.
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{SimpleVectorStore, VectorStore};
+
+    #[test]
+    fn test_ngrams() {
+        assert_eq!(ngrams("林风，去了 Qingyun 山。"), vec!["林风", "去了", "qingyun", "山"]);
+        assert_eq!(ngrams("青云宗"), vec!["青云", "云宗", "青云宗"]);
+    }
+
+    #[test]
+    fn test_vectors_are_deterministic_and_normalized() {
+        let provider = LocalEmbeddingProvider::new(64);
+        let a = provider.vector("林风拔剑而起");
+        assert_eq!(a, LocalEmbeddingProvider::new(64).vector("林风拔剑而起"));
+        assert_eq!(a.len(), 64);
+        let norm: f32 = a.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert!(provider.vector("。，！").iter().all(|v| *v == 0.0));
+    }
+
+    /// Chapters mentioning the current chapter's characters and places rank first
+    #[tokio::test]
+    async fn test_ranks_chapters_by_characters_and_locations() {
+        let chapters = [
+            "林风在青云宗的后山练剑，师父苏婉儿站在一旁指点他的剑法。",
+            "城里的集市很热闹，一个卖糖葫芦的老人在街角吆喝，他的声音传得很远。",
+            "萧炎来到了乌坦城，他的父亲正在家族大厅里等他。",
+            "林风回到青云宗，听说魔教的人已经潜入了山门。",
+            "他的心里很乱，一个人坐在窗前，看着外面的雨一直下。",
+        ];
+
+        let mut provider = LocalEmbeddingProvider::default();
+        provider.fit(&chapters);
+
+        let store = SimpleVectorStore::new();
+        let texts: Vec<String> = chapters.iter().map(|c| c.to_string()).collect();
+        for (i, vector) in provider.embed(&texts).await.unwrap().iter().enumerate() {
+            store.add(&format!("chapter-{}", i + 1), vector, chapters[i]).unwrap();
+        }
+
+        let query = provider.embed_one("本章人物：林风。地点：青云宗。").await.unwrap();
+        let results = store.search(&query, 5).unwrap();
+        let top: Vec<&str> = results.iter().take(2).map(|r| r.id.as_str()).collect();
+        assert!(top.contains(&"chapter-1") && top.contains(&"chapter-4"), "ranking: {:?}", top);
+        assert!(results[1].score > results[2].score);
+    }
+}
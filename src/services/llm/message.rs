@@ -0,0 +1,81 @@
+//! Chat messages sent to providers
+
+use serde::{Deserialize, Serialize};
+
+/// Author of a chat message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// A single message in a conversation
+///
+/// Serializes as `{"role": "...", "content": "..."}`, which every supported
+/// provider accepts as-is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self { role, content: content.into() }
+    }
+
+    pub fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    /// An earlier reply, or a prefill the model should continue from
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    /// Messages for a `(context, prompt)` call
+    ///
+    /// The context is sent verbatim as the system message and is left out
+    /// when empty.
+    pub fn from_context(context: &str, prompt: &str) -> Vec<ChatMessage> {
+        let mut messages = Vec::with_capacity(2);
+        if !context.is_empty() {
+            messages.push(ChatMessage::system(context));
+        }
+        messages.push(ChatMessage::user(prompt));
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_context() {
+        let messages = ChatMessage::from_context("背景", "写一章");
+        assert_eq!(messages, vec![ChatMessage::system("背景"), ChatMessage::user("写一章")]);
+
+        let messages = ChatMessage::from_context("", "写一章");
+        assert_eq!(messages, vec![ChatMessage::user("写一章")]);
+
+        let json = serde_json::to_value(ChatMessage::assistant("好")).unwrap();
+        assert_eq!(json, serde_json::json!({ "role": "assistant", "content": "好" }));
+    }
+}
@@ -1,34 +1,471 @@
 //! LLM Client Module
 
+mod anthropic;
+mod budget;
+mod cache;
+mod completion;
+mod embedding;
+mod error;
+mod limiter;
+mod local_embedding;
+mod message;
+mod ollama;
+mod openai_compatible;
+mod options;
+mod replay;
+mod retry;
+mod routing;
+mod sse;
+mod structured;
+mod tokens;
+
+pub use anthropic::AnthropicProvider;
+pub use budget::{FittedPrompt, FittedSection, Overflow, PromptBudget};
+pub use cache::{CachedProvider, ResponseCache};
+pub use completion::{Completion, CompletionStream, EventStream, StreamEvent};
+pub use embedding::{
+    create_embedding_provider, DashScopeEmbeddingProvider, EmbeddingProvider, OpenAiEmbeddingProvider,
+};
+pub use error::LlmError;
+pub use limiter::{estimate_tokens, shared_limiter, RateLimiter, RatePermit};
+pub use local_embedding::{LocalEmbeddingProvider, LOCAL_EMBEDDING_DIMENSIONS};
+pub use message::{ChatMessage, Role};
+pub use ollama::{OllamaModel, OllamaProvider, OLLAMA_DEFAULT_URL};
+pub use openai_compatible::OpenAiCompatibleProvider;
+pub use options::GenerationOptions;
+pub use replay::{Cassette, Interaction, ReplayProvider};
+pub use retry::RetryPolicy;
+pub use routing::{config_for_project, config_for_task, create_client_for_task, LlmTask};
+pub use structured::{extract_json, StructuredCompletion};
+pub use tokens::{context_window, max_output_tokens, TokenEstimator, DEFAULT_CONTEXT_WINDOW, DEFAULT_MAX_OUTPUT_TOKENS};
+pub use tokio_util::sync::CancellationToken;
+
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+use crate::config::{CassetteMode, LlmConfig};
+use crate::models::TokenUsage;
+
 /// LLM client trait
+///
+/// Providers send the messages exactly as given and never add system text
+/// of their own.
 #[async_trait]
 pub trait LlmProvider: Send + Sync {
-    async fn generate(&self, context: &str, prompt: &str) -> Result<String>;
+    /// Generate the next assistant message for a conversation
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion>;
+
+    /// Generate the next assistant message as a stream of events
+    ///
+    /// Providers without native streaming yield the full response as a
+    /// single chunk followed by its usage.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        Ok(self.chat(messages, options).await?.into_events())
+    }
+
+    /// Convenience wrapper sending `context` as the system message
+    async fn generate(
+        &self,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        self.chat(&ChatMessage::from_context(context, prompt), options).await
+    }
+
+    /// Streaming variant of [`LlmProvider::generate`]
+    async fn generate_stream(
+        &self,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        self.chat_stream(&ChatMessage::from_context(context, prompt), options).await
+    }
+
     fn name(&self) -> &str;
+
+    /// Model the provider sends requests to
+    fn model(&self) -> &str;
+
+    /// The part of `options` the provider's API accepts; the rest is not sent
+    fn supported_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        options.clone()
+    }
+
+    /// Response available without sending the request, such as a cache hit
+    ///
+    /// Clients look here before waiting on the rate limiter.
+    fn cached(&self, _messages: &[ChatMessage], _options: &GenerationOptions) -> Option<Completion> {
+        None
+    }
 }
 
 /// LLM client wrapper
+///
+/// Clients can be chained with [`LlmClient::with_fallback`]: when a client
+/// has exhausted its retries, the request moves on to the next one.
 pub struct LlmClient {
     provider: Box<dyn LlmProvider>,
+    retry_policy: RetryPolicy,
+    default_options: GenerationOptions,
+    fallback: Option<Box<LlmClient>>,
+    json_repairs: u32,
+    limiter: Option<Arc<RateLimiter>>,
+    context_window: Option<u32>,
+    max_output_tokens: Option<u32>,
+    cancel: Option<CancellationToken>,
 }
 
 impl LlmClient {
     pub fn new(provider: Box<dyn LlmProvider>) -> Self {
-        Self { provider }
+        Self {
+            provider,
+            retry_policy: RetryPolicy::default(),
+            // Same defaults as LlmConfig
+            default_options: GenerationOptions::new().temperature(0.8).max_tokens(4096),
+            fallback: None,
+            json_repairs: 2,
+            limiter: None,
+            context_window: None,
+            max_output_tokens: None,
+            cancel: None,
+        }
     }
 
-    pub async fn generate(&self, context: &str, prompt: &str) -> Result<String> {
-        self.provider.generate(context, prompt).await
+    /// Set the retry policy used for retryable provider errors
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
     }
 
+    /// Set the options used for fields a request leaves unset
+    pub fn with_default_options(mut self, options: GenerationOptions) -> Self {
+        self.default_options = options;
+        self
+    }
+
+    /// Set how many times a reply that is not valid JSON is sent back for
+    /// repair by [`LlmClient::chat_json`]
+    pub fn with_json_repairs(mut self, repairs: u32) -> Self {
+        self.json_repairs = repairs;
+        self
+    }
+
+    /// Wait on `limiter` before every request to the provider
+    ///
+    /// 429 responses pause the limiter, which holds back every client
+    /// sharing it.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Override the context window known for the model
+    pub fn with_context_window(mut self, tokens: u32) -> Self {
+        self.context_window = Some(tokens);
+        self
+    }
+
+    /// Override the reply limit known for the model
+    pub fn with_max_output_tokens(mut self, tokens: u32) -> Self {
+        self.max_output_tokens = Some(tokens);
+        self
+    }
+
+    /// Abandon requests once `token` is cancelled
+    ///
+    /// Pending and future calls fail with [`LlmError::Cancelled`], including
+    /// retries, backoff waits and streams already being read. Dropping the
+    /// request closes its connection, so the provider stops generating.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Append a client to the end of the fallback chain
+    ///
+    /// While a fallback is available, rate-limited requests move on at once
+    /// instead of waiting out their retries.
+    pub fn with_fallback(mut self, fallback: LlmClient) -> Self {
+        self.fallback = Some(Box::new(match self.fallback.take() {
+            Some(existing) => existing.with_fallback(fallback),
+            None => fallback,
+        }));
+        self
+    }
+
+    /// Options that will actually be sent for a request with `options`
+    pub fn resolve_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        self.provider.supported_options(&options.or(&self.default_options))
+    }
+
+    pub async fn chat(&self, messages: &[ChatMessage]) -> Result<Completion> {
+        self.chat_with(messages, &GenerationOptions::default()).await
+    }
+
+    /// Chat with per-request options
+    pub async fn chat_with(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        self.cancellable(async {
+            let mut client = self;
+            loop {
+                match client.chat_once(messages, options).await {
+                    Ok(mut completion) => {
+                        completion.fill_source(client.name(), client.model());
+                        return Ok(completion);
+                    }
+                    Err(e) => client = client.next_in_chain(e)?,
+                }
+            }
+        })
+        .await
+    }
+
+    /// Start a streaming chat with per-request options
+    ///
+    /// Only establishing the stream is retried; errors after the first
+    /// chunk are passed through to the caller.
+    pub async fn chat_stream_with(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream> {
+        let (stream, provider, model) = self
+            .cancellable(async {
+                let mut client = self;
+                loop {
+                    match client.chat_stream_once(messages, options).await {
+                        Ok(stream) => return Ok((stream, client.name(), client.model())),
+                        Err(e) => client = client.next_in_chain(e)?,
+                    }
+                }
+            })
+            .await?;
+
+        let stream = match &self.cancel {
+            Some(token) => cancellable_stream(stream, token.clone(), provider.to_string()),
+            None => stream,
+        };
+        Ok(CompletionStream::new(stream, provider, model))
+    }
+
+    /// Convenience wrapper sending `context` as the system message
+    pub async fn generate(&self, context: &str, prompt: &str) -> Result<Completion> {
+        self.generate_with(context, prompt, &GenerationOptions::default()).await
+    }
+
+    /// Generate with per-request options
+    pub async fn generate_with(
+        &self,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        self.chat_with(&ChatMessage::from_context(context, prompt), options).await
+    }
+
+    pub async fn generate_stream(&self, context: &str, prompt: &str) -> Result<CompletionStream> {
+        self.generate_stream_with(context, prompt, &GenerationOptions::default()).await
+    }
+
+    /// Start a streaming generation with per-request options
+    pub async fn generate_stream_with(
+        &self,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<CompletionStream> {
+        self.chat_stream_with(&ChatMessage::from_context(context, prompt), options).await
+    }
+
+    /// Run `request` unless the cancellation token fires first
+    async fn cancellable<T>(&self, request: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+        match &self.cancel {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(LlmError::Cancelled { provider: self.name().to_string() }.into()),
+                result = request => result,
+            },
+            None => request.await,
+        }
+    }
+
+    /// One client's attempts at a request, including retries
+    async fn chat_once(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        let options = self.resolve_options(options);
+        if let Some(completion) = self.provider.cached(messages, &options) {
+            return Ok(completion);
+        }
+        let mut attempt = 0;
+        loop {
+            let mut permit = self.acquire(messages, &options).await;
+            let result = self.provider.chat(messages, &options).await;
+            if let Some(permit) = &mut permit {
+                match &result {
+                    Ok(completion) if completion.usage.total() > 0 => permit.set_usage(completion.usage.total()),
+                    Ok(_) => {}
+                    Err(e) => self.release_failed(permit, e),
+                }
+            }
+            drop(permit);
+
+            match result {
+                Ok(completion) => return Ok(completion),
+                Err(e) if self.should_retry(attempt, &e) => {
+                    self.backoff(attempt, &e).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn chat_stream_once(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        let options = self.resolve_options(options);
+        if let Some(completion) = self.provider.cached(messages, &options) {
+            return Ok(completion.into_events());
+        }
+        let mut attempt = 0;
+        loop {
+            let mut permit = self.acquire(messages, &options).await;
+            match self.provider.chat_stream(messages, &options).await {
+                Ok(stream) => return Ok(match permit {
+                    // The slot is held until the stream is dropped
+                    Some(mut permit) => Box::pin(stream.inspect(move |event| {
+                        if let Ok(StreamEvent::Usage(usage)) = event {
+                            permit.set_usage(usage.total());
+                        }
+                    })),
+                    None => stream,
+                }),
+                Err(e) => {
+                    if let Some(permit) = &mut permit {
+                        self.release_failed(permit, &e);
+                    }
+                    drop(permit);
+                    if !self.should_retry(attempt, &e) {
+                        return Err(e);
+                    }
+                    self.backoff(attempt, &e).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Wait for the rate limiter, if any
+    async fn acquire(&self, messages: &[ChatMessage], options: &GenerationOptions) -> Option<RatePermit> {
+        match &self.limiter {
+            Some(limiter) => Some(limiter.acquire(estimate_tokens(&self.token_estimator(), messages, options)).await),
+            None => None,
+        }
+    }
+
+    /// Refund a failed request and pause the limiter if it was a 429
+    fn release_failed(&self, permit: &mut RatePermit, err: &anyhow::Error) {
+        permit.set_usage(0);
+        if let Some(LlmError::RateLimited { retry_after, .. }) = err.downcast_ref::<LlmError>() {
+            if let Some(limiter) = &self.limiter {
+                limiter.throttle(*retry_after);
+            }
+        }
+    }
+
+    fn should_retry(&self, attempt: u32, err: &anyhow::Error) -> bool {
+        let rate_limited = matches!(err.downcast_ref::<LlmError>(), Some(LlmError::RateLimited { .. }));
+        if rate_limited && self.fallback.is_some() {
+            return false;
+        }
+        self.retry_policy.should_retry(attempt, err)
+    }
+
+    /// The fallback to try after `err`, or `err` itself at the end of the chain
+    fn next_in_chain(&self, err: anyhow::Error) -> Result<&LlmClient> {
+        match &self.fallback {
+            Some(fallback) => {
+                tracing::warn!(
+                    "{}/{} failed ({}), falling back to {}/{}",
+                    self.name(),
+                    self.model(),
+                    err,
+                    fallback.name(),
+                    fallback.model()
+                );
+                Ok(fallback)
+            }
+            None => Err(err),
+        }
+    }
+
+    async fn backoff(&self, attempt: u32, err: &anyhow::Error) {
+        let retry_after = err.downcast_ref::<LlmError>().and_then(LlmError::retry_after);
+        let delay = self.retry_policy.delay(attempt, retry_after);
+        tracing::warn!(
+            "{} request failed ({}), retrying in {:?} (attempt {}/{})",
+            self.provider.name(),
+            err,
+            delay,
+            attempt + 1,
+            self.retry_policy.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+
+    /// Name of the primary provider
     pub fn name(&self) -> &str {
         self.provider.name()
     }
+
+    /// Model of the primary provider
+    pub fn model(&self) -> &str {
+        self.provider.model()
+    }
+
+    /// Context window in tokens of the primary model
+    pub fn context_window(&self) -> u32 {
+        self.context_window
+            .unwrap_or_else(|| context_window(self.name(), self.model()))
+    }
+
+    /// Most tokens the primary model can write in one reply
+    ///
+    /// Never more than half the context window, the most a prompt budget
+    /// reserves for the reply.
+    pub fn max_output_tokens(&self) -> u32 {
+        self.max_output_tokens
+            .unwrap_or_else(|| max_output_tokens(self.name(), self.model()))
+            .min(self.context_window() / 2)
+    }
+
+    /// Token estimator matching the primary provider's tokenizer
+    pub fn token_estimator(&self) -> TokenEstimator {
+        TokenEstimator::for_provider(self.name())
+    }
 }
 
 // ============ Qwen Provider ============
@@ -42,23 +479,65 @@ struct QwenRequest {
 
 #[derive(Serialize)]
 struct QwenInput {
-    messages: Vec<QwenMessage>,
+    messages: Vec<ChatMessage>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 struct QwenMessage {
-    role: String,
     content: String,
 }
 
 #[derive(Serialize)]
 struct QwenParameters {
     result_format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    incremental_output: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+/// `response_format` field shared by Qwen and OpenAI-compatible APIs
+#[derive(Serialize)]
+pub(crate) struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+impl ResponseFormat {
+    pub(crate) fn for_options(options: &GenerationOptions) -> Option<Self> {
+        options.json.then_some(ResponseFormat { kind: "json_object" })
+    }
 }
 
 #[derive(Deserialize)]
 struct QwenResponse {
     output: QwenOutput,
+    #[serde(default)]
+    usage: QwenUsage,
+}
+
+#[derive(Deserialize, Default)]
+struct QwenUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+impl From<QwenUsage> for TokenUsage {
+    fn from(usage: QwenUsage) -> Self {
+        TokenUsage::new(usage.input_tokens, usage.output_tokens)
+    }
 }
 
 #[derive(Deserialize)]
@@ -71,6 +550,32 @@ struct QwenChoice {
     message: QwenMessage,
 }
 
+#[derive(Deserialize)]
+struct QwenStreamChunk {
+    output: QwenStreamOutput,
+    usage: Option<QwenUsage>,
+}
+
+#[derive(Deserialize)]
+struct QwenStreamOutput {
+    #[serde(default)]
+    choices: Vec<QwenStreamChoice>,
+    /// "null" until the last chunk
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct QwenStreamChoice {
+    message: QwenStreamMessage,
+}
+
+#[derive(Deserialize)]
+struct QwenStreamMessage {
+    #[serde(default)]
+    content: String,
+}
+
 /// Qwen provider
 pub struct QwenProvider {
     client: Client,
@@ -88,53 +593,110 @@ impl QwenProvider {
             base_url: "https://dashscope.aliyuncs.com/api/v1".to_string(),
         }
     }
+
+    /// Override the DashScope API base URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    fn build_request(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        stream: bool,
+    ) -> QwenRequest {
+        QwenRequest {
+            model: self.model.clone(),
+            input: QwenInput { messages: messages.to_vec() },
+            parameters: QwenParameters {
+                result_format: "message".to_string(),
+                incremental_output: stream.then_some(true),
+                temperature: options.temperature,
+                top_p: options.top_p,
+                max_tokens: options.max_tokens,
+                stop: options.stop.clone(),
+                seed: options.seed,
+                response_format: ResponseFormat::for_options(options),
+            },
+        }
+    }
 }
 
 #[async_trait]
 impl LlmProvider for QwenProvider {
-    async fn generate(&self, context: &str, prompt: &str) -> Result<String> {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
         tracing::info!("Calling Qwen API with model: {}", self.model);
 
         let url = format!("{}/services/aigc/text-generation/generation", self.base_url);
+        let request = self.build_request(messages, options, false);
 
-        let messages = vec![
-            QwenMessage {
-                role: "system".to_string(),
-                content: format!("上下文背景:\n{}\n\n请根据以上上下文生成内容。", context),
-            },
-            QwenMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            },
-        ];
+        let response = self.client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network("qwen", e))
+            .context("Failed to call Qwen API")?;
+        let response = error::check_status("qwen", response).await?;
 
-        let request = QwenRequest {
-            model: self.model.clone(),
-            input: QwenInput { messages },
-            parameters: QwenParameters {
-                result_format: "message".to_string(),
-            },
-        };
+        let api_response: QwenResponse = response.json().await
+            .context("Failed to parse Qwen response")?;
+
+        let usage = api_response.usage.into();
+        api_response.output.choices.into_iter().next()
+            .map(|c| c.message.content)
+            .filter(|content| !content.is_empty())
+            .map(|content| Completion::new(content, usage))
+            .ok_or_else(|| LlmError::empty_response("qwen").into())
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        tracing::info!("Streaming from Qwen API with model: {}", self.model);
+
+        let url = format!("{}/services/aigc/text-generation/generation", self.base_url);
+        let request = self.build_request(messages, options, true);
 
         let response = self.client
             .post(&url)
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
+            .header("X-DashScope-SSE", "enable")
             .json(&request)
             .send()
             .await
+            .map_err(|e| LlmError::network("qwen", e))
             .context("Failed to call Qwen API")?;
+        let response = error::check_status("qwen", response).await?;
 
-        let api_response: QwenResponse = response.json().await?;
-
-        Ok(api_response.output.choices.first()
-            .map(|c| c.message.content.clone())
-            .unwrap_or_else(|| "生成失败".to_string()))
+        Ok(sse::event_stream(response, |data| {
+            let chunk: QwenStreamChunk = serde_json::from_str(data)
+                .context("Failed to parse Qwen stream chunk")?;
+            // Usage in each chunk is cumulative
+            let done = chunk.output.finish_reason.as_deref().is_some_and(|r| r != "null");
+            let text = chunk.output.choices.into_iter().next().map(|c| StreamEvent::Text(c.message.content));
+            let usage = chunk.usage.map(|u| StreamEvent::Usage(u.into()));
+            Ok(text.into_iter().chain(usage).chain(done.then_some(StreamEvent::Done)).collect())
+        }))
     }
 
     fn name(&self) -> &str {
         "qwen"
     }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
 }
 
 // ============ MiniMax Provider ============
@@ -142,15 +704,19 @@ impl LlmProvider for QwenProvider {
 #[derive(Serialize)]
 struct MiniMaxRequest {
     model: String,
-    messages: Vec<MiniMaxMessage>,
-    #[serde(rename = "max_tokens")]
+    messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 struct MiniMaxMessage {
-    role: String,
     content: String,
 }
 
@@ -158,6 +724,22 @@ struct MiniMaxMessage {
 struct MiniMaxResponse {
     base_resp: MiniMaxBaseResp,
     choices: Option<Vec<MiniMaxChoice>>,
+    #[serde(default)]
+    usage: MiniMaxUsage,
+}
+
+#[derive(Deserialize, Default)]
+struct MiniMaxUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+impl From<MiniMaxUsage> for TokenUsage {
+    fn from(usage: MiniMaxUsage) -> Self {
+        TokenUsage::new(usage.prompt_tokens, usage.completion_tokens)
+    }
 }
 
 #[derive(Deserialize)]
@@ -166,6 +748,23 @@ struct MiniMaxBaseResp {
     status_msg: String,
 }
 
+impl MiniMaxBaseResp {
+    /// MiniMax reports most failures with HTTP 200 and a non-zero status code
+    fn check(&self) -> Result<()> {
+        let provider = "minimax".to_string();
+        let message = self.status_msg.clone();
+        let err = match self.status_code {
+            0 => return Ok(()),
+            1002 | 1039 => LlmError::RateLimited { provider, message, retry_after: None },
+            1004 | 1008 => LlmError::Auth { provider, message },
+            1026 | 1027 => LlmError::ContentBlocked { provider, message },
+            1000 | 1001 | 1013 => LlmError::Server { provider, status: self.status_code as u16, message },
+            code => LlmError::InvalidRequest { provider, status: code as u16, message },
+        };
+        Err(err.into())
+    }
+}
+
 #[derive(Deserialize)]
 struct MiniMaxChoice {
     message: MiniMaxMessage,
@@ -174,12 +773,33 @@ struct MiniMaxChoice {
     finish_reason: String,
 }
 
+#[derive(Deserialize)]
+struct MiniMaxStreamChunk {
+    base_resp: Option<MiniMaxBaseResp>,
+    #[serde(default)]
+    choices: Vec<MiniMaxStreamChoice>,
+    usage: Option<MiniMaxUsage>,
+}
+
+#[derive(Deserialize)]
+struct MiniMaxStreamChoice {
+    delta: Option<MiniMaxDelta>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MiniMaxDelta {
+    content: Option<String>,
+}
+
 /// MiniMax provider
 pub struct MiniMaxProvider {
     client: Client,
     api_key: String,
     model: String,
     group_id: String,
+    base_url: String,
 }
 
 impl MiniMaxProvider {
@@ -189,151 +809,200 @@ impl MiniMaxProvider {
             api_key,
             model: model.unwrap_or_else(|| "abab6.5s-chat".to_string()), // MiniMax 2.5
             group_id: group_id.unwrap_or_default(),
+            base_url: "https://api.minimax.chat/v1".to_string(),
+        }
+    }
+
+    /// Override the MiniMax API base URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "{}/text/chatcompletion_v2?GroupId={}",
+            self.base_url, self.group_id
+        )
+    }
+
+    fn build_request(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        stream: bool,
+    ) -> MiniMaxRequest {
+        if !options.stop.is_empty() || options.seed.is_some() {
+            tracing::debug!("MiniMax does not support stop or seed; not sent");
+        }
+        MiniMaxRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stream,
         }
     }
 }
 
 #[async_trait]
 impl LlmProvider for MiniMaxProvider {
-    async fn generate(&self, context: &str, prompt: &str) -> Result<String> {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
         tracing::info!("Calling MiniMax API with model: {}", self.model);
 
-        let url = format!(
-            "https://api.minimax.chat/v1/text/chatcompletion_v2?GroupId={}",
-            self.group_id
-        );
-
-        let system_prompt = format!("你是小说作家，根据以下上下文背景创作小说内容。\n\n上下文背景:\n{}", context);
-
-        let messages = vec![
-            MiniMaxMessage {
-                role: "system".to_string(),
-                content: system_prompt,
-            },
-            MiniMaxMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            },
-        ];
-
-        let request = MiniMaxRequest {
-            model: self.model.clone(),
-            messages,
-            max_tokens: Some(4096),
-            temperature: Some(0.7),
-        };
+        let request = self.build_request(messages, options, false);
 
         let response = self.client
-            .post(&url)
+            .post(self.url())
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
             .json(&request)
             .send()
             .await
+            .map_err(|e| LlmError::network("minimax", e))
             .context("Failed to call MiniMax API")?;
+        let response = error::check_status("minimax", response).await?;
 
         let api_response: MiniMaxResponse = response.json().await
             .context("Failed to parse MiniMax response")?;
 
-        if api_response.base_resp.status_code != 0 {
-            anyhow::bail!("MiniMax API error: {}", api_response.base_resp.status_msg);
-        }
+        api_response.base_resp.check()?;
+
+        let usage = api_response.usage.into();
+        api_response.choices
+            .and_then(|c| c.into_iter().next())
+            .map(|choice| choice.message.content)
+            .filter(|content| !content.is_empty())
+            .map(|content| Completion::new(content, usage))
+            .ok_or_else(|| LlmError::empty_response("minimax").into())
+    }
 
-        Ok(api_response.choices
-            .and_then(|c| c.first().map(|choice| choice.message.content.clone()))
-            .unwrap_or_else(|| "生成失败".to_string()))
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        tracing::info!("Streaming from MiniMax API with model: {}", self.model);
+
+        let request = self.build_request(messages, options, true);
+
+        let response = self.client
+            .post(self.url())
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network("minimax", e))
+            .context("Failed to call MiniMax API")?;
+        let response = error::check_status("minimax", response).await?;
+
+        Ok(sse::event_stream(response, |data| {
+            let chunk: MiniMaxStreamChunk = serde_json::from_str(data)
+                .context("Failed to parse MiniMax stream chunk")?;
+            if let Some(base_resp) = chunk.base_resp {
+                base_resp.check()?;
+            }
+            let choice = chunk.choices.into_iter().next();
+            let done = choice.as_ref().is_some_and(|c| c.finish_reason.is_some());
+            let text = choice
+                .and_then(|c| c.delta)
+                .and_then(|d| d.content)
+                .map(StreamEvent::Text);
+            let usage = chunk.usage.map(|u| StreamEvent::Usage(u.into()));
+            Ok(text.into_iter().chain(usage).chain(done.then_some(StreamEvent::Done)).collect())
+        }))
     }
 
     fn name(&self) -> &str {
         "minimax"
     }
-}
 
-// ============ OpenAI Provider ============
-
-#[derive(Serialize)]
-struct OpenAIRequest {
-    model: String,
-    messages: Vec<OpenAIMessage>,
-    temperature: f32,
-}
-
-#[derive(Serialize, Deserialize)]
-struct OpenAIMessage {
-    role: String,
-    content: String,
-}
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-#[derive(Deserialize)]
-struct OpenAIResponse {
-    choices: Vec<OpenAIChoice>,
+    fn supported_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        GenerationOptions {
+            stop: Vec::new(),
+            seed: None,
+            ..options.clone()
+        }
+    }
 }
 
-#[derive(Deserialize)]
-struct OpenAIChoice {
-    message: OpenAIMessage,
-}
+// ============ OpenAI Provider ============
 
 /// OpenAI provider
 pub struct OpenAiProvider {
-    client: Client,
-    api_key: String,
-    model: String,
+    inner: OpenAiCompatibleProvider,
 }
 
 impl OpenAiProvider {
     pub fn new(api_key: String, model: Option<String>) -> Self {
         Self {
-            client: Client::new(),
-            api_key,
-            model: model.unwrap_or_else(|| "gpt-4o".to_string()),
+            inner: OpenAiCompatibleProvider::new(
+                "https://api.openai.com/v1",
+                Some(api_key),
+                model.unwrap_or_else(|| "gpt-4o".to_string()),
+            )
+            .with_name("openai"),
         }
     }
+
+    /// Override the OpenAI API base URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.inner = self.inner.with_base_url(base_url);
+        self
+    }
 }
 
 #[async_trait]
 impl LlmProvider for OpenAiProvider {
-    async fn generate(&self, context: &str, prompt: &str) -> Result<String> {
-        tracing::info!("Calling OpenAI API with model: {}", self.model);
-
-        let url = "https://api.openai.com/v1/chat/completions";
-
-        let messages = vec![
-            OpenAIMessage {
-                role: "system".to_string(),
-                content: format!("上下文背景:\n{}\n\n请根据以上上下文生成内容。", context),
-            },
-            OpenAIMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            },
-        ];
-
-        let request = OpenAIRequest {
-            model: self.model.clone(),
-            messages,
-            temperature: 0.7,
-        };
-
-        let response = self.client
-            .post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to call OpenAI API")?;
-
-        let api_response: OpenAIResponse = response.json().await?;
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        self.inner.chat(messages, options).await
+    }
 
-        Ok(api_response.choices.first()
-            .map(|c| c.message.content.clone())
-            .unwrap_or_else(|| "生成失败".to_string()))
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        self.inner.chat_stream(messages, options).await
     }
 
     fn name(&self) -> &str {
-        "openai"
+        self.inner.name()
     }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+}
+
+/// `stream`, ending with [`LlmError::Cancelled`] once `token` fires
+fn cancellable_stream(stream: EventStream, token: CancellationToken, provider: String) -> EventStream {
+    Box::pin(futures::stream::unfold(Some(stream), move |stream| {
+        let token = token.clone();
+        let provider = provider.clone();
+        async move {
+            let mut stream = stream?;
+            tokio::select! {
+                biased;
+                _ = token.cancelled() => Some((Err(LlmError::Cancelled { provider }.into()), None)),
+                event = stream.next() => event.map(|event| (event, Some(stream))),
+            }
+        }
+    }))
 }
 
 /// Available LLM providers
@@ -342,64 +1011,508 @@ pub enum LlmProviderType {
     Qwen,
     MiniMax,
     OpenAI,
+    Anthropic,
+    /// Any server speaking the OpenAI chat-completions protocol
+    OpenAICompatible,
+    /// Ollama's native API
+    Ollama,
 }
 
 impl LlmProviderType {
-    pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
+    /// Parse a provider name from the config; unknown names are an error
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
             "qwen" | "tongyi" | "aliyun" => LlmProviderType::Qwen,
             "minimax" => LlmProviderType::MiniMax,
             "openai" | "gpt" => LlmProviderType::OpenAI,
-            _ => LlmProviderType::Qwen,
+            "anthropic" | "claude" => LlmProviderType::Anthropic,
+            "openai_compatible" | "openai-compatible" | "local" => LlmProviderType::OpenAICompatible,
+            "ollama" => LlmProviderType::Ollama,
+            _ => anyhow::bail!(
+                "Unknown LLM provider '{}' (expected qwen, minimax, openai, anthropic, openai_compatible or ollama)",
+                s
+            ),
+        })
+    }
+
+    /// Whether requests cannot be made without an API key
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(self, LlmProviderType::OpenAICompatible | LlmProviderType::Ollama)
+    }
+}
+
+/// Whether the config has what its provider needs to make requests
+pub fn is_configured(config: &LlmConfig) -> bool {
+    if matches!(&config.cassette, Some(c) if c.mode == CassetteMode::Replay) {
+        return true;
+    }
+    match LlmProviderType::from_str(&config.provider) {
+        Ok(LlmProviderType::OpenAICompatible) => {
+            config.base_url.as_deref().is_some_and(|url| !url.is_empty())
         }
+        Ok(provider) => !provider.requires_api_key() || !config.api_key.is_empty(),
+        Err(_) => false,
     }
 }
 
 /// Create LLM client from config
-pub fn create_client(provider: &str, api_key: &str) -> LlmClient {
-    match LlmProviderType::from_str(provider) {
-        LlmProviderType::MiniMax => {
-            LlmClient::new(Box::new(MiniMaxProvider::new(api_key.to_string(), None, None)))
+pub fn create_client(provider: &str, api_key: &str) -> Result<LlmClient> {
+    let mut config = crate::config::Config::default().llm;
+    config.provider = provider.to_string();
+    config.api_key = api_key.to_string();
+    create_client_with_config(&config)
+}
+
+/// Create LLM client with full config
+///
+/// Applies the configured model, base URL, sampling defaults and retry
+/// policy, and chains the configured fallbacks behind the primary provider.
+/// With a replay cassette configured no real provider is built.
+pub fn create_client_with_config(config: &LlmConfig) -> Result<LlmClient> {
+    if let Some(cassette) = config.cassette.as_ref().filter(|c| c.mode == CassetteMode::Replay) {
+        return client_for(config, Box::new(ReplayProvider::replay(&cassette.path)?));
+    }
+
+    let mut client = client_for(config, create_provider(config)?)?;
+    let mut chain = vec![(config.provider.to_lowercase(), config.model.clone())];
+    for endpoint in &config.fallbacks {
+        let fallback = config.with_endpoint(endpoint);
+        let key = (fallback.provider.to_lowercase(), fallback.model.clone());
+        if chain.contains(&key) {
+            continue;
         }
-        LlmProviderType::OpenAI => {
-            LlmClient::new(Box::new(OpenAiProvider::new(api_key.to_string(), None)))
+        LlmProviderType::from_str(&fallback.provider)?;
+        if !is_configured(&fallback) {
+            tracing::warn!("Skipping fallback provider {}: not configured", fallback.provider);
+            continue;
         }
-        LlmProviderType::Qwen => {
-            LlmClient::new(Box::new(QwenProvider::new(api_key.to_string(), None)))
+        client = client.with_fallback(client_for(&fallback, create_provider(&fallback)?)?);
+        chain.push(key);
+    }
+
+    Ok(client)
+}
+
+/// Client for a single provider, with the response cache and cassette
+/// recording applied if configured
+fn client_for(config: &LlmConfig, provider: Box<dyn LlmProvider>) -> Result<LlmClient> {
+    let replaying = config.cassette.as_ref().is_some_and(|c| c.mode == CassetteMode::Replay);
+    let provider: Box<dyn LlmProvider> = if config.cache.enabled && !replaying {
+        Box::new(CachedProvider::new(provider, ResponseCache::from_config(&config.cache)))
+    } else {
+        provider
+    };
+    let provider: Box<dyn LlmProvider> = match &config.cassette {
+        Some(cassette) if cassette.mode == CassetteMode::Record => {
+            Box::new(ReplayProvider::record(provider, &cassette.path)?)
         }
+        _ => provider,
+    };
+
+    let mut client = LlmClient::new(provider)
+        .with_default_options(GenerationOptions::from(config))
+        .with_retry_policy(RetryPolicy::from(&config.retry));
+    if let Some(limiter) = shared_limiter(config).filter(|_| !replaying) {
+        client = client.with_rate_limiter(limiter);
+    }
+    if let Some(window) = config.context_window {
+        client = client.with_context_window(window);
     }
+    if let Some(limit) = config.max_output_tokens {
+        client = client.with_max_output_tokens(limit);
+    }
+    Ok(client)
 }
 
-/// Create LLM client with full config
-pub fn create_client_with_config(
-    provider: &str,
-    api_key: &str,
-    model: Option<String>,
-    group_id: Option<String>,
-) -> LlmClient {
-    match LlmProviderType::from_str(provider) {
+/// Ollama provider for `config`, also used to list models outside a client
+pub fn ollama_provider(config: &LlmConfig) -> OllamaProvider {
+    let model = config.model.clone().filter(|m| !m.is_empty());
+    let provider = OllamaProvider::new(model);
+    let provider = match config.base_url.as_deref().filter(|url| !url.is_empty()) {
+        Some(url) => provider.with_base_url(url),
+        None => provider,
+    };
+    // Load the model with the window prompts are fitted to
+    let window = config.context_window.unwrap_or_else(|| context_window("ollama", provider.model()));
+    let provider = provider.with_num_ctx(window);
+    match &config.keep_alive {
+        Some(keep_alive) => provider.with_keep_alive(keep_alive),
+        None => provider,
+    }
+}
+
+fn create_provider(config: &LlmConfig) -> Result<Box<dyn LlmProvider>> {
+    let api_key = config.api_key.clone();
+    let model = config.model.clone().filter(|m| !m.is_empty());
+    let base_url = config.base_url.as_deref().filter(|url| !url.is_empty());
+
+    let provider: Box<dyn LlmProvider> = match LlmProviderType::from_str(&config.provider)? {
         LlmProviderType::MiniMax => {
-            LlmClient::new(Box::new(MiniMaxProvider::new(api_key.to_string(), model, group_id)))
+            let provider = MiniMaxProvider::new(api_key, model, config.group_id.clone());
+            Box::new(match base_url {
+                Some(url) => provider.with_base_url(url),
+                None => provider,
+            })
         }
         LlmProviderType::OpenAI => {
-            LlmClient::new(Box::new(OpenAiProvider::new(api_key.to_string(), model)))
+            let provider = OpenAiProvider::new(api_key, model);
+            Box::new(match base_url {
+                Some(url) => provider.with_base_url(url),
+                None => provider,
+            })
+        }
+        LlmProviderType::Anthropic => {
+            let provider = AnthropicProvider::new(api_key, model);
+            Box::new(match base_url {
+                Some(url) => provider.with_base_url(url),
+                None => provider,
+            })
         }
         LlmProviderType::Qwen => {
-            LlmClient::new(Box::new(QwenProvider::new(api_key.to_string(), model)))
+            let provider = QwenProvider::new(api_key, model);
+            Box::new(match base_url {
+                Some(url) => provider.with_base_url(url),
+                None => provider,
+            })
         }
-    }
+        LlmProviderType::OpenAICompatible => {
+            let base_url = base_url
+                .context("llm.base_url is required for the openai_compatible provider")?;
+            let model = model
+                .context("llm.model is required for the openai_compatible provider")?;
+            Box::new(OpenAiCompatibleProvider::new(base_url, Some(api_key), model))
+        }
+        LlmProviderType::Ollama => Box::new(ollama_provider(config)),
+    };
+
+    Ok(provider)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    /// Provider that fails with a server error a fixed number of times
+    struct FlakyProvider {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl LlmProvider for FlakyProvider {
+        async fn chat(
+            &self,
+            _messages: &[ChatMessage],
+            _options: &GenerationOptions,
+        ) -> Result<Completion> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                return Err(LlmError::Server {
+                    provider: "flaky".to_string(),
+                    status: 503,
+                    message: "unavailable".to_string(),
+                }.into());
+            }
+            Ok(Completion::new("ok", TokenUsage::new(3, 1)))
+        }
+
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn model(&self) -> &str {
+            "flaky-1"
+        }
+    }
+
+    /// Provider that is always rate limited, counting its calls
+    struct RateLimitedProvider {
+        calls: std::sync::Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for RateLimitedProvider {
+        async fn chat(
+            &self,
+            _messages: &[ChatMessage],
+            _options: &GenerationOptions,
+        ) -> Result<Completion> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(LlmError::RateLimited {
+                provider: "limited".to_string(),
+                message: "slow down".to_string(),
+                retry_after: None,
+            }.into())
+        }
+
+        fn name(&self) -> &str {
+            "limited"
+        }
+
+        fn model(&self) -> &str {
+            "limited-1"
+        }
+    }
+
+    /// Provider whose replies never finish: chat hangs, streams stall
+    /// after their first chunk
+    struct HangingProvider;
+
+    #[async_trait]
+    impl LlmProvider for HangingProvider {
+        async fn chat(
+            &self,
+            _messages: &[ChatMessage],
+            _options: &GenerationOptions,
+        ) -> Result<Completion> {
+            futures::future::pending().await
+        }
+
+        async fn chat_stream(
+            &self,
+            _messages: &[ChatMessage],
+            _options: &GenerationOptions,
+        ) -> Result<EventStream> {
+            let first = futures::stream::iter([Ok(StreamEvent::Text("第一段".to_string()))]);
+            Ok(Box::pin(first.chain(futures::stream::pending())))
+        }
+
+        fn name(&self) -> &str {
+            "hanging"
+        }
+
+        fn model(&self) -> &str {
+            "hanging-1"
+        }
+    }
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            multiplier: 2.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_retries_server_errors() {
+        let client = LlmClient::new(Box::new(FlakyProvider { failures: 2, calls: AtomicU32::new(0) }))
+            .with_retry_policy(fast_policy(3));
+
+        assert_eq!(client.generate("", "").await.unwrap().text, "ok");
+    }
+
+    #[tokio::test]
+    async fn test_client_gives_up_after_max_retries() {
+        let client = LlmClient::new(Box::new(FlakyProvider { failures: 5, calls: AtomicU32::new(0) }))
+            .with_retry_policy(fast_policy(1));
+
+        let err = client.generate("", "").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<LlmError>(), Some(LlmError::Server { status: 503, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_after_retries_exhausted() {
+        let client = LlmClient::new(Box::new(FlakyProvider { failures: 5, calls: AtomicU32::new(0) }))
+            .with_retry_policy(fast_policy(1))
+            .with_fallback(LlmClient::new(Box::new(FlakyProvider { failures: 5, calls: AtomicU32::new(0) }))
+                .with_retry_policy(RetryPolicy::none()))
+            .with_fallback(LlmClient::new(Box::new(FlakyProvider { failures: 0, calls: AtomicU32::new(0) })));
+
+        let completion = client.chat(&[ChatMessage::user("hi")]).await.unwrap();
+        assert_eq!(completion.text, "ok");
+        assert_eq!(completion.provider, "flaky");
+        assert_eq!(completion.usage, TokenUsage::new(3, 1));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_falls_back_without_retrying() {
+        let calls = std::sync::Arc::new(AtomicU32::new(0));
+        let client = LlmClient::new(Box::new(RateLimitedProvider { calls: calls.clone() }))
+            .with_retry_policy(fast_policy(3))
+            .with_fallback(LlmClient::new(Box::new(FlakyProvider { failures: 0, calls: AtomicU32::new(0) })));
+
+        let completion = client.generate("", "hi").await.unwrap();
+        assert_eq!(completion.provider, "flaky");
+        assert_eq!(completion.model, "flaky-1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Without a fallback the rate limit is retried as usual
+        let calls = std::sync::Arc::new(AtomicU32::new(0));
+        let client = LlmClient::new(Box::new(RateLimitedProvider { calls: calls.clone() }))
+            .with_retry_policy(fast_policy(2));
+        assert!(client.generate("", "hi").await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_pauses_shared_limiter() {
+        let limiter = Arc::new(RateLimiter::new(&crate::config::RateLimitConfig {
+            provider: "limited".to_string(),
+            requests_per_minute: None,
+            tokens_per_minute: Some(100_000),
+            max_concurrent: Some(2),
+        }));
+        let client = LlmClient::new(Box::new(RateLimitedProvider { calls: Arc::new(AtomicU32::new(0)) }))
+            .with_retry_policy(RetryPolicy::none())
+            .with_rate_limiter(limiter.clone());
+        assert!(client.generate("", "hi").await.is_err());
+
+        // Other holders of the limiter now wait out the 429
+        let waited = tokio::time::timeout(Duration::from_millis(100), limiter.acquire(1)).await;
+        assert!(waited.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cache_hits_skip_the_limiter() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path(), Duration::from_secs(3600), 1024 * 1024);
+        let provider = FlakyProvider { failures: 0, calls: AtomicU32::new(0) };
+        let limiter = Arc::new(RateLimiter::new(&crate::config::RateLimitConfig {
+            provider: "flaky".to_string(),
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+            max_concurrent: None,
+        }));
+        let client = LlmClient::new(Box::new(CachedProvider::new(Box::new(provider), cache)))
+            .with_rate_limiter(limiter);
+        client.generate("", "hi").await.unwrap();
+
+        // The only request of the minute is spent
+        let hit = tokio::time::timeout(Duration::from_millis(100), client.generate("", "hi")).await;
+        assert_eq!(hit.expect("cache hit waited on the limiter").unwrap().text, "ok");
+        let stream = tokio::time::timeout(Duration::from_millis(100), client.generate_stream("", "hi")).await;
+        assert!(stream.is_ok(), "cached stream waited on the limiter");
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_aborts_requests_and_streams() {
+        let token = CancellationToken::new();
+        let client = LlmClient::new(Box::new(HangingProvider)).with_cancellation(token.clone());
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+
+        let err = tokio::time::timeout(Duration::from_secs(5), client.generate("", "hi"))
+            .await
+            .expect("request was not cancelled")
+            .unwrap_err();
+        assert!(LlmError::is_cancelled(&err));
+
+        // Streams end with the error once cancelled mid-way
+        let token = CancellationToken::new();
+        let client = LlmClient::new(Box::new(HangingProvider)).with_cancellation(token.clone());
+        let mut stream = client.generate_stream("", "hi").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "第一段");
+        token.cancel();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(LlmError::is_cancelled(&err));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[test]
+    fn test_fallbacks_from_config() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "minimax".to_string();
+        config.api_key = "minimax-key".to_string();
+        config.fallbacks = vec![
+            crate::config::EndpointConfig {
+                provider: Some("qwen".to_string()),
+                api_key: Some("qwen-key".to_string()),
+                ..Default::default()
+            },
+            // Unconfigured entries are skipped
+            crate::config::EndpointConfig {
+                provider: Some("openai".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let client = create_client_with_config(&config).unwrap();
+        assert_eq!(client.name(), "minimax");
+        let fallback = client.fallback.as_ref().unwrap();
+        assert_eq!(fallback.name(), "qwen");
+        assert!(fallback.fallback.is_none());
+    }
+
+    #[test]
+    fn test_client_resolves_config_defaults() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "openai".to_string();
+        config.temperature = 0.5;
+        config.max_tokens = 2048;
+
+        let client = create_client_with_config(&config).unwrap();
+        let sent = client.resolve_options(&GenerationOptions::new().max_tokens(100));
+        assert_eq!(client.model(), "gpt-4o");
+        assert_eq!(sent.temperature, Some(0.5));
+        assert_eq!(sent.max_tokens, Some(100));
+    }
+
+    #[test]
+    fn test_unsupported_options_are_not_reported_as_sent() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "minimax".to_string();
+
+        let client = create_client_with_config(&config).unwrap();
+        let options = GenerationOptions::new().temperature(0.3).stop(["###"]).seed(7);
+        let sent = client.resolve_options(&options);
+        assert_eq!(sent.temperature, Some(0.3));
+        assert!(sent.stop.is_empty() && sent.seed.is_none());
+
+        let request = serde_json::to_value(MiniMaxProvider::new("key".to_string(), None, None).build_request(&[], &options, false)).unwrap();
+        assert!(request.get("stop").is_none() && request.get("seed").is_none());
+    }
 
     #[test]
     fn test_provider_type_from_str() {
-        assert_eq!(LlmProviderType::from_str("minimax"), LlmProviderType::MiniMax);
-        assert_eq!(LlmProviderType::from_str("MiniMax"), LlmProviderType::MiniMax);
-        assert_eq!(LlmProviderType::from_str("qwen"), LlmProviderType::Qwen);
-        assert_eq!(LlmProviderType::from_str("openai"), LlmProviderType::OpenAI);
-        assert_eq!(LlmProviderType::from_str("unknown"), LlmProviderType::Qwen);
+        assert_eq!(LlmProviderType::from_str("minimax").unwrap(), LlmProviderType::MiniMax);
+        assert_eq!(LlmProviderType::from_str("MiniMax").unwrap(), LlmProviderType::MiniMax);
+        assert_eq!(LlmProviderType::from_str("qwen").unwrap(), LlmProviderType::Qwen);
+        assert_eq!(LlmProviderType::from_str("openai").unwrap(), LlmProviderType::OpenAI);
+        assert_eq!(LlmProviderType::from_str("anthropic").unwrap(), LlmProviderType::Anthropic);
+        assert_eq!(LlmProviderType::from_str("openai_compatible").unwrap(), LlmProviderType::OpenAICompatible);
+        assert!(LlmProviderType::from_str("unknown").is_err());
+    }
+
+    #[test]
+    fn test_unknown_provider_is_config_error() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "qwne".to_string();
+        config.api_key = "key".to_string();
+        assert!(!is_configured(&config));
+        let err = create_client_with_config(&config).err().unwrap();
+        assert!(err.to_string().contains("Unknown LLM provider 'qwne'"));
+
+        config.provider = "qwen".to_string();
+        config.fallbacks = vec![crate::config::EndpointConfig {
+            provider: Some("nope".to_string()),
+            ..Default::default()
+        }];
+        assert!(create_client_with_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_openai_compatible_requires_base_url_and_model() {
+        let mut config = crate::config::Config::default().llm;
+        config.provider = "openai_compatible".to_string();
+        config.api_key = String::new();
+        assert!(!is_configured(&config));
+        assert!(create_client_with_config(&config).is_err());
+
+        config.base_url = Some("http://127.0.0.1:8080/v1".to_string());
+        assert!(is_configured(&config));
+        assert!(create_client_with_config(&config).is_err());
+
+        config.model = Some("llama-3-8b".to_string());
+        let client = create_client_with_config(&config).unwrap();
+        assert_eq!(client.name(), "openai_compatible");
+        assert_eq!(client.model(), "llama-3-8b");
     }
 }
@@ -0,0 +1,365 @@
+//! Native Ollama API
+//!
+//! Uses `/api/chat` for conversations, `/api/generate` for single prompts and
+//! `/api/tags` to list installed models. Streams are newline-delimited JSON.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error, sse, ChatMessage, Completion, EventStream, GenerationOptions, LlmError, LlmProvider,
+    StreamEvent,
+};
+use crate::models::TokenUsage;
+
+/// Default address of a local Ollama server
+pub const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+
+const OLLAMA_DEFAULT_MODEL: &str = "qwen2.5:7b";
+
+#[derive(Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    #[serde(skip_serializing_if = "OllamaOptions::is_empty")]
+    options: OllamaOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+}
+
+#[derive(Serialize)]
+struct OllamaGenerateRequest {
+    model: String,
+    prompt: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    system: String,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    #[serde(skip_serializing_if = "OllamaOptions::is_empty")]
+    options: OllamaOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<String>,
+}
+
+/// Sampling options, which Ollama takes in a nested object
+#[derive(Serialize, Default)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
+}
+
+impl OllamaOptions {
+    fn is_empty(&self) -> bool {
+        self.num_ctx.is_none()
+            && self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.num_predict.is_none()
+            && self.stop.is_empty()
+            && self.seed.is_none()
+    }
+}
+
+impl From<&GenerationOptions> for OllamaOptions {
+    fn from(options: &GenerationOptions) -> Self {
+        Self {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            num_predict: options.max_tokens,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            num_ctx: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+}
+
+/// Response of `/api/chat` and `/api/generate`, or one line of their streams
+#[derive(Deserialize)]
+struct OllamaResponse {
+    /// Set by `/api/chat`
+    #[serde(default)]
+    message: OllamaMessage,
+    /// Set by `/api/generate`
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+    /// Failure reported in place of a reply, e.g. when the model runner
+    /// crashes after the response has started
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl OllamaResponse {
+    /// The failure the server reported in the body, if any
+    fn error(&self) -> Option<LlmError> {
+        self.error
+            .as_deref()
+            .map(|message| LlmError::from_status("ollama", StatusCode::INTERNAL_SERVER_ERROR, message, None))
+    }
+
+    fn into_text(self) -> String {
+        if self.message.content.is_empty() {
+            self.response
+        } else {
+            self.message.content
+        }
+    }
+
+    fn usage(&self) -> TokenUsage {
+        TokenUsage::new(self.prompt_eval_count, self.eval_count)
+    }
+
+    /// Text and, on the final line, usage and end of a stream line
+    fn into_events(self) -> Vec<StreamEvent> {
+        let end = match self.done {
+            true => vec![StreamEvent::Usage(self.usage()), StreamEvent::Done],
+            false => Vec::new(),
+        };
+        std::iter::once(StreamEvent::Text(self.into_text())).chain(end).collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct OllamaTags {
+    #[serde(default)]
+    models: Vec<OllamaModel>,
+}
+
+/// A model installed on an Ollama server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OllamaModel {
+    /// Name to pass as the model, e.g. `qwen2.5:7b`
+    pub name: String,
+
+    /// Size on disk in bytes
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// Provider for a local or remote Ollama server
+pub struct OllamaProvider {
+    client: Client,
+    base_url: String,
+    model: String,
+    keep_alive: Option<String>,
+    num_ctx: Option<u32>,
+}
+
+impl OllamaProvider {
+    pub fn new(model: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: OLLAMA_DEFAULT_URL.to_string(),
+            model: model.unwrap_or_else(|| OLLAMA_DEFAULT_MODEL.to_string()),
+            keep_alive: None,
+            num_ctx: None,
+        }
+    }
+
+    /// Override the server address
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// How long the server keeps the model loaded after a request, e.g.
+    /// `"10m"`, `"0"` to unload at once or `"-1"` to keep it forever
+    pub fn with_keep_alive(mut self, keep_alive: impl Into<String>) -> Self {
+        self.keep_alive = Some(keep_alive.into());
+        self
+    }
+
+    /// Context window in tokens the server loads the model with; without
+    /// it the server's own `num_ctx` default applies
+    pub fn with_num_ctx(mut self, tokens: u32) -> Self {
+        self.num_ctx = Some(tokens);
+        self
+    }
+
+    /// Models installed on the server
+    pub async fn list_models(&self) -> Result<Vec<OllamaModel>> {
+        let response = self.client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .map_err(|e| LlmError::network("ollama", e))
+            .context("Failed to list Ollama models")?;
+        let response = error::check_status("ollama", response).await?;
+
+        let tags: OllamaTags = response.json().await
+            .context("Failed to parse Ollama model list")?;
+        Ok(tags.models)
+    }
+
+    fn chat_request(&self, messages: &[ChatMessage], options: &GenerationOptions, stream: bool) -> OllamaChatRequest {
+        OllamaChatRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            stream,
+            format: options.json.then_some("json"),
+            options: self.options(options),
+            keep_alive: self.keep_alive.clone(),
+        }
+    }
+
+    fn generate_request(&self, context: &str, prompt: &str, options: &GenerationOptions, stream: bool) -> OllamaGenerateRequest {
+        OllamaGenerateRequest {
+            model: self.model.clone(),
+            prompt: prompt.to_string(),
+            system: context.to_string(),
+            stream,
+            format: options.json.then_some("json"),
+            options: self.options(options),
+            keep_alive: self.keep_alive.clone(),
+        }
+    }
+
+    fn options(&self, options: &GenerationOptions) -> OllamaOptions {
+        OllamaOptions {
+            num_ctx: self.num_ctx,
+            ..options.into()
+        }
+    }
+
+    async fn post<T: Serialize>(&self, endpoint: &str, request: &T) -> Result<reqwest::Response> {
+        let response = self.client
+            .post(format!("{}/api/{}", self.base_url, endpoint))
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network("ollama", e))
+            .context("Failed to call Ollama API")?;
+        error::check_status("ollama", response).await
+    }
+
+    async fn complete<T: Serialize>(&self, endpoint: &str, request: &T) -> Result<Completion> {
+        let response: OllamaResponse = self.post(endpoint, request).await?
+            .json()
+            .await
+            .context("Failed to parse Ollama response")?;
+        if let Some(err) = response.error() {
+            return Err(err.into());
+        }
+
+        let usage = response.usage();
+        let text = response.into_text();
+        if text.is_empty() {
+            return Err(LlmError::empty_response("ollama").into());
+        }
+        Ok(Completion::new(text, usage))
+    }
+
+    async fn stream<T: Serialize>(&self, endpoint: &str, request: &T) -> Result<EventStream> {
+        let response = self.post(endpoint, request).await?;
+        Ok(sse::ndjson_stream(response, |line| {
+            let chunk: OllamaResponse = serde_json::from_str(line)
+                .context("Failed to parse Ollama stream chunk")?;
+            if let Some(err) = chunk.error() {
+                return Err(err.into());
+            }
+            Ok(chunk.into_events())
+        }))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        tracing::info!("Calling Ollama chat with model: {}", self.model);
+        self.complete("chat", &self.chat_request(messages, options, false)).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        tracing::info!("Streaming Ollama chat with model: {}", self.model);
+        self.stream("chat", &self.chat_request(messages, options, true)).await
+    }
+
+    /// Sends `context` as the system prompt of `/api/generate`
+    async fn generate(
+        &self,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        tracing::info!("Calling Ollama generate with model: {}", self.model);
+        self.complete("generate", &self.generate_request(context, prompt, options, false)).await
+    }
+
+    async fn generate_stream(
+        &self,
+        context: &str,
+        prompt: &str,
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        tracing::info!("Streaming Ollama generate with model: {}", self.model);
+        self.stream("generate", &self.generate_request(context, prompt, options, true)).await
+    }
+
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_nests_options() {
+        let provider = OllamaProvider::new(Some("llama3".to_string())).with_keep_alive("10m").with_num_ctx(8192);
+        let options = GenerationOptions::new().temperature(0.3).max_tokens(128).json(true);
+
+        let json = serde_json::to_value(provider.chat_request(&[ChatMessage::user("hi")], &options, false)).unwrap();
+        assert_eq!(json["model"], "llama3");
+        assert_eq!(json["stream"], false);
+        assert_eq!(json["format"], "json");
+        assert_eq!(json["keep_alive"], "10m");
+        assert_eq!(json["options"]["num_predict"], 128);
+        assert_eq!(json["options"]["num_ctx"], 8192);
+        assert!(json.get("max_tokens").is_none());
+
+        let provider = OllamaProvider::new(None);
+        let json = serde_json::to_value(provider.generate_request("", "hi", &GenerationOptions::default(), true)).unwrap();
+        assert!(json.get("system").is_none());
+        assert!(json.get("options").is_none());
+        assert!(json.get("format").is_none());
+    }
+}
@@ -0,0 +1,265 @@
+//! OpenAI chat-completions protocol
+//!
+//! Shared by the hosted OpenAI API and any local server speaking the same
+//! protocol (vLLM, llama.cpp server, LM Studio).
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error, sse, ChatMessage, Completion, EventStream, GenerationOptions, LlmError, LlmProvider,
+    ResponseFormat, StreamEvent,
+};
+use crate::models::TokenUsage;
+
+#[derive(Serialize)]
+struct OpenAIRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OpenAIStreamOptions>,
+}
+
+#[derive(Serialize)]
+struct OpenAIStreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize)]
+struct OpenAIMessage {
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct OpenAIResponse {
+    choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    usage: OpenAIUsage,
+}
+
+#[derive(Deserialize, Default)]
+struct OpenAIUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+impl From<OpenAIUsage> for TokenUsage {
+    fn from(usage: OpenAIUsage) -> Self {
+        TokenUsage::new(usage.prompt_tokens, usage.completion_tokens)
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAIChoice {
+    message: OpenAIMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAIStreamChoice>,
+    /// Only sent on the final chunk, when usage was requested
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIDelta {
+    content: Option<String>,
+}
+
+/// Provider for any OpenAI-compatible chat-completions endpoint
+pub struct OpenAiCompatibleProvider {
+    client: Client,
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiCompatibleProvider {
+    /// `base_url` is the API root, e.g. `http://localhost:8000/v1`
+    pub fn new(base_url: impl Into<String>, api_key: Option<String>, model: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            name: "openai_compatible".to_string(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|key| !key.is_empty()),
+            model: model.into(),
+        }
+    }
+
+    /// Name reported in logs and errors
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Override the API base URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    fn url(&self) -> String {
+        format!("{}/chat/completions", self.base_url)
+    }
+
+    fn post(&self) -> RequestBuilder {
+        let request = self.client
+            .post(self.url())
+            .header("Content-Type", "application/json");
+        match &self.api_key {
+            Some(key) => request.header("Authorization", format!("Bearer {}", key)),
+            None => request,
+        }
+    }
+
+    fn build_request(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+        stream: bool,
+    ) -> OpenAIRequest {
+        OpenAIRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            response_format: ResponseFormat::for_options(options),
+            stream,
+            stream_options: stream.then_some(OpenAIStreamOptions { include_usage: true }),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiCompatibleProvider {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        tracing::info!("Calling {} API with model: {}", self.name, self.model);
+
+        let request = self.build_request(messages, options, false);
+
+        let response = self.post()
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network(&self.name, e))
+            .with_context(|| format!("Failed to call {} API", self.name))?;
+        let response = error::check_status(&self.name, response).await?;
+
+        let api_response: OpenAIResponse = response.json().await
+            .with_context(|| format!("Failed to parse {} response", self.name))?;
+
+        let choice = api_response.choices.into_iter().next()
+            .ok_or_else(|| LlmError::empty_response(&self.name))?;
+        if choice.finish_reason.as_deref() == Some("content_filter") {
+            return Err(LlmError::ContentBlocked {
+                provider: self.name.clone(),
+                message: "response stopped by content filter".to_string(),
+            }.into());
+        }
+        if choice.message.content.is_empty() {
+            return Err(LlmError::empty_response(&self.name).into());
+        }
+
+        Ok(Completion::new(choice.message.content, api_response.usage.into()))
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<EventStream> {
+        tracing::info!("Streaming from {} API with model: {}", self.name, self.model);
+
+        let request = self.build_request(messages, options, true);
+
+        let response = self.post()
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LlmError::network(&self.name, e))
+            .with_context(|| format!("Failed to call {} API", self.name))?;
+        let response = error::check_status(&self.name, response).await?;
+
+        let name = self.name.clone();
+        Ok(sse::event_stream(response, move |data| {
+            let chunk: OpenAIStreamChunk = serde_json::from_str(data)
+                .with_context(|| format!("Failed to parse {} stream chunk", name))?;
+            // Usage follows the finish reason in a chunk of its own
+            let choice = chunk.choices.into_iter().next();
+            let done = choice.as_ref().is_some_and(|c| c.finish_reason.is_some());
+            let text = choice.and_then(|c| c.delta.content).map(StreamEvent::Text);
+            let usage = chunk.usage.map(|u| StreamEvent::Usage(u.into()));
+            Ok(text.into_iter().chain(usage).chain(done.then_some(StreamEvent::Done)).collect())
+        }))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_sends_options() {
+        let provider = OpenAiCompatibleProvider::new("http://localhost:8000/v1/", None, "qwen2.5-7b");
+        let options = GenerationOptions::new().temperature(0.2).max_tokens(512).stop(["###"]).seed(42);
+
+        let json = serde_json::to_value(provider.build_request(&ChatMessage::from_context("ctx", "prompt"), &options, false)).unwrap();
+        assert_eq!(provider.url(), "http://localhost:8000/v1/chat/completions");
+        assert_eq!(json["model"], "qwen2.5-7b");
+        assert_eq!(json["messages"][0], serde_json::json!({ "role": "system", "content": "ctx" }));
+        assert_eq!(json["max_tokens"], 512);
+        assert_eq!(json["stop"][0], "###");
+        assert_eq!(json["seed"], 42);
+        assert!(json.get("top_p").is_none());
+        assert!(json.get("stream").is_none());
+        assert!(json.get("response_format").is_none());
+
+        let json_options = GenerationOptions::new().json(true);
+        let json = serde_json::to_value(provider.build_request(&[ChatMessage::user("x")], &json_options, false)).unwrap();
+        assert_eq!(json["response_format"]["type"], "json_object");
+    }
+}
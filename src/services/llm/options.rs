@@ -0,0 +1,149 @@
+//! Per-request generation options
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::LlmConfig;
+
+/// Sampling options for a single LLM request
+///
+/// Unset fields fall back to the client's defaults (from `LlmConfig`), and
+/// then to the provider's own defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    pub seed: Option<u64>,
+    /// Ask the provider to constrain output to a JSON object, where supported
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub json: bool,
+}
+
+impl GenerationOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn stop(mut self, stop: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.stop = stop.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Every field set, to find which ones a provider drops
+    pub fn probe() -> Self {
+        Self::new().temperature(1.0).top_p(1.0).max_tokens(1).stop(["\n"]).seed(0).json(true)
+    }
+
+    /// Names of the fields set here but not in `kept`
+    pub fn dropped_in(&self, kept: &GenerationOptions) -> Vec<String> {
+        let fields = [
+            ("temperature", self.temperature.is_some() && kept.temperature.is_none()),
+            ("top_p", self.top_p.is_some() && kept.top_p.is_none()),
+            ("max_tokens", self.max_tokens.is_some() && kept.max_tokens.is_none()),
+            ("stop", !self.stop.is_empty() && kept.stop.is_empty()),
+            ("seed", self.seed.is_some() && kept.seed.is_none()),
+            ("json", self.json && !kept.json),
+        ];
+        fields.iter().filter(|(_, dropped)| *dropped).map(|(name, _)| name.to_string()).collect()
+    }
+
+    /// These options with the named fields unset
+    pub fn without(&self, fields: &[String]) -> GenerationOptions {
+        let mut options = self.clone();
+        for field in fields {
+            match field.as_str() {
+                "temperature" => options.temperature = None,
+                "top_p" => options.top_p = None,
+                "max_tokens" => options.max_tokens = None,
+                "stop" => options.stop.clear(),
+                "seed" => options.seed = None,
+                "json" => options.json = false,
+                _ => {}
+            }
+        }
+        options
+    }
+
+    /// Fill unset fields from `defaults`
+    pub fn or(&self, defaults: &GenerationOptions) -> GenerationOptions {
+        GenerationOptions {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            stop: if self.stop.is_empty() {
+                defaults.stop.clone()
+            } else {
+                self.stop.clone()
+            },
+            seed: self.seed.or(defaults.seed),
+            json: self.json || defaults.json,
+        }
+    }
+}
+
+impl From<&LlmConfig> for GenerationOptions {
+    fn from(config: &LlmConfig) -> Self {
+        Self {
+            temperature: Some(config.temperature),
+            top_p: config.top_p,
+            max_tokens: Some(config.max_tokens),
+            stop: Vec::new(),
+            seed: config.seed,
+            json: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_options_override_defaults() {
+        let defaults = GenerationOptions::new().temperature(0.8).max_tokens(4096).stop(["END"]);
+        let request = GenerationOptions::new().temperature(0.3).seed(7);
+
+        let merged = request.or(&defaults);
+        assert_eq!(merged.temperature, Some(0.3));
+        assert_eq!(merged.max_tokens, Some(4096));
+        assert_eq!(merged.stop, vec!["END".to_string()]);
+        assert_eq!(merged.seed, Some(7));
+        assert_eq!(merged.top_p, None);
+    }
+
+    #[test]
+    fn test_dropped_fields_round_trip() {
+        let probe = GenerationOptions::probe();
+        let kept = GenerationOptions { stop: Vec::new(), seed: None, ..probe.clone() };
+        let dropped = probe.dropped_in(&kept);
+        assert_eq!(dropped, ["stop", "seed"]);
+        assert_eq!(probe.without(&dropped), kept);
+    }
+}
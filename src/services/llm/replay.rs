@@ -0,0 +1,333 @@
+//! Record-and-replay provider for deterministic tests and offline demos
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{ChatMessage, Completion, GenerationOptions, LlmProvider};
+use crate::models::TokenUsage;
+
+/// A recorded request/response pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    /// Hash of the request, used as the lookup key
+    pub hash: String,
+    pub messages: Vec<ChatMessage>,
+    pub options: GenerationOptions,
+    pub response: String,
+    #[serde(default)]
+    pub usage: TokenUsage,
+    /// Provider that served the request
+    #[serde(default)]
+    pub provider: String,
+    /// Model that served the request
+    #[serde(default)]
+    pub model: String,
+}
+
+/// Cassette file contents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    /// Provider the responses were recorded from
+    pub provider: String,
+    /// Model the responses were recorded from
+    pub model: String,
+    /// Options the recording provider does not send; they are dropped on
+    /// replay too, so requests hash the same
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unsupported_options: Vec<String>,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Load a cassette from a JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read cassette {:?}", path))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse cassette {:?}", path))
+    }
+
+    /// Save the cassette as pretty JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let json = serde_json::to_string_pretty(self)
+            .context("Failed to serialize cassette")?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write cassette {:?}", path))
+    }
+}
+
+/// Stable hash of a request
+pub fn request_hash(messages: &[ChatMessage], options: &GenerationOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&(messages, options)).unwrap_or_default());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Serializes cassette writes; every provider in a fallback chain may
+/// record into the same file
+static CASSETTE_LOCK: Mutex<()> = Mutex::new(());
+
+enum Mode {
+    Record {
+        inner: Box<dyn LlmProvider>,
+    },
+    Replay {
+        cassette: Cassette,
+        responses: HashMap<String, Completion>,
+    },
+}
+
+/// Provider that records real responses to a cassette, or replays them
+///
+/// In replay mode a request that is not in the cassette is an error, so a
+/// changed prompt can never silently hit the network.
+pub struct ReplayProvider {
+    path: PathBuf,
+    mode: Mode,
+}
+
+impl ReplayProvider {
+    /// Wrap `inner`, appending every interaction to the cassette at `path`
+    ///
+    /// Interactions already in an existing cassette are kept.
+    pub fn record(inner: Box<dyn LlmProvider>, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            // Fail now rather than on the first response
+            Cassette::load(&path)?;
+        }
+
+        Ok(Self {
+            path,
+            mode: Mode::Record { inner },
+        })
+    }
+
+    fn save_interaction(&self, inner: &dyn LlmProvider, interaction: Interaction) -> Result<()> {
+        let _guard = CASSETTE_LOCK.lock().unwrap();
+
+        let mut cassette = if self.path.exists() {
+            Cassette::load(&self.path)?
+        } else {
+            Cassette::default()
+        };
+        if cassette.provider.is_empty() {
+            cassette.provider = inner.name().to_string();
+            cassette.model = inner.model().to_string();
+            let probe = GenerationOptions::probe();
+            cassette.unsupported_options = probe.dropped_in(&inner.supported_options(&probe));
+        }
+        cassette.interactions.retain(|i| i.hash != interaction.hash);
+        cassette.interactions.push(interaction);
+        cassette.save(&self.path)
+    }
+
+    /// Serve responses from the cassette at `path`
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let cassette = Cassette::load(&path)?;
+        let responses = cassette
+            .interactions
+            .iter()
+            .map(|i| {
+                let mut completion = Completion::new(i.response.clone(), i.usage);
+                completion.fill_source(&i.provider, &i.model);
+                (i.hash.clone(), completion)
+            })
+            .collect();
+
+        Ok(Self {
+            path,
+            mode: Mode::Replay { cassette, responses },
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ReplayProvider {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        options: &GenerationOptions,
+    ) -> Result<Completion> {
+        // Hash only what reaches the provider, as recorded and replayed alike
+        let options = &self.supported_options(options);
+        let hash = request_hash(messages, options);
+
+        match &self.mode {
+            Mode::Replay { responses, .. } => {
+                tracing::debug!("Replaying request {} from {:?}", hash, self.path);
+                responses.get(&hash).cloned().with_context(|| {
+                    format!(
+                        "No recorded response for request {} in cassette {:?} (last message: {:?})",
+                        hash,
+                        self.path,
+                        messages.last().map(|m| m.content.chars().take(80).collect::<String>())
+                    )
+                })
+            }
+            Mode::Record { inner } => {
+                let completion = inner.chat(messages, options).await?;
+
+                self.save_interaction(inner.as_ref(), Interaction {
+                    hash,
+                    messages: messages.to_vec(),
+                    options: options.clone(),
+                    response: completion.text.clone(),
+                    usage: completion.usage,
+                    provider: inner.name().to_string(),
+                    model: inner.model().to_string(),
+                })?;
+
+                Ok(completion)
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        match &self.mode {
+            Mode::Record { inner, .. } => inner.name(),
+            Mode::Replay { cassette, .. } => &cassette.provider,
+        }
+    }
+
+    fn model(&self) -> &str {
+        match &self.mode {
+            Mode::Record { inner, .. } => inner.model(),
+            Mode::Replay { cassette, .. } => &cassette.model,
+        }
+    }
+
+    fn supported_options(&self, options: &GenerationOptions) -> GenerationOptions {
+        match &self.mode {
+            Mode::Record { inner, .. } => inner.supported_options(options),
+            Mode::Replay { cassette, .. } => options.without(&cassette.unsupported_options),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::llm::LlmClient;
+    use tempfile::tempdir;
+
+    struct EchoProvider;
+
+    #[async_trait]
+    impl LlmProvider for EchoProvider {
+        async fn chat(
+            &self,
+            messages: &[ChatMessage],
+            _options: &GenerationOptions,
+        ) -> Result<Completion> {
+            Ok(Completion::new(format!("echo: {}", messages.last().unwrap().content), TokenUsage::new(5, 2)))
+        }
+
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn model(&self) -> &str {
+            "echo-1"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        let options = GenerationOptions::new().temperature(0.5);
+
+        let recorder = ReplayProvider::record(Box::new(EchoProvider), &path).unwrap();
+        assert_eq!(recorder.generate("ctx", "第一章", &options).await.unwrap().text, "echo: 第一章");
+
+        let replayer = ReplayProvider::replay(&path).unwrap();
+        assert_eq!(replayer.name(), "echo");
+        assert_eq!(replayer.model(), "echo-1");
+        let replayed = replayer.generate("ctx", "第一章", &options).await.unwrap();
+        assert_eq!(replayed.text, "echo: 第一章");
+        assert_eq!(replayed.usage, TokenUsage::new(5, 2));
+    }
+
+    /// Drops the seed, like MiniMax and Anthropic
+    struct SeedlessProvider;
+
+    #[async_trait]
+    impl LlmProvider for SeedlessProvider {
+        async fn chat(&self, messages: &[ChatMessage], options: &GenerationOptions) -> Result<Completion> {
+            assert!(options.seed.is_none());
+            EchoProvider.chat(messages, options).await
+        }
+
+        fn name(&self) -> &str {
+            "seedless"
+        }
+
+        fn model(&self) -> &str {
+            "seedless-1"
+        }
+
+        fn supported_options(&self, options: &GenerationOptions) -> GenerationOptions {
+            GenerationOptions { seed: None, ..options.clone() }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_drops_options_the_recording_provider_dropped() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+        let messages = [ChatMessage::user("第一章")];
+        let options = GenerationOptions::new().temperature(0.5).seed(3);
+
+        let recorder = LlmClient::new(Box::new(ReplayProvider::record(Box::new(SeedlessProvider), &path).unwrap()));
+        assert_eq!(recorder.chat_with(&messages, &options).await.unwrap().text, "echo: 第一章");
+        assert_eq!(Cassette::load(&path).unwrap().unsupported_options, ["seed"]);
+
+        let replayer = LlmClient::new(Box::new(ReplayProvider::replay(&path).unwrap()));
+        assert_eq!(replayer.chat_with(&messages, &options).await.unwrap().text, "echo: 第一章");
+        assert!(replayer.resolve_options(&options).seed.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replay_miss_fails() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+
+        let recorder = ReplayProvider::record(Box::new(EchoProvider), &path).unwrap();
+        recorder.generate("ctx", "a", &GenerationOptions::default()).await.unwrap();
+
+        let replayer = ReplayProvider::replay(&path).unwrap();
+        let other_options = GenerationOptions::new().seed(1);
+        assert!(replayer.generate("ctx", "a", &other_options).await.is_err());
+        assert!(replayer.generate("ctx", "b", &GenerationOptions::default()).await.is_err());
+    }
+
+    #[test]
+    fn test_request_hash_separates_messages() {
+        let options = GenerationOptions::default();
+        let split = |a: &str, b: &str| vec![ChatMessage::system(a), ChatMessage::user(b)];
+        assert_ne!(request_hash(&split("ab", "c"), &options), request_hash(&split("a", "bc"), &options));
+        assert_ne!(
+            request_hash(&[ChatMessage::user("a")], &options),
+            request_hash(&[ChatMessage::assistant("a")], &options)
+        );
+        assert_eq!(request_hash(&split("a", "b"), &options), request_hash(&split("a", "b"), &options));
+    }
+}
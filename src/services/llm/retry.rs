@@ -0,0 +1,162 @@
+//! Retry policy with exponential backoff
+
+use std::time::Duration;
+
+use crate::config::RetryConfig;
+
+use super::LlmError;
+
+/// Retry policy applied by `LlmClient` to retryable errors
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+
+    /// Upper bound for any single delay
+    pub max_backoff: Duration,
+
+    /// Factor applied to the delay after each retry
+    pub multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based)
+    ///
+    /// A provider-supplied `Retry-After` wins when it is longer than the
+    /// computed backoff. Neither exceeds `max_backoff`.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        // In f64 so large exponents saturate instead of overflowing Duration
+        let factor = self.multiplier.max(1.0).powi(attempt.min(i32::MAX as u32) as i32);
+        let max = self.max_backoff.as_secs_f64();
+        let backoff = Duration::from_secs_f64((self.initial_backoff.as_secs_f64() * factor).min(max));
+        match retry_after {
+            Some(after) => backoff.max(after).min(self.max_backoff),
+            None => backoff,
+        }
+    }
+
+    /// Whether `err` should be retried after `attempt` retries so far
+    ///
+    /// An error asking to wait longer than `max_backoff` is not retried, so
+    /// the request falls back to the next provider or fails instead of
+    /// stalling.
+    pub fn should_retry(&self, attempt: u32, err: &anyhow::Error) -> bool {
+        let Some(err) = err.downcast_ref::<LlmError>() else {
+            return false;
+        };
+        attempt < self.max_retries
+            && err.is_retryable()
+            && err.retry_after().is_none_or(|after| after <= self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(config: &RetryConfig) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            multiplier: config.multiplier,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_delay_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            multiplier: 2.0,
+        };
+
+        assert_eq!(policy.delay(0, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(1, None), Duration::from_millis(200));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(400));
+        assert_eq!(policy.delay(3, None), Duration::from_millis(500));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_millis(300))),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(86_400))),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn test_large_attempts_do_not_overflow() {
+        let policy = RetryPolicy {
+            max_retries: 200,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 10.0,
+        };
+
+        assert_eq!(policy.delay(99, None), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX, None), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_long_retry_after_is_not_retried() {
+        let policy = RetryPolicy::default();
+        let limited = |secs| -> anyhow::Error {
+            LlmError::RateLimited {
+                provider: "qwen".to_string(),
+                message: String::new(),
+                retry_after: Some(Duration::from_secs(secs)),
+            }
+            .into()
+        };
+
+        assert!(policy.should_retry(0, &limited(5)));
+        assert!(!policy.should_retry(0, &limited(86_400)));
+    }
+
+    #[test]
+    fn test_only_retryable_errors_are_retried() {
+        let policy = RetryPolicy::default();
+        let server: anyhow::Error = LlmError::Server {
+            provider: "qwen".to_string(),
+            status: 500,
+            message: String::new(),
+        }
+        .into();
+        let auth: anyhow::Error = LlmError::Auth {
+            provider: "qwen".to_string(),
+            message: String::new(),
+        }
+        .into();
+
+        assert!(policy.should_retry(0, &server));
+        assert!(!policy.should_retry(3, &server));
+        assert!(!policy.should_retry(0, &auth));
+        assert!(!policy.should_retry(0, &anyhow::anyhow!("other")));
+    }
+}
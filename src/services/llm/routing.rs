@@ -0,0 +1,177 @@
+//! Task-based model routing
+
+use anyhow::Result;
+
+use crate::config::{EndpointConfig, LlmConfig, TaskRoutes};
+use crate::models::ProjectModelConfig;
+
+use super::{create_client_with_config, LlmClient};
+
+/// Kind of work an LLM call does, used to pick its model
+///
+/// Only tasks that call the LLM are listed; outline and plan are built
+/// without one so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LlmTask {
+    /// Chapter prose
+    Prose,
+    /// Condensing story material that does not fit the context window
+    Summary,
+    /// Candidate judging and the continuity pass
+    Consistency,
+}
+
+impl LlmTask {
+    /// Route configured for this task, if any
+    pub fn route<'a>(&self, routes: &'a TaskRoutes) -> Option<&'a EndpointConfig> {
+        match self {
+            LlmTask::Prose => routes.prose.as_ref(),
+            LlmTask::Summary => routes.summary.as_ref(),
+            LlmTask::Consistency => routes.consistency.as_ref(),
+        }
+    }
+}
+
+/// The global LLM config with `project`'s overrides applied
+///
+/// Credentials stay in the global config: project endpoints are resolved
+/// against it, so `project.json` only needs provider and model names.
+pub fn config_for_project(global: &LlmConfig, project: &ProjectModelConfig) -> LlmConfig {
+    if !project.enabled {
+        return global.clone();
+    }
+
+    // Make global entries self-contained before the primary changes
+    let resolve_global = |endpoint: &EndpointConfig| global.with_endpoint(endpoint).endpoint();
+    let mut config = global.with_endpoint(&EndpointConfig {
+        provider: project.provider.clone(),
+        model: project.model.clone(),
+        ..Default::default()
+    });
+    config.fallbacks = global.fallbacks.iter().map(resolve_global).collect();
+    config.routes = global.routes.map(resolve_global);
+
+    // Endpoints naming a provider take credentials from the global config,
+    // the others inherit the project's primary provider
+    let resolve = |endpoint: &EndpointConfig| match endpoint.provider {
+        Some(_) => global.with_endpoint(endpoint).endpoint(),
+        None => config.with_endpoint(endpoint).endpoint(),
+    };
+    let fallbacks: Vec<EndpointConfig> = project.fallbacks.iter().map(resolve).collect();
+    let routes = project.routes.map(resolve);
+
+    if !fallbacks.is_empty() {
+        config.fallbacks = fallbacks;
+    }
+    config.routes = config.routes.merged(&routes);
+    config
+}
+
+/// Config for `task`: its route applied to `[llm]`, keeping the fallback chain
+pub fn config_for_task(config: &LlmConfig, task: LlmTask) -> LlmConfig {
+    match task.route(&config.routes) {
+        Some(endpoint) => config.with_endpoint(endpoint),
+        None => config.clone(),
+    }
+}
+
+/// Create a client for `task`
+pub fn create_client_for_task(config: &LlmConfig, task: LlmTask) -> Result<LlmClient> {
+    create_client_with_config(&config_for_task(config, task))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn endpoint(provider: Option<&str>, model: &str) -> EndpointConfig {
+        EndpointConfig {
+            provider: provider.map(str::to_string),
+            model: Some(model.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn routed_config() -> LlmConfig {
+        let mut config = Config::default().llm;
+        config.provider = "minimax".to_string();
+        config.api_key = "minimax-key".to_string();
+        config.model = Some("abab6.5s-chat".to_string());
+        config.fallbacks = vec![EndpointConfig {
+            api_key: Some("qwen-key".to_string()),
+            ..endpoint(Some("qwen"), "qwen-plus")
+        }];
+        config.routes.prose = Some(endpoint(Some("qwen"), "qwen-max"));
+        config.routes.summary = Some(endpoint(None, "abab5.5s-chat"));
+        config
+    }
+
+    #[test]
+    fn test_route_picks_model_and_credentials() {
+        let config = routed_config();
+
+        let prose = config_for_task(&config, LlmTask::Prose);
+        assert_eq!(prose.provider, "qwen");
+        assert_eq!(prose.model.as_deref(), Some("qwen-max"));
+        assert_eq!(prose.api_key, "qwen-key");
+
+        // Routes without a provider keep the primary one
+        let summary = config_for_task(&config, LlmTask::Summary);
+        assert_eq!((summary.provider.as_str(), summary.model.as_deref()), ("minimax", Some("abab5.5s-chat")));
+        assert_eq!(summary.api_key, "minimax-key");
+        let consistency = config_for_task(&config, LlmTask::Consistency);
+        assert_eq!(consistency.model.as_deref(), Some("abab6.5s-chat"));
+
+        let mut unrouted = config.clone();
+        unrouted.routes.prose = None;
+        let prose = config_for_task(&unrouted, LlmTask::Prose);
+        assert_eq!((prose.provider.as_str(), prose.model.as_deref()), ("minimax", Some("abab6.5s-chat")));
+
+        let client = create_client_for_task(&config, LlmTask::Prose).unwrap();
+        assert_eq!(client.name(), "qwen");
+        assert_eq!(client.model(), "qwen-max");
+    }
+
+    #[test]
+    fn test_project_overrides_routes() {
+        let global = routed_config();
+        let project = ProjectModelConfig {
+            enabled: true,
+            provider: Some("qwen".to_string()),
+            model: Some("qwen-turbo".to_string()),
+            ..Default::default()
+        };
+
+        let config = config_for_project(&global, &project);
+        assert_eq!(config.provider, "qwen");
+        assert_eq!(config.api_key, "qwen-key");
+        assert_eq!(config.model.as_deref(), Some("qwen-turbo"));
+        // Global routes keep their provider and credentials
+        let prose = config_for_task(&config, LlmTask::Prose);
+        assert_eq!((prose.model.as_deref(), prose.api_key.as_str()), (Some("qwen-max"), "qwen-key"));
+
+        // A project route without a provider uses the project's primary
+        let routed = ProjectModelConfig {
+            routes: TaskRoutes { prose: Some(endpoint(None, "qwen-long")), ..Default::default() },
+            ..project.clone()
+        };
+        let prose = config_for_task(&config_for_project(&global, &routed), LlmTask::Prose);
+        assert_eq!(prose.provider, "qwen");
+        assert_eq!(prose.model.as_deref(), Some("qwen-long"));
+        assert_eq!(prose.api_key, "qwen-key");
+
+        // A project route naming a provider takes its credentials from the global config
+        let mut global = global;
+        global.routes.prose = None;
+        let routed = ProjectModelConfig {
+            routes: TaskRoutes { prose: Some(endpoint(Some("minimax"), "abab6.5g-chat")), ..Default::default() },
+            ..project.clone()
+        };
+        let prose = config_for_task(&config_for_project(&global, &routed), LlmTask::Prose);
+        assert_eq!((prose.provider.as_str(), prose.api_key.as_str()), ("minimax", "minimax-key"));
+
+        let disabled = ProjectModelConfig { enabled: false, ..project };
+        assert_eq!(config_for_project(&global, &disabled).provider, "minimax");
+    }
+}
@@ -0,0 +1,249 @@
+//! Decoding of streaming LLM responses
+//!
+//! Most providers stream Server-Sent Events; Ollama streams newline-delimited
+//! JSON. Both share the same stream plumbing.
+
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+use reqwest::Response;
+
+use super::{EventStream, StreamEvent};
+
+/// Splits a byte stream into payloads
+pub(crate) trait Framing: Send + 'static {
+    /// Feed a chunk of bytes, returning any completed payloads
+    fn push(&mut self, chunk: &[u8]) -> Vec<String>;
+
+    /// Flush whatever is left once the stream has ended
+    fn finish(&mut self) -> Vec<String>;
+}
+
+/// Incremental SSE decoder
+///
+/// Bytes are fed in as they arrive from the network; complete events are
+/// returned as their joined `data:` payloads.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a chunk of bytes, returning the payloads of any completed events
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            self.handle_line(line, &mut events);
+        }
+        events
+    }
+
+    /// Flush whatever is left once the stream has ended
+    pub(crate) fn finish(&mut self) -> Vec<String> {
+        let mut events = Vec::new();
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&rest);
+            self.handle_line(line.trim_end_matches(['\n', '\r']), &mut events);
+        }
+        self.handle_line("", &mut events);
+        events
+    }
+
+    fn handle_line(&mut self, line: &str, events: &mut Vec<String>) {
+        if line.is_empty() {
+            if !self.data.is_empty() {
+                events.push(self.data.join("\n"));
+                self.data.clear();
+            }
+            return;
+        }
+
+        if let Some(value) = line.strip_prefix("data:") {
+            self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+        // Other fields (event:, id:, retry:) and comments are ignored
+    }
+}
+
+impl Framing for SseDecoder {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        SseDecoder::push(self, chunk)
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        SseDecoder::finish(self)
+    }
+}
+
+/// Incremental decoder for newline-delimited JSON, one payload per line
+#[derive(Debug, Default)]
+pub(crate) struct NdjsonDecoder {
+    buffer: Vec<u8>,
+}
+
+impl Framing for NdjsonDecoder {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn finish(&mut self) -> Vec<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let line = String::from_utf8_lossy(&rest).trim().to_string();
+        if line.is_empty() { Vec::new() } else { vec![line] }
+    }
+}
+
+struct StreamState<D, F> {
+    response: Response,
+    decoder: D,
+    pending: VecDeque<String>,
+    ready: VecDeque<StreamEvent>,
+    finished: bool,
+    parse: F,
+}
+
+/// Turn an SSE response into a stream of events
+///
+/// `parse` maps each event payload to the text and usage it carries; empty
+/// text is skipped. A `[DONE]` payload ends the stream.
+pub(crate) fn event_stream<F>(response: Response, parse: F) -> EventStream
+where
+    F: Fn(&str) -> Result<Vec<StreamEvent>> + Send + 'static,
+{
+    framed_stream(response, SseDecoder::new(), parse)
+}
+
+/// Turn a newline-delimited JSON response into a stream of events
+///
+/// `parse` is called once per line, as for [`event_stream`].
+pub(crate) fn ndjson_stream<F>(response: Response, parse: F) -> EventStream
+where
+    F: Fn(&str) -> Result<Vec<StreamEvent>> + Send + 'static,
+{
+    framed_stream(response, NdjsonDecoder::default(), parse)
+}
+
+fn framed_stream<D, F>(response: Response, decoder: D, parse: F) -> EventStream
+where
+    D: Framing,
+    F: Fn(&str) -> Result<Vec<StreamEvent>> + Send + 'static,
+{
+    let state = StreamState {
+        response,
+        decoder,
+        pending: VecDeque::new(),
+        ready: VecDeque::new(),
+        finished: false,
+        parse,
+    };
+
+    Box::pin(futures::stream::unfold(state, |mut st| async move {
+        loop {
+            if let Some(event) = st.ready.pop_front() {
+                return Some((Ok(event), st));
+            }
+
+            if let Some(data) = st.pending.pop_front() {
+                if data.trim() == "[DONE]" {
+                    st.finished = true;
+                    st.pending.clear();
+                    continue;
+                }
+                match (st.parse)(&data) {
+                    Ok(events) => {
+                        st.ready.extend(events.into_iter().filter(|event| {
+                            !matches!(event, StreamEvent::Text(text) if text.is_empty())
+                        }));
+                        continue;
+                    }
+                    Err(e) => {
+                        st.finished = true;
+                        st.pending.clear();
+                        return Some((Err(e), st));
+                    }
+                }
+            }
+
+            if st.finished {
+                return None;
+            }
+
+            match st.response.chunk().await.context("Failed to read response stream") {
+                Ok(Some(bytes)) => {
+                    let events = st.decoder.push(&bytes);
+                    st.pending.extend(events);
+                }
+                Ok(None) => {
+                    st.finished = true;
+                    let events = st.decoder.finish();
+                    st.pending.extend(events);
+                }
+                Err(e) => {
+                    st.finished = true;
+                    return Some((Err(e), st));
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_split_events() {
+        let mut decoder = SseDecoder::new();
+
+        assert!(decoder.push(b"data: {\"a\":").is_empty());
+        let events = decoder.push(b"1}\n\ndata: second\r\n\r\n");
+        assert_eq!(events, vec!["{\"a\":1}".to_string(), "second".to_string()]);
+    }
+
+    #[test]
+    fn test_decode_multibyte_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        let payload = "data: 你好\n\n".as_bytes();
+
+        assert!(decoder.push(&payload[..7]).is_empty());
+        assert_eq!(decoder.push(&payload[7..]), vec!["你好".to_string()]);
+    }
+
+    #[test]
+    fn test_decode_ignores_comments_and_flushes_tail() {
+        let mut decoder = SseDecoder::new();
+
+        assert!(decoder.push(b": keep-alive\nevent: result\ndata: tail").is_empty());
+        assert_eq!(decoder.finish(), vec!["tail".to_string()]);
+    }
+
+    #[test]
+    fn test_decode_ndjson_lines() {
+        let mut decoder = NdjsonDecoder::default();
+
+        assert!(decoder.push(b"{\"a\":").is_empty());
+        assert_eq!(decoder.push(b"1}\n\n{\"b\":2}\r\n{\"c\""), vec!["{\"a\":1}".to_string(), "{\"b\":2}".to_string()]);
+        assert_eq!(decoder.finish(), vec!["{\"c\"".to_string()]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Structured JSON output
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+
+use super::{ChatMessage, Completion, GenerationOptions, LlmClient, LlmError, Role};
+
+/// A reply deserialized into `T`
+#[derive(Debug, Clone)]
+pub struct StructuredCompletion<T> {
+    pub value: T,
+
+    /// The reply that parsed, with usage summed over every attempt
+    pub completion: Completion,
+}
+
+impl LlmClient {
+    /// Ask for JSON matching `T` and deserialize the reply
+    ///
+    /// `schema` describes the expected shape; an example object works well.
+    /// JSON is pulled out of fenced or chatty replies. When parsing fails the
+    /// error is sent back to the model, up to the client's repair limit,
+    /// before giving up with [`LlmError::InvalidJson`].
+    pub async fn chat_json<T: DeserializeOwned>(
+        &self,
+        messages: &[ChatMessage],
+        schema: &str,
+        options: &GenerationOptions,
+    ) -> Result<StructuredCompletion<T>> {
+        let options = options.clone().json(true);
+        let mut conversation = with_json_instruction(messages, schema);
+        let mut usage = crate::models::TokenUsage::default();
+        let mut repairs = 0;
+
+        loop {
+            let mut completion = self.chat_with(&conversation, &options).await?;
+            usage += completion.usage;
+
+            let error = match parse_json::<T>(&completion.text) {
+                Ok(value) => {
+                    completion.usage = usage;
+                    return Ok(StructuredCompletion { value, completion });
+                }
+                Err(error) => error,
+            };
+
+            if repairs >= self.json_repairs {
+                return Err(LlmError::InvalidJson {
+                    provider: completion.provider,
+                    message: error,
+                    raw: completion.text,
+                }.into());
+            }
+            repairs += 1;
+            tracing::warn!("Reply is not valid JSON ({}), asking for repair {}/{}", error, repairs, self.json_repairs);
+
+            conversation.push(ChatMessage::assistant(completion.text));
+            conversation.push(ChatMessage::user(format!(
+                "上面的回复无法解析为所需的 JSON：{}\n请修正后只输出 JSON，不要包含其他文字。",
+                error
+            )));
+        }
+    }
+}
+
+/// `messages` with the JSON instruction added to the final user message
+fn with_json_instruction(messages: &[ChatMessage], schema: &str) -> Vec<ChatMessage> {
+    let instruction = format!("请只输出符合以下结构的 JSON，不要包含其他文字：\n{}", schema);
+
+    let mut messages = messages.to_vec();
+    match messages.last_mut() {
+        Some(last) if last.role == Role::User => {
+            last.content = format!("{}\n\n{}", last.content, instruction);
+        }
+        _ => messages.push(ChatMessage::user(instruction)),
+    }
+    messages
+}
+
+fn parse_json<T: DeserializeOwned>(text: &str) -> std::result::Result<T, String> {
+    let json = extract_json(text).ok_or_else(|| "no JSON object or array found".to_string())?;
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+/// Find the JSON in a reply
+///
+/// Prefers the contents of a fenced code block, then the first balanced
+/// object or array. An unterminated value is returned up to the end of the
+/// text so the parse error points at the truncation.
+pub fn extract_json(text: &str) -> Option<&str> {
+    if let Some(fenced) = fenced_block(text) {
+        return Some(fenced);
+    }
+
+    let start = text.find(['{', '['])?;
+    let rest = &text[start..];
+    Some(balanced_prefix(rest).unwrap_or(rest.trim_end()))
+}
+
+/// Contents of the first fenced block that looks like JSON
+fn fenced_block(text: &str) -> Option<&str> {
+    let mut rest = text;
+    while let Some(open) = rest.find("```") {
+        let after = &rest[open + 3..];
+        // Skip the language tag
+        let body_start = after.find('\n').map(|i| i + 1).unwrap_or(after.len());
+        let body = &after[body_start..];
+        let close = body.find("```")?;
+        let content = body[..close].trim();
+        if content.starts_with('{') || content.starts_with('[') {
+            return Some(content);
+        }
+        rest = &body[close + 3..];
+    }
+    None
+}
+
+/// The leading balanced JSON value of `text`, which starts with `{` or `[`
+fn balanced_prefix(text: &str) -> Option<&str> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(&text[..=i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenUsage;
+    use crate::services::llm::LlmProvider;
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Character {
+        name: String,
+        role: String,
+    }
+
+    /// Provider returning scripted replies and recording what it was sent
+    struct ScriptedProvider {
+        replies: Mutex<Vec<&'static str>>,
+        seen: Mutex<Vec<Vec<ChatMessage>>>,
+    }
+
+    impl ScriptedProvider {
+        fn new(replies: &[&'static str]) -> Self {
+            Self {
+                replies: Mutex::new(replies.iter().rev().copied().collect()),
+                seen: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for ScriptedProvider {
+        async fn chat(&self, messages: &[ChatMessage], options: &GenerationOptions) -> Result<Completion> {
+            assert!(options.json);
+            self.seen.lock().unwrap().push(messages.to_vec());
+            let reply = self.replies.lock().unwrap().pop().unwrap();
+            Ok(Completion::new(reply, TokenUsage::new(10, 5)))
+        }
+
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        fn model(&self) -> &str {
+            "scripted-1"
+        }
+    }
+
+    #[test]
+    fn test_extract_json() {
+        assert_eq!(extract_json("好的：\n```json\n{\"a\": 1}\n```\n希望有帮助"), Some("{\"a\": 1}"));
+        assert_eq!(extract_json("结果是 [1, 2] 。"), Some("[1, 2]"));
+        assert_eq!(extract_json(r#"{"s": "a } b", "n": {"x": "\"}"}} 多余"#), Some(r#"{"s": "a } b", "n": {"x": "\"}"}}"#));
+        assert_eq!(extract_json("```\n代码\n```\n{\"ok\": true}"), Some("{\"ok\": true}"));
+        assert_eq!(extract_json("{\"cut\": \"off"), Some("{\"cut\": \"off"));
+        assert_eq!(extract_json("没有 JSON"), None);
+    }
+
+    #[tokio::test]
+    async fn test_chat_json_repairs_reply() {
+        let provider = ScriptedProvider::new(&[
+            "当然！角色如下：{\"name\": \"林风\",}",
+            "```json\n{\"name\": \"林风\", \"role\": \"主角\"}\n```",
+        ]);
+        let client = LlmClient::new(Box::new(provider));
+
+        let result: StructuredCompletion<Character> = client
+            .chat_json(&[ChatMessage::user("提取主角")], r#"{"name": "", "role": ""}"#, &GenerationOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.value, Character { name: "林风".to_string(), role: "主角".to_string() });
+        assert_eq!(result.completion.usage, TokenUsage::new(20, 10));
+    }
+
+    #[tokio::test]
+    async fn test_chat_json_gives_up_with_raw_text() {
+        let provider = ScriptedProvider::new(&["不是 JSON", "还是不是", "{\"name\": 1}"]);
+        let client = LlmClient::new(Box::new(provider)).with_json_repairs(2);
+
+        let err = client
+            .chat_json::<Character>(&[ChatMessage::user("提取主角")], "{}", &GenerationOptions::default())
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<LlmError>() {
+            Some(LlmError::InvalidJson { provider, raw, .. }) => {
+                assert_eq!(provider, "scripted");
+                assert_eq!(raw, "{\"name\": 1}");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_instruction_goes_into_last_user_message() {
+        let messages = with_json_instruction(&[ChatMessage::system("s"), ChatMessage::user("u")], "{}");
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].content.starts_with("u\n\n"));
+
+        let messages = with_json_instruction(&[ChatMessage::user("u"), ChatMessage::assistant("{")], "{}");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].role, Role::User);
+    }
+}
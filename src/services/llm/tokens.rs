@@ -0,0 +1,191 @@
+//! Token estimates and model context windows
+//!
+//! No tokenizer is bundled, so counts are estimated per character class.
+//! Tokenizers differ most on Chinese: Qwen and MiniMax vocabularies hold
+//! many whole words, while GPT and Claude split most characters. The ratios
+//! below lean high so prompts fit with room to spare.
+
+use super::ChatMessage;
+use crate::text::is_cjk;
+
+/// Used for models not in [`context_window`]
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 8192;
+
+/// Used for models not in [`max_output_tokens`]
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 8192;
+
+/// Tokens added per message for role markers and separators
+const MESSAGE_OVERHEAD: f32 = 4.0;
+
+/// Estimates token counts for one provider's tokenizer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenEstimator {
+    /// Tokens per CJK character
+    cjk: f32,
+    /// Tokens per ASCII letter or digit
+    ascii: f32,
+    /// Tokens per other character: punctuation, symbols, other scripts
+    other: f32,
+}
+
+impl TokenEstimator {
+    /// Estimator for the tokenizer of `provider`
+    pub fn for_provider(provider: &str) -> Self {
+        let cjk = match provider.to_lowercase().as_str() {
+            "qwen" | "tongyi" | "aliyun" | "minimax" | "ollama" => 0.8,
+            "openai" | "gpt" => 1.0,
+            "anthropic" | "claude" => 1.3,
+            // Unknown vocabulary: assume the least efficient common one
+            _ => 1.3,
+        };
+        Self {
+            cjk,
+            ascii: 0.3,
+            other: 1.0,
+        }
+    }
+
+    /// Estimated tokens of `text`
+    pub fn count(&self, text: &str) -> u32 {
+        text.chars().map(|c| self.cost(c)).sum::<f32>().ceil() as u32
+    }
+
+    /// Estimated tokens of a request's messages
+    pub fn count_messages(&self, messages: &[ChatMessage]) -> u32 {
+        messages
+            .iter()
+            .map(|m| self.count(&m.content) + MESSAGE_OVERHEAD as u32)
+            .sum()
+    }
+
+    /// Chinese characters that fit in `tokens`
+    pub fn chars_for(&self, tokens: u32) -> u32 {
+        (tokens as f32 / self.cjk) as u32
+    }
+
+    /// Estimated tokens of one character
+    pub(crate) fn cost(&self, c: char) -> f32 {
+        if is_cjk(c) {
+            self.cjk
+        } else if c.is_ascii_alphanumeric() {
+            self.ascii
+        } else if c.is_whitespace() {
+            // Mostly merged into the following word
+            0.1
+        } else {
+            self.other
+        }
+    }
+}
+
+impl Default for TokenEstimator {
+    fn default() -> Self {
+        Self::for_provider("")
+    }
+}
+
+/// Context window in tokens of `model` on `provider`
+///
+/// Matches on model name prefixes, most specific first. Ollama models get
+/// the default window, which is sent to the server as `num_ctx` so it
+/// loads the model with that much context.
+pub fn context_window(provider: &str, model: &str) -> u32 {
+    const WINDOWS: &[(&str, u32)] = &[
+        ("qwen-long", 10_000_000),
+        ("qwen-turbo", 131_072),
+        ("qwen-plus", 131_072),
+        ("qwen-max", 32_768),
+        ("abab6.5s", 245_760),
+        ("abab6.5", 8_192),
+        ("abab5.5", 16_384),
+        ("minimax-text-01", 1_000_192),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4.1", 1_047_576),
+        ("gpt-4", 8_192),
+        ("gpt-3.5-turbo", 16_385),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("claude", 200_000),
+    ];
+
+    if provider.eq_ignore_ascii_case("ollama") {
+        return DEFAULT_CONTEXT_WINDOW;
+    }
+    let model = model.to_lowercase();
+    WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Most tokens `model` on `provider` writes in one reply
+///
+/// Matches on model name prefixes like [`context_window`]. Ollama models are
+/// bounded by their window instead.
+pub fn max_output_tokens(provider: &str, model: &str) -> u32 {
+    const LIMITS: &[(&str, u32)] = &[
+        ("qwen", 8_192),
+        ("abab", 8_192),
+        ("minimax-text-01", 40_000),
+        ("gpt-4o", 16_384),
+        ("gpt-4-turbo", 4_096),
+        ("gpt-4.1", 32_768),
+        ("gpt-4", 8_192),
+        ("gpt-3.5-turbo", 4_096),
+        ("o1", 100_000),
+        ("o3", 100_000),
+        ("claude-3-opus", 4_096),
+        ("claude-3-haiku", 4_096),
+        ("claude-3-sonnet", 4_096),
+        ("claude", 8_192),
+    ];
+
+    if provider.eq_ignore_ascii_case("ollama") {
+        return context_window(provider, model);
+    }
+    let model = model.to_lowercase();
+    LIMITS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, limit)| *limit)
+        .unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_by_character_class() {
+        let qwen = TokenEstimator::for_provider("qwen");
+        let claude = TokenEstimator::for_provider("anthropic");
+        assert_eq!(qwen.count(""), 0);
+        assert_eq!(qwen.count("林风拔剑而起"), 5);
+        assert_eq!(claude.count("林风拔剑而起"), 8);
+        assert!(qwen.count("The sword rose") < qwen.count("林风拔剑而起，剑光如雪"));
+
+        let messages = [ChatMessage::system("你好"), ChatMessage::user("你好")];
+        assert_eq!(qwen.count_messages(&messages), 2 * (2 + 4));
+    }
+
+    #[test]
+    fn test_context_windows() {
+        assert_eq!(context_window("qwen", "qwen-max-latest"), 32_768);
+        assert_eq!(context_window("openai", "gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("openai", "gpt-4-0613"), 8_192);
+        assert_eq!(context_window("anthropic", "claude-3-5-sonnet-latest"), 200_000);
+        assert_eq!(context_window("ollama", "qwen2.5:7b"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(context_window("openai_compatible", "mystery"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_max_output_tokens() {
+        assert_eq!(max_output_tokens("openai", "gpt-4o-mini"), 16_384);
+        assert_eq!(max_output_tokens("anthropic", "claude-3-opus-20240229"), 4_096);
+        assert_eq!(max_output_tokens("anthropic", "claude-3-5-sonnet-latest"), 8_192);
+        assert_eq!(max_output_tokens("ollama", "qwen2.5:7b"), DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(max_output_tokens("openai_compatible", "mystery"), DEFAULT_MAX_OUTPUT_TOKENS);
+    }
+}
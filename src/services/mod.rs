@@ -10,11 +10,14 @@ pub mod chapter_planning;
 pub mod generation;
 pub mod llm;
 pub mod context;
+pub mod segment;
+pub mod candidates;
 pub mod fanqie;
 pub mod vector_store;
 pub mod consistency;
-pub mod progress;
 pub mod validation;
+pub mod usage;
+pub mod revision;
 
 pub use storage::*;
 pub use scraping::*;
@@ -26,10 +29,12 @@ pub use chapter_planning::*;
 pub use generation::*;
 pub use llm::*;
 pub use context::*;
+pub use segment::{SegmentOptions, SegmentPlan};
 pub use fanqie::*;
 pub use vector_store::*;
 pub use consistency::{ConsistencyCheckResult, ConsistencyChecker};
-pub use progress::*;
+pub use usage::{StageUsage, UsageReport, UsageTracker};
+pub use revision::{DiffKind, DiffSpan, TextChanges};
 pub use validation::{
     CopyrightChecker, ConsistencyChecker as ValidationConsistencyChecker,
     is_common_name, ProjectValidator,
@@ -0,0 +1,116 @@
+//! Chapter revision diffs
+//!
+//! Chinese text has no word boundaries to diff on, so revisions are compared
+//! character by character. Adjacent changes of the same kind are merged so a
+//! rewritten sentence shows as one deletion and one insertion.
+
+use std::time::Duration;
+
+use similar::{ChangeTag, TextDiff};
+
+/// Longest time spent looking for a minimal diff; a coarser one is returned after
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Kind of a diff span
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A run of characters with the same kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffSpan {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+/// Character-level diff between two texts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextChanges {
+    /// Spans in reading order
+    pub spans: Vec<DiffSpan>,
+}
+
+impl TextChanges {
+    /// Compare `old` with `new`
+    pub fn new(old: &str, new: &str) -> Self {
+        let diff = TextDiff::configure().timeout(DIFF_TIMEOUT).diff_chars(old, new);
+
+        let mut spans: Vec<DiffSpan> = Vec::new();
+        for change in diff.iter_all_changes() {
+            let kind = match change.tag() {
+                ChangeTag::Equal => DiffKind::Equal,
+                ChangeTag::Insert => DiffKind::Insert,
+                ChangeTag::Delete => DiffKind::Delete,
+            };
+            match spans.last_mut() {
+                Some(last) if last.kind == kind => last.text.push_str(change.value()),
+                _ => spans.push(DiffSpan { kind, text: change.value().to_string() }),
+            }
+        }
+        Self { spans }
+    }
+
+    /// Characters inserted
+    pub fn inserted(&self) -> usize {
+        self.count(DiffKind::Insert)
+    }
+
+    /// Characters deleted
+    pub fn deleted(&self) -> usize {
+        self.count(DiffKind::Delete)
+    }
+
+    /// Whether the texts are the same
+    pub fn is_unchanged(&self) -> bool {
+        self.spans.iter().all(|s| s.kind == DiffKind::Equal)
+    }
+
+    fn count(&self, kind: DiffKind) -> usize {
+        self.spans.iter().filter(|s| s.kind == kind).map(|s| s.text.chars().count()).sum()
+    }
+}
+
+/// `text` with its middle replaced by "…" when longer than `2 * keep` characters
+///
+/// Used to shorten unchanged stretches between edits.
+pub fn collapse(text: &str, keep: usize) -> String {
+    let count = text.chars().count();
+    if count <= keep * 2 + 1 {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(keep).collect();
+    let tail: String = text.chars().skip(count - keep).collect();
+    format!("{}…{}", head, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_are_merged_per_run() {
+        let changes = TextChanges::new("林风拔出长剑。", "林风拔出短刀，冷笑。");
+        let text: String = changes
+            .spans
+            .iter()
+            .map(|s| match s.kind {
+                DiffKind::Equal => s.text.clone(),
+                DiffKind::Insert => format!("{{+{}+}}", s.text),
+                DiffKind::Delete => format!("[-{}-]", s.text),
+            })
+            .collect();
+        assert_eq!(text, "林风拔出[-长剑-]{+短刀，冷笑+}。");
+        assert_eq!((changes.deleted(), changes.inserted()), (2, 5));
+        assert!(!changes.is_unchanged());
+        assert!(TextChanges::new("相同", "相同").is_unchanged());
+    }
+
+    #[test]
+    fn test_collapse_keeps_both_ends() {
+        assert_eq!(collapse("一二三四五六七八", 2), "一二…七八");
+        assert_eq!(collapse("一二三四五", 2), "一二三四五");
+    }
+}
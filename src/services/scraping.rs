@@ -123,8 +123,7 @@ impl ScrapingService {
                 *tag_counts.entry(tag).or_insert(0) += 1;
             }
         }
-        let tags: Vec<String> = tag_counts.into_iter()
-            .map(|(k, _)| k)
+        let tags: Vec<String> = tag_counts.into_keys()
             .take(10)
             .collect();
 
@@ -609,6 +608,5 @@ mod tests {
         }
 
         // Just verify it runs without panic
-        assert!(true);
     }
 }
@@ -0,0 +1,321 @@
+//! Segmented chapter writing
+//!
+//! One reply holds a few thousand Chinese characters at most, well short of
+//! a 10,000-character chapter. Long chapters are written as a run of
+//! segments instead: each is a continuation call that sees the chapter's
+//! beats and the end of the text so far, and covers the next beats. Writing
+//! stops once the chapter is within its target length range.
+
+use crate::services::context::ChapterBrief;
+use crate::text::{is_sentence_end, tail, MIN_REPEATED_SENTENCE_CHARS};
+
+/// Characters of the chapter so far shown to each continuation
+pub const SEGMENT_TAIL_CHARS: usize = 800;
+
+/// Characters at the end of the text searched for sentences a continuation
+/// repeats
+const SEAM_WINDOW_CHARS: usize = 400;
+
+/// How planned chapters are split into segments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentOptions {
+    /// Characters asked for per segment; derived from the reply limit if `None`
+    pub segment_chars: Option<u32>,
+
+    /// Accepted deviation from the chapter's target length, as a fraction
+    pub tolerance: f32,
+
+    /// Segments allowed beyond the plan when the model writes short
+    pub extra_segments: u32,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            segment_chars: None,
+            tolerance: 0.1,
+            extra_segments: 2,
+        }
+    }
+}
+
+/// How one chapter is split into segments
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentPlan {
+    target: u32,
+    min: u32,
+    max: u32,
+    segment_chars: u32,
+    segments: u32,
+    extra_segments: u32,
+    beats: Vec<String>,
+}
+
+impl SegmentPlan {
+    /// Plan for the chapter of `brief`, asking for `segment_chars` characters per call
+    pub fn new(brief: &ChapterBrief, segment_chars: u32, options: &SegmentOptions) -> Self {
+        let summary = &brief.summary;
+        let target = summary.word_count_estimate.max(1);
+        let segment_chars = segment_chars.max(1);
+        let tolerance = options.tolerance.clamp(0.0, 1.0);
+
+        let mut beats: Vec<String> = summary.key_events.iter().filter(|e| !e.is_empty()).cloned().collect();
+        if summary.is_plot_twist_chapter {
+            if let Some(twist) = &summary.plot_twist_description {
+                beats.push(format!("转折：{}", twist));
+            }
+        }
+        if beats.is_empty() {
+            beats.push(summary.summary.clone());
+        }
+
+        Self {
+            target,
+            min: (target as f32 * (1.0 - tolerance)) as u32,
+            max: (target as f32 * (1.0 + tolerance)).ceil() as u32,
+            segment_chars,
+            segments: target.div_ceil(segment_chars),
+            extra_segments: options.extra_segments,
+            beats,
+        }
+    }
+
+    /// Target length in characters
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Shortest accepted length
+    pub fn min(&self) -> u32 {
+        self.min
+    }
+
+    /// Longest accepted length; longer text is cut at a sentence end
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Segments planned to reach the target
+    pub fn segments(&self) -> u32 {
+        self.segments
+    }
+
+    /// Most segments written, including extras when the model writes short
+    pub fn limit(&self) -> u32 {
+        self.segments + self.extra_segments
+    }
+
+    /// Whether a chapter of `written` characters is long enough
+    pub fn is_done(&self, written: u32) -> bool {
+        written >= self.min
+    }
+
+    /// Chapter beats: the key events, then the twist
+    pub fn beats(&self) -> &[String] {
+        &self.beats
+    }
+
+    /// Segment that covers beat `beat`
+    fn segment_of(&self, beat: usize) -> u32 {
+        (beat as u64 * self.segments as u64 / self.beats.len() as u64) as u32
+    }
+
+    /// Prompt for segment `index` (from 0) with `written` characters already written
+    pub fn instruction(&self, brief: &ChapterBrief, index: u32, written: u32) -> String {
+        let summary = &brief.summary;
+        let remaining = self.target.saturating_sub(written);
+        let ask = remaining.min(self.segment_chars).max(self.segment_chars / 4);
+        let last = index + 1 >= self.segments || remaining <= self.segment_chars;
+
+        let mut text = format!(
+            "请创作{}的第{}段。全章约{}字，分段写作，本段约{}字。\n本章概要：{}\n本章节拍：\n",
+            summary.title,
+            index + 1,
+            self.target,
+            ask,
+            summary.summary
+        );
+        let mut current = Vec::new();
+        for (i, beat) in self.beats.iter().enumerate() {
+            let mark = match self.segment_of(i).cmp(&index) {
+                std::cmp::Ordering::Less => "（已写）",
+                std::cmp::Ordering::Equal => {
+                    current.push(beat.as_str());
+                    "（本段）"
+                }
+                std::cmp::Ordering::Greater => "",
+            };
+            text.push_str(&format!("{}. {}{}\n", i + 1, beat, mark));
+        }
+        if !summary.protagonist_development.is_empty() {
+            text.push_str(&format!("主角成长：{}\n", summary.protagonist_development));
+        }
+
+        if !current.is_empty() {
+            text.push_str(&format!("本段任务：写出“{}”。\n", current.join("”“")));
+        } else if last {
+            text.push_str("本段任务：写完剩余情节。\n");
+        } else {
+            text.push_str("本段任务：推进情节，为下一个节拍做铺垫。\n");
+        }
+
+        text.push_str("要求：只输出正文，不要输出章节标题");
+        if index == 0 {
+            text.push_str(brief.continuity_requirement());
+        } else {
+            text.push_str(&format!(
+                "；本章已写约{}字，从已写内容的最后一句接着写，不要重复已写内容，不要重新开头",
+                written
+            ));
+        }
+        if last {
+            text.push_str("；写完本段即结束本章，给本章一个收尾");
+        } else {
+            text.push_str("；写完本段任务即停，不要结束本章");
+        }
+        text.push_str(&brief.draft_requirement());
+        text.push('。');
+        text
+    }
+}
+
+/// Append a continuation `segment` to `written`, returning what was added
+///
+/// Smooths the seam: drops a restated chapter heading and leading sentences
+/// copied from the end of `written`, and starts a new paragraph unless
+/// `written` stopped mid-sentence.
+pub fn append_segment(written: &mut String, title: &str, segment: &str) -> String {
+    let text = &segment[seam_start(written, title, segment)..];
+    if text.trim().is_empty() {
+        return String::new();
+    }
+
+    let mut added = String::new();
+    let end = written.trim_end_matches([' ', '\t']);
+    if !end.is_empty() && !end.ends_with('\n') && ends_sentence(end) {
+        added.push('\n');
+    }
+    added.push_str(text);
+    written.push_str(&added);
+    added
+}
+
+/// Cut `text` to at most `max` characters, at the last sentence end if any
+pub fn trim_to_length(text: &mut String, max: u32) {
+    let Some((cut, _)) = text.char_indices().nth(max as usize) else {
+        return;
+    };
+    let kept = &text[..cut];
+    let end = kept
+        .char_indices()
+        .rev()
+        .find(|(_, c)| is_sentence_end(*c))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(cut);
+    text.truncate(end);
+    let trimmed = text.trim_end().len();
+    text.truncate(trimmed);
+}
+
+/// Byte offset in `segment` where new text starts
+fn seam_start(written: &str, title: &str, segment: &str) -> usize {
+    let mut rest = segment.trim_start();
+
+    // A restated heading: the title or a short "第N章" line
+    let first_line = rest.lines().next().unwrap_or_default().trim();
+    let is_heading = !first_line.is_empty()
+        && (first_line == title.trim()
+            || (first_line.starts_with('第')
+                && first_line.contains('章')
+                && first_line.chars().count() <= 30
+                && !first_line.contains(is_sentence_end)));
+    if is_heading {
+        rest = rest[rest.find('\n').unwrap_or(rest.len())..].trim_start();
+    }
+
+    // Sentences already at the end of the text
+    let window = tail(written, SEAM_WINDOW_CHARS);
+    while let Some(end) = rest.find(is_sentence_end) {
+        let end = end + rest[end..].chars().next().map_or(0, char::len_utf8);
+        // Keep a closing quote with its sentence
+        let end = end + rest[end..].chars().take_while(|c| matches!(c, '”' | '」' | '』')).map(char::len_utf8).sum::<usize>();
+        let sentence = rest[..end].trim();
+        if sentence.chars().count() < MIN_REPEATED_SENTENCE_CHARS || !window.contains(sentence) {
+            break;
+        }
+        rest = rest[end..].trim_start();
+    }
+
+    segment.len() - rest.len()
+}
+
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end_matches(['”', '」', '』']).ends_with(is_sentence_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ChapterPlan, ChapterSummary};
+    use uuid::Uuid;
+
+    fn brief(target: u32, key_events: &[&str]) -> ChapterBrief {
+        let mut plan = ChapterPlan::new(Uuid::new_v4(), 1);
+        plan.chapters.push(ChapterSummary {
+            number: 1,
+            title: "第1章 出山".to_string(),
+            summary: "林风下山历练".to_string(),
+            key_events: key_events.iter().map(|e| e.to_string()).collect(),
+            protagonist_development: String::new(),
+            word_count_estimate: target,
+            is_plot_twist_chapter: false,
+            plot_twist_description: None,
+        });
+        ChapterBrief::new(None, &plan, 1, None).unwrap()
+    }
+
+    #[test]
+    fn test_plan_spreads_beats_over_segments() {
+        let brief = brief(10_000, &["告别师父", "山下遇袭", "结识苏婉儿", "入城"]);
+        let plan = SegmentPlan::new(&brief, 4000, &SegmentOptions::default());
+        assert_eq!((plan.segments(), plan.limit()), (3, 5));
+        assert_eq!((plan.min(), plan.max()), (9000, 11_000));
+        assert!(!plan.is_done(8999) && plan.is_done(9000));
+
+        let first = plan.instruction(&brief, 0, 0);
+        assert!(first.contains("1. 告别师父（本段）") && first.contains("2. 山下遇袭（本段）"));
+        assert!(first.contains("不要结束本章") && !first.contains("已写约"));
+
+        let last = plan.instruction(&brief, 2, 8000);
+        assert!(last.contains("3. 结识苏婉儿（已写）") && last.contains("4. 入城（本段）"));
+        assert!(last.contains("已写约8000字") && last.contains("本段约2000字") && last.contains("收尾"));
+    }
+
+    #[test]
+    fn test_seams_drop_repeats_and_headings() {
+        let mut text = "林风走出山门。师父的声音还在耳边回响。".to_string();
+        let added = append_segment(&mut text, "第1章 出山", "第1章 出山\n师父的声音还在耳边回响。山路蜿蜒向下。");
+        assert_eq!(added, "\n山路蜿蜒向下。");
+        assert_eq!(text, "林风走出山门。师父的声音还在耳边回响。\n山路蜿蜒向下。");
+
+        // A segment cut mid-sentence is continued in place
+        let mut text = "他握紧了".to_string();
+        append_segment(&mut text, "第1章 出山", "剑柄。");
+        assert_eq!(text, "他握紧了剑柄。");
+
+        // Nothing new
+        let mut text = "山路蜿蜒向下。".to_string();
+        assert_eq!(append_segment(&mut text, "", "山路蜿蜒向下。"), "");
+    }
+
+    #[test]
+    fn test_trim_to_length_ends_at_sentence() {
+        let mut text = "第一句。第二句很长很长".to_string();
+        trim_to_length(&mut text, 8);
+        assert_eq!(text, "第一句。");
+
+        let mut text = "短。".to_string();
+        trim_to_length(&mut text, 8);
+        assert_eq!(text, "短。");
+    }
+}
@@ -3,6 +3,8 @@
 use anyhow::{Context, Result};
 use serde::{de::DeserializeOwned, Serialize};
 use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use uuid::Uuid;
 
@@ -63,7 +65,7 @@ impl StorageService {
         }
 
         // Sort by creation date (newest first)
-        projects.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        projects.sort_by_key(|p| std::cmp::Reverse(p.created_at));
 
         Ok(projects)
     }
@@ -76,10 +78,12 @@ impl StorageService {
         // Create project directory structure
         Self::create_project_dirs(&project_path)?;
 
-        Ok(Self {
+        let storage = Self {
             base_path: project_path,
             project_id: Some(project_id),
-        })
+        };
+        storage.migrate_legacy_chapter()?;
+        Ok(storage)
     }
 
     /// Create project directory structure
@@ -173,6 +177,301 @@ impl StorageService {
     }
 }
 
+impl StorageService {
+    /// Path of the entity with `key`
+    fn keyed_path<T: KeyedStorageKey>(&self, key: &T::Key) -> PathBuf {
+        self.base_path
+            .join(T::storage_folder())
+            .join(format!("{}.json", T::storage_filename(key)))
+    }
+
+    /// Save an entity to its own file, replacing any with the same key
+    pub fn save_keyed<T: KeyedStorageKey + Serialize>(&self, entity: &T) -> Result<()> {
+        let path = self.keyed_path::<T>(&entity.storage_key());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(entity)
+            .context("Failed to serialize entity")?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write entity to {:?}", path))?;
+
+        tracing::debug!("Saved entity to {:?}", path);
+        Ok(())
+    }
+
+    /// Load the entity with `key`
+    pub fn load_keyed<T: KeyedStorageKey + DeserializeOwned>(&self, key: &T::Key) -> Result<Option<T>> {
+        let path = self.keyed_path::<T>(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read entity from {:?}", path))?;
+        let entity = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to deserialize entity from {:?}", path))?;
+        Ok(Some(entity))
+    }
+
+    /// Keys of all stored entities, in ascending order
+    pub fn list_keys<T: KeyedStorageKey>(&self) -> Result<Vec<T::Key>> {
+        let folder = self.base_path.join(T::storage_folder());
+        if !folder.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&folder)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(key) = path.file_stem().and_then(|s| s.to_str()).and_then(T::parse_key) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Delete the entity with `key`; returns whether it existed
+    pub fn delete_keyed<T: KeyedStorageKey>(&self, key: &T::Key) -> Result<bool> {
+        let path = self.keyed_path::<T>(key);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to delete entity {:?}", path))?;
+        tracing::debug!("Deleted entity from {:?}", path);
+        Ok(true)
+    }
+
+    /// Save a chapter as `chapters/NNNN.json`, adding a revision from `source`
+    /// to its history
+    pub fn save_chapter(&self, chapter: &GeneratedChapter, source: RevisionSource) -> Result<ChapterRevision> {
+        self.save_chapter_revision(chapter, ChapterRevision::new(chapter, source))
+    }
+
+    /// Save a chapter and append `revision`, which should hold its text, to
+    /// the chapter's history, returning the revision as numbered
+    ///
+    /// Saving the same title and text as the latest revision adds nothing.
+    /// If the saved chapter differs from the latest revision, its text is
+    /// recorded first so it is not lost: as a manual revision when the file
+    /// was edited by hand, or as a generated one when the chapter was saved
+    /// before histories existed.
+    pub fn save_chapter_revision(&self, chapter: &GeneratedChapter, mut revision: ChapterRevision) -> Result<ChapterRevision> {
+        let number = chapter.chapter_number;
+        let mut latest = self.list_revisions(number)?.pop();
+        if let Some(existing) = self.load_chapter(number)? {
+            let recorded = latest
+                .as_ref()
+                .is_some_and(|l| l.content == existing.content && l.title == existing.title);
+            if !recorded {
+                let mut current = match &latest {
+                    Some(_) => ChapterRevision::new(&existing, RevisionSource::Manual),
+                    None => {
+                        let mut first = ChapterRevision::new(&existing, RevisionSource::Generated);
+                        first.created_at = existing.updated_at;
+                        first
+                    }
+                };
+                current.revision = latest.as_ref().map_or(1, |l| l.revision + 1);
+                self.append_revision(&current)?;
+                latest = Some(current);
+            }
+        }
+
+        self.save_keyed(chapter)?;
+        if let Some(latest) = latest {
+            if latest.content == revision.content && latest.title == revision.title {
+                return Ok(latest);
+            }
+            revision.revision = latest.revision + 1;
+        } else {
+            revision.revision = 1;
+        }
+        self.append_revision(&revision)?;
+        Ok(revision)
+    }
+
+    /// Every revision of chapter `number`, oldest first
+    pub fn list_revisions(&self, number: u32) -> Result<Vec<ChapterRevision>> {
+        let path = self.history_path(number);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read revision history {:?}", path))?;
+        // A damaged line, such as one cut short by a crash, costs that
+        // revision rather than the whole history
+        let revisions = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match serde_json::from_str(line) {
+                Ok(revision) => Some(revision),
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable line {} of revision history {:?}: {}", i + 1, path, e);
+                    None
+                }
+            })
+            .collect();
+        Ok(revisions)
+    }
+
+    /// Revision `revision` of chapter `number`
+    pub fn load_revision(&self, number: u32, revision: u32) -> Result<Option<ChapterRevision>> {
+        Ok(self.list_revisions(number)?.into_iter().find(|r| r.revision == revision))
+    }
+
+    /// Make revision `revision` the current text of chapter `number`
+    ///
+    /// The history is not rewritten: the restored text is added as a new
+    /// revision with the source and parameters of the old one.
+    pub fn restore_revision(&self, number: u32, revision: u32) -> Result<ChapterRevision> {
+        let old = self.load_revision(number, revision)?
+            .with_context(|| format!("Chapter {} has no revision {}", number, revision))?;
+        let mut chapter = match self.load_chapter(number)? {
+            Some(chapter) => chapter,
+            None => GeneratedChapter::new(
+                self.project_id.unwrap_or_default(),
+                number,
+                old.title.clone(),
+                String::new(),
+                old.generation_params.clone(),
+            ),
+        };
+        chapter.title = old.title.clone();
+        chapter.generation_params = old.generation_params.clone();
+        chapter.set_content(old.content.clone());
+
+        let mut restored = ChapterRevision::new(&chapter, old.source);
+        restored.instruction = old.instruction;
+        restored.restored_from = Some(revision);
+        self.save_chapter_revision(&chapter, restored)
+    }
+
+    /// `chapters/history/NNNN.jsonl`: one revision per line, only ever appended to
+    fn history_path(&self, number: u32) -> PathBuf {
+        self.base_path
+            .join(GeneratedChapter::storage_folder())
+            .join("history")
+            .join(format!("{}.jsonl", GeneratedChapter::storage_filename(&number)))
+    }
+
+    fn append_revision(&self, revision: &ChapterRevision) -> Result<()> {
+        let path = self.history_path(revision.chapter_number);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(revision).context("Failed to serialize revision")?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open revision history {:?}", path))?;
+        // Start a new line after a last line cut short, so the new revision
+        // stays readable
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n');
+            }
+        }
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("Failed to append to revision history {:?}", path))?;
+
+        tracing::debug!("Saved revision {} of chapter {}", revision.revision, revision.chapter_number);
+        Ok(())
+    }
+
+    /// Load chapter `number`
+    pub fn load_chapter(&self, number: u32) -> Result<Option<GeneratedChapter>> {
+        self.load_keyed(&number)
+    }
+
+    /// Numbers of all saved chapters, in ascending order
+    pub fn list_chapters(&self) -> Result<Vec<u32>> {
+        self.list_keys::<GeneratedChapter>()
+    }
+
+    /// Load the saved chapters within `range`, in order
+    pub fn load_chapters(&self, range: RangeInclusive<u32>) -> Result<Vec<GeneratedChapter>> {
+        let mut chapters = Vec::new();
+        for number in self.list_chapters()?.into_iter().filter(|n| range.contains(n)) {
+            if let Some(chapter) = self.load_chapter(number)? {
+                chapters.push(chapter);
+            }
+        }
+        Ok(chapters)
+    }
+
+    /// Delete chapter `number`; returns whether it existed
+    pub fn delete_chapter(&self, number: u32) -> Result<bool> {
+        self.delete_keyed::<GeneratedChapter>(&number)
+    }
+
+    /// Keep `alternates` as the alternate drafts of chapter `number`, under
+    /// `chapters/candidates/`, replacing any from an earlier run
+    pub fn save_candidates(&self, number: u32, alternates: &[ChapterCandidate]) -> Result<()> {
+        for key in self.list_keys::<ChapterCandidate>()?.into_iter().filter(|(n, _)| *n == number) {
+            self.delete_keyed::<ChapterCandidate>(&key)?;
+        }
+        for candidate in alternates {
+            self.save_keyed(candidate)?;
+        }
+        Ok(())
+    }
+
+    /// Alternate drafts of chapter `number`, best first
+    pub fn list_candidates(&self, number: u32) -> Result<Vec<ChapterCandidate>> {
+        let mut candidates = Vec::new();
+        for key in self.list_keys::<ChapterCandidate>()?.into_iter().filter(|(n, _)| *n == number) {
+            if let Some(candidate) = self.load_keyed::<ChapterCandidate>(&key)? {
+                candidates.push(candidate);
+            }
+        }
+        candidates.sort_by_key(|c| c.rank);
+        Ok(candidates)
+    }
+
+    /// Move a `chapters/chapter.json` written by older versions, which kept
+    /// only the latest chapter, to its numbered file
+    ///
+    /// If that file already exists the legacy file is kept as
+    /// `chapter.json.bak` instead.
+    fn migrate_legacy_chapter(&self) -> Result<()> {
+        let legacy = self.base_path.join(GeneratedChapter::storage_folder()).join("chapter.json");
+        if !legacy.exists() {
+            return Ok(());
+        }
+
+        let json = fs::read_to_string(&legacy)
+            .with_context(|| format!("Failed to read legacy chapter {:?}", legacy))?;
+        let chapter: GeneratedChapter = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse legacy chapter {:?}", legacy))?;
+
+        if self.load_chapter(chapter.chapter_number)?.is_some() {
+            fs::rename(&legacy, legacy.with_extension("json.bak"))?;
+            tracing::warn!("Chapter {} already exists; kept legacy chapter.json as a backup", chapter.chapter_number);
+        } else {
+            self.save_keyed(&chapter)?;
+            fs::remove_file(&legacy)?;
+            tracing::info!("Migrated legacy chapter.json to chapter {}", chapter.chapter_number);
+        }
+        Ok(())
+    }
+}
+
 /// Trait for storage key
 pub trait StorageKey {
     /// Storage folder name
@@ -182,8 +481,25 @@ pub trait StorageKey {
     fn storage_filename() -> &'static str;
 }
 
+/// Trait for entities stored one file per key in a shared folder
+pub trait KeyedStorageKey {
+    type Key: Ord;
+
+    /// Storage folder name
+    fn storage_folder() -> &'static str;
+
+    /// Storage filename (without extension) of the entity with `key`
+    fn storage_filename(key: &Self::Key) -> String;
+
+    /// Key of a storage filename, `None` for files that are not entities
+    fn parse_key(filename: &str) -> Option<Self::Key>;
+
+    /// Key of this entity
+    fn storage_key(&self) -> Self::Key;
+}
+
 // Import models for storage key implementations
-use crate::models::{NovelProject, NovelOutline, ChapterPlan, GeneratedChapter, FeasibilityReport};
+use crate::models::{NovelProject, NovelOutline, ChapterPlan, GeneratedChapter, FeasibilityReport, UsageLedger, GenerationJob, ChapterRevision, RevisionSource, ChapterCandidate};
 
 impl StorageKey for NovelProject {
     fn storage_folder() -> &'static str {
@@ -215,13 +531,44 @@ impl StorageKey for ChapterPlan {
     }
 }
 
-impl StorageKey for GeneratedChapter {
+impl KeyedStorageKey for GeneratedChapter {
+    type Key = u32;
+
     fn storage_folder() -> &'static str {
         "chapters"
     }
 
-    fn storage_filename() -> &'static str {
-        "chapter"
+    fn storage_filename(key: &u32) -> String {
+        format!("{:04}", key)
+    }
+
+    fn parse_key(filename: &str) -> Option<u32> {
+        filename.parse().ok()
+    }
+
+    fn storage_key(&self) -> u32 {
+        self.chapter_number
+    }
+}
+
+impl KeyedStorageKey for ChapterCandidate {
+    type Key = (u32, u32);
+
+    fn storage_folder() -> &'static str {
+        "chapters/candidates"
+    }
+
+    fn storage_filename(key: &(u32, u32)) -> String {
+        format!("{:04}-{}", key.0, key.1)
+    }
+
+    fn parse_key(filename: &str) -> Option<(u32, u32)> {
+        let (chapter, candidate) = filename.split_once('-')?;
+        Some((chapter.parse().ok()?, candidate.parse().ok()?))
+    }
+
+    fn storage_key(&self) -> (u32, u32) {
+        (self.chapter.chapter_number, self.candidate)
     }
 }
 
@@ -235,6 +582,26 @@ impl StorageKey for FeasibilityReport {
     }
 }
 
+impl StorageKey for UsageLedger {
+    fn storage_folder() -> &'static str {
+        ""  // Next to project.json
+    }
+
+    fn storage_filename() -> &'static str {
+        "usage"
+    }
+}
+
+impl StorageKey for GenerationJob {
+    fn storage_folder() -> &'static str {
+        "jobs"
+    }
+
+    fn storage_filename() -> &'static str {
+        "generation"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -258,4 +625,179 @@ mod tests {
         assert!(loaded.is_some());
         assert_eq!(loaded.unwrap().name, "Test Novel");
     }
+
+    fn chapter(project_id: Uuid, number: u32) -> GeneratedChapter {
+        GeneratedChapter::new(
+            project_id,
+            number,
+            format!("第{}章", number),
+            format!("第{}章正文", number),
+            Default::default(),
+        )
+    }
+
+    #[test]
+    fn test_chapters_stored_one_file_each() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+
+        for number in [3, 1, 2, 10] {
+            storage.save_chapter(&chapter(project_id, number), RevisionSource::Generated).unwrap();
+        }
+        assert!(storage.base_path().join("chapters/0010.json").exists());
+        assert_eq!(storage.list_chapters().unwrap(), vec![1, 2, 3, 10]);
+        assert_eq!(storage.load_chapter(2).unwrap().unwrap().content, "第2章正文");
+        assert!(storage.load_chapter(4).unwrap().is_none());
+
+        let range: Vec<u32> = storage.load_chapters(2..=9).unwrap().iter().map(|c| c.chapter_number).collect();
+        assert_eq!(range, vec![2, 3]);
+
+        assert!(storage.delete_chapter(3).unwrap());
+        assert!(!storage.delete_chapter(3).unwrap());
+        assert_eq!(storage.list_chapters().unwrap(), vec![1, 2, 10]);
+    }
+
+    #[test]
+    fn test_alternate_drafts_replace_earlier_ones() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+        let candidate = |number: u32, candidate: u32, rank: u32| ChapterCandidate {
+            candidate,
+            rank,
+            score: Default::default(),
+            chapter: chapter(project_id, number),
+        };
+
+        storage.save_candidates(5, &[candidate(5, 1, 2), candidate(5, 2, 3)]).unwrap();
+        storage.save_candidates(6, &[candidate(6, 1, 2)]).unwrap();
+        storage.save_candidates(5, &[candidate(5, 3, 3), candidate(5, 1, 2)]).unwrap();
+        assert!(storage.base_path().join("chapters/candidates/0005-3.json").exists());
+
+        let alternates: Vec<u32> = storage.list_candidates(5).unwrap().iter().map(|c| c.candidate).collect();
+        assert_eq!(alternates, vec![1, 3]);
+        assert_eq!(storage.list_candidates(6).unwrap().len(), 1);
+        // Not mistaken for chapters
+        assert!(storage.list_chapters().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_revision_history_is_append_only() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+
+        let original = chapter(project_id, 1);
+        storage.save_chapter(&original, RevisionSource::Generated).unwrap();
+        let mut revised = original.clone();
+        revised.set_content("修改后的正文".to_string());
+        let revision = ChapterRevision::new(&revised, RevisionSource::Revised).with_instruction("改短");
+        assert_eq!(storage.save_chapter_revision(&revised, revision).unwrap().revision, 2);
+        // Saving unchanged text adds nothing
+        assert_eq!(storage.save_chapter(&revised, RevisionSource::Manual).unwrap().revision, 2);
+
+        let restored = storage.restore_revision(1, 1).unwrap();
+        assert_eq!((restored.revision, restored.restored_from), (3, Some(1)));
+        assert_eq!(restored.source, RevisionSource::Generated);
+        assert_eq!(storage.load_chapter(1).unwrap().unwrap().content, "第1章正文");
+
+        let history = storage.list_revisions(1).unwrap();
+        let contents: Vec<&str> = history.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents, ["第1章正文", "修改后的正文", "第1章正文"]);
+        assert_eq!(history[1].instruction.as_deref(), Some("改短"));
+        assert_eq!(storage.list_chapters().unwrap(), vec![1]);
+        assert!(storage.restore_revision(1, 9).is_err());
+    }
+
+    #[test]
+    fn test_truncated_history_line_is_skipped() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+
+        let original = chapter(project_id, 3);
+        storage.save_chapter(&original, RevisionSource::Generated).unwrap();
+        let mut revised = original.clone();
+        revised.set_content("修改后的正文".to_string());
+        storage.save_chapter(&revised, RevisionSource::Manual).unwrap();
+        // Cut the last line short, as a crash mid-write would
+        let path = storage.history_path(3);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 20]).unwrap();
+
+        let history = storage.list_revisions(3).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content, "第3章正文");
+
+        // Later revisions still save and read back
+        let mut rewritten = original.clone();
+        rewritten.set_content("重新改写".to_string());
+        let revision = ChapterRevision::new(&rewritten, RevisionSource::Revised);
+        storage.save_chapter_revision(&rewritten, revision).unwrap();
+        let contents: Vec<String> = storage.list_revisions(3).unwrap().into_iter().map(|r| r.content).collect();
+        assert_eq!(contents.first().map(String::as_str), Some("第3章正文"));
+        assert_eq!(contents.last().map(String::as_str), Some("重新改写"));
+    }
+
+    #[test]
+    fn test_chapter_saved_before_history_keeps_its_text() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+
+        let original = chapter(project_id, 4);
+        storage.save_keyed(&original).unwrap();
+        let mut edited = original.clone();
+        edited.set_content("手动修改".to_string());
+        storage.save_chapter(&edited, RevisionSource::Manual).unwrap();
+
+        let history = storage.list_revisions(4).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].source, history[0].content.as_str()), (RevisionSource::Generated, "第4章正文"));
+        assert_eq!((history[1].source, history[1].revision), (RevisionSource::Manual, 2));
+    }
+
+    #[test]
+    fn test_chapter_edited_on_disk_is_kept_as_manual_revision() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+
+        let original = chapter(project_id, 2);
+        storage.save_chapter(&original, RevisionSource::Generated).unwrap();
+        // Edited by hand in chapters/0002.json
+        let mut edited = original.clone();
+        edited.set_content("手动修改".to_string());
+        storage.save_keyed(&edited).unwrap();
+
+        let mut revised = original.clone();
+        revised.set_content("重新改写".to_string());
+        let revision = ChapterRevision::new(&revised, RevisionSource::Revised).with_instruction("改写");
+        assert_eq!(storage.save_chapter_revision(&revised, revision).unwrap().revision, 3);
+
+        let history = storage.list_revisions(2).unwrap();
+        let saved: Vec<(u32, RevisionSource, &str)> =
+            history.iter().map(|r| (r.revision, r.source, r.content.as_str())).collect();
+        assert_eq!(saved, [
+            (1, RevisionSource::Generated, "第2章正文"),
+            (2, RevisionSource::Manual, "手动修改"),
+            (3, RevisionSource::Revised, "重新改写"),
+        ]);
+    }
+
+    #[test]
+    fn test_legacy_chapter_json_is_migrated() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+        let chapters = dir.path().join("projects").join(project_id.to_string()).join("chapters");
+        fs::create_dir_all(&chapters).unwrap();
+        let legacy = chapter(project_id, 7);
+        fs::write(chapters.join("chapter.json"), serde_json::to_string(&legacy).unwrap()).unwrap();
+
+        let storage = StorageService::new_project(dir.path(), project_id).unwrap();
+        assert!(!chapters.join("chapter.json").exists());
+        assert_eq!(storage.list_chapters().unwrap(), vec![7]);
+        assert_eq!(storage.load_chapter(7).unwrap().unwrap().id, legacy.id);
+    }
 }
@@ -0,0 +1,168 @@
+//! Token usage tracking and cost reports
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::config::ModelPricing;
+use crate::models::{TokenUsage, UsageLedger, UsageStage};
+use crate::services::StorageService;
+
+/// Records LLM usage into a project's ledger
+///
+/// The ledger is written back after every call so an interrupted run keeps
+/// what it has already spent.
+pub struct UsageTracker {
+    storage: StorageService,
+    ledger: Mutex<UsageLedger>,
+}
+
+impl UsageTracker {
+    /// Open the ledger of a project, starting an empty one if none exists
+    pub fn open(base_path: impl Into<PathBuf>, project_id: Uuid) -> Result<Self> {
+        let storage = StorageService::new_project(base_path, project_id)?;
+        let ledger = storage
+            .load::<UsageLedger>()?
+            .unwrap_or_else(|| UsageLedger::new(project_id));
+
+        Ok(Self {
+            storage,
+            ledger: Mutex::new(ledger),
+        })
+    }
+
+    /// Record one call and save the ledger
+    pub fn record(&self, stage: UsageStage, provider: &str, model: &str, usage: TokenUsage) -> Result<()> {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.record(stage, provider, model, usage);
+        self.storage.save(&*ledger)
+    }
+
+    /// Snapshot of the ledger
+    pub fn ledger(&self) -> UsageLedger {
+        self.ledger.lock().unwrap().clone()
+    }
+}
+
+/// Usage of one stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageUsage {
+    pub stage: UsageStage,
+    pub calls: u32,
+    pub usage: TokenUsage,
+    pub cost: f64,
+}
+
+/// Usage and cost broken down by stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageReport {
+    /// One entry per stage, in pipeline order
+    pub stages: Vec<StageUsage>,
+    pub total: TokenUsage,
+    pub total_cost: f64,
+    /// `provider/model` pairs without a configured price
+    pub unpriced: Vec<String>,
+}
+
+impl UsageReport {
+    pub fn build(ledger: &UsageLedger, pricing: &[ModelPricing]) -> Self {
+        let mut stages: Vec<StageUsage> = UsageStage::ALL
+            .iter()
+            .map(|&stage| StageUsage { stage, calls: 0, usage: TokenUsage::default(), cost: 0.0 })
+            .collect();
+        let mut unpriced = Vec::new();
+
+        for record in &ledger.records {
+            let entry = stages.iter_mut().find(|s| s.stage == record.stage).unwrap();
+            entry.calls += 1;
+            entry.usage += record.usage;
+
+            match price_for(pricing, &record.provider, &record.model) {
+                Some(price) => entry.cost += cost(price, &record.usage),
+                None => {
+                    let key = format!("{}/{}", record.provider, record.model);
+                    if !unpriced.contains(&key) {
+                        unpriced.push(key);
+                    }
+                }
+            }
+        }
+
+        Self {
+            total: ledger.total(),
+            total_cost: stages.iter().map(|s| s.cost).sum(),
+            stages,
+            unpriced,
+        }
+    }
+}
+
+/// Price for a model, preferring an exact model entry over a provider-wide one
+pub fn price_for<'a>(pricing: &'a [ModelPricing], provider: &str, model: &str) -> Option<&'a ModelPricing> {
+    let provider_entries = pricing.iter().filter(|p| p.provider.eq_ignore_ascii_case(provider));
+    provider_entries
+        .clone()
+        .find(|p| p.model.as_deref() == Some(model))
+        .or_else(|| provider_entries.clone().find(|p| p.model.is_none()))
+}
+
+/// Cost of `usage` at `price`
+pub fn cost(price: &ModelPricing, usage: &TokenUsage) -> f64 {
+    (usage.prompt_tokens as f64 * price.input_per_million
+        + usage.completion_tokens as f64 * price.output_per_million)
+        / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn pricing() -> Vec<ModelPricing> {
+        vec![
+            ModelPricing {
+                provider: "qwen".to_string(),
+                model: None,
+                input_per_million: 1.0,
+                output_per_million: 2.0,
+            },
+            ModelPricing {
+                provider: "qwen".to_string(),
+                model: Some("qwen-max".to_string()),
+                input_per_million: 10.0,
+                output_per_million: 20.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_report_by_stage() {
+        let mut ledger = UsageLedger::new(Uuid::new_v4());
+        ledger.record(UsageStage::Generate, "qwen", "qwen-turbo", TokenUsage::new(1_000_000, 500_000));
+        ledger.record(UsageStage::Generate, "qwen", "qwen-max", TokenUsage::new(100_000, 0));
+        ledger.record(UsageStage::Check, "openai", "gpt-4o", TokenUsage::new(10, 10));
+
+        let report = UsageReport::build(&ledger, &pricing());
+        let generate = report.stages.iter().find(|s| s.stage == UsageStage::Generate).unwrap();
+        assert_eq!(generate.calls, 2);
+        assert!((generate.cost - 3.0).abs() < 1e-9);
+        assert_eq!(report.stages.len(), UsageStage::ALL.len());
+        assert_eq!(report.total, TokenUsage::new(1_100_010, 500_010));
+        assert_eq!(report.unpriced, vec!["openai/gpt-4o".to_string()]);
+    }
+
+    #[test]
+    fn test_tracker_persists_ledger() {
+        let dir = tempdir().unwrap();
+        let project_id = Uuid::new_v4();
+
+        let tracker = UsageTracker::open(dir.path(), project_id).unwrap();
+        tracker.record(UsageStage::Outline, "qwen", "qwen-turbo", TokenUsage::new(3, 4)).unwrap();
+
+        let reopened = UsageTracker::open(dir.path(), project_id).unwrap();
+        assert_eq!(reopened.ledger().records.len(), 1);
+        assert_eq!(reopened.ledger().total(), TokenUsage::new(3, 4));
+    }
+}
@@ -273,7 +273,7 @@ impl ConsistencyChecker {
         let keyword_count = matched_keywords.len() as f32;
         let antonym_penalty = mismatched_elements.len() as f32 * 0.3;
         let score = (keyword_count / 10.0).min(1.0) - antonym_penalty;
-        let score = score.max(0.0).min(1.0);
+        let score = score.clamp(0.0, 1.0);
 
         // Generate warnings
         if matched_keywords.is_empty() {
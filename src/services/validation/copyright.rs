@@ -74,7 +74,7 @@ impl CopyrightChecker {
             };
             self.known_characters
                 .entry(genre.to_string())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(entry);
         }
     }
@@ -100,17 +100,16 @@ impl CopyrightChecker {
         // Check for partial matches (simpler matching)
         for characters in self.known_characters.values() {
             for entry in characters {
-                if entry.name.to_lowercase().contains(&name_lower)
-                    || name_lower.contains(&entry.name.to_lowercase())
+                if (entry.name.to_lowercase().contains(&name_lower)
+                    || name_lower.contains(&entry.name.to_lowercase()))
+                    && entry.name.len() > 2 && name.len() > 2
                 {
-                    if entry.name.len() > 2 && name.len() > 2 {
-                        return CopyrightCheckResult::risky(
-                            name,
-                            RiskLevel::Medium,
-                            Some(entry.source.clone()),
-                            entry.alternatives.clone(),
-                        );
-                    }
+                    return CopyrightCheckResult::risky(
+                        name,
+                        RiskLevel::Medium,
+                        Some(entry.source.clone()),
+                        entry.alternatives.clone(),
+                    );
                 }
             }
         }
@@ -169,6 +168,6 @@ mod tests {
         let checker = CopyrightChecker::new();
         let result = checker.check("萧云", None);
         // Should suggest alternatives
-        assert!(result.suggested_alternatives.len() > 0 || !result.is_potential_duplicate);
+        assert!(!result.suggested_alternatives.is_empty() || !result.is_potential_duplicate);
     }
 }
@@ -1,5 +1,7 @@
 //! Vector Store Service
 
+use std::sync::Mutex;
+
 use anyhow::Result;
 
 /// Vector storage interface
@@ -14,28 +16,39 @@ pub struct SearchResult {
     pub payload: String,
 }
 
-/// In-memory vector store (simple implementation)
-#[allow(dead_code)]
+/// In-memory vector store ranking by cosine similarity
 pub struct SimpleVectorStore {
-    vectors: Vec<(String, Vec<f32>, String)>,
+    vectors: Mutex<Vec<(String, Vec<f32>, String)>>,
 }
 
 impl SimpleVectorStore {
     pub fn new() -> Self {
-        Self { vectors: Vec::new() }
+        Self { vectors: Mutex::new(Vec::new()) }
     }
 }
 
 impl VectorStore for SimpleVectorStore {
-    #[allow(unused_variables)]
+    /// Add an entry, replacing any existing entry with the same id
     fn add(&self, id: &str, vector: &[f32], payload: &str) -> Result<()> {
-        // Simple implementation - store as-is
+        let mut vectors = self.vectors.lock().unwrap();
+        vectors.retain(|(existing, _, _)| existing != id);
+        vectors.push((id.to_string(), vector.to_vec(), payload.to_string()));
         Ok(())
     }
 
-    fn search(&self, _query: &[f32], top_k: usize) -> Result<Vec<SearchResult>> {
-        // Simple implementation - return empty
-        Ok(Vec::with_capacity(top_k))
+    fn search(&self, query: &[f32], top_k: usize) -> Result<Vec<SearchResult>> {
+        let vectors = self.vectors.lock().unwrap();
+        let mut results: Vec<SearchResult> = vectors
+            .iter()
+            .map(|(id, vector, payload)| SearchResult {
+                id: id.clone(),
+                score: cosine_similarity(query, vector),
+                payload: payload.clone(),
+            })
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(top_k);
+        Ok(results)
     }
 }
 
@@ -44,3 +57,18 @@ impl Default for SimpleVectorStore {
         Self::new()
     }
 }
+
+/// Cosine similarity, 0.0 when either vector is zero or the lengths differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
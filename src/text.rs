@@ -0,0 +1,59 @@
+//! Text helpers
+//!
+//! Novel text is mostly Chinese, so text is cut by characters rather than
+//! bytes or words, and sentences end in full-width punctuation as often as
+//! in ASCII.
+
+/// Repeated sentences shorter than this are left alone; they may be deliberate
+pub const MIN_REPEATED_SENTENCE_CHARS: usize = 4;
+
+/// Last `chars` characters of `text`
+pub fn tail(text: &str, chars: usize) -> &str {
+    let count = text.chars().count();
+    match text.char_indices().nth(count.saturating_sub(chars)) {
+        Some((i, _)) => &text[i..],
+        None => "",
+    }
+}
+
+/// Whether `c` is a CJK ideograph
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{4E00}'..='\u{9FFF}'    // CJK Unified Ideographs
+        | '\u{3400}'..='\u{4DBF}'  // Extension A
+        | '\u{F900}'..='\u{FAFF}'  // Compatibility Ideographs
+        | '\u{20000}'..='\u{2A6DF}' // Extension B
+    )
+}
+
+/// Whether `c` is punctuation that ends a sentence
+pub fn is_sentence_end(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '…' | '.' | '!' | '?')
+}
+
+/// Whether `c` ends a sentence or a line
+///
+/// Dialogue and scene breaks often end a line without punctuation.
+pub fn is_sentence_break(c: char) -> bool {
+    is_sentence_end(c) || c == '\n'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_counts_characters() {
+        assert_eq!(tail("林风握紧了剑。", 3), "了剑。");
+        assert_eq!(tail("剑。", 10), "剑。");
+        assert_eq!(tail("剑。", 0), "");
+    }
+
+    #[test]
+    fn test_sentence_ends() {
+        assert!(is_sentence_end('。') && is_sentence_end('?'));
+        assert!(!is_sentence_end('\n') && is_sentence_break('\n'));
+        assert!(!is_sentence_break('，'));
+        assert!(is_cjk('剑') && !is_cjk('。') && !is_cjk('a'));
+    }
+}
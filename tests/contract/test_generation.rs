@@ -11,6 +11,7 @@ mod tests {
             model: "qwen2.5".to_string(),
             temperature: 0.8,
             max_tokens: 4096,
+            ..Default::default()
         };
 
         let chapter = GeneratedChapter::new(
@@ -33,6 +34,7 @@ mod tests {
             model: "test".to_string(),
             temperature: 0.8,
             max_tokens: 4096,
+            ..Default::default()
         };
 
         let mut chapter = GeneratedChapter::new(
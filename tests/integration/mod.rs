@@ -3,3 +3,9 @@
 pub mod test_scraping;
 pub mod test_outline;
 pub mod test_consistency;
+pub mod test_openai_compatible;
+pub mod test_anthropic;
+pub mod test_replay;
+pub mod test_ollama;
+pub mod test_embedding;
+pub mod test_generation;
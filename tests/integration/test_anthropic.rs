@@ -0,0 +1,146 @@
+//! Integration tests for the Anthropic provider against a local stub server
+
+#[cfg(test)]
+mod tests {
+    use ai_novel_agent::config::{Config, ResponseCacheConfig};
+    use ai_novel_agent::models::TokenUsage;
+    use ai_novel_agent::services::generation::GenerationService;
+    use ai_novel_agent::services::llm::{create_client_with_config, GenerationOptions, LlmError, RetryPolicy};
+    use ai_novel_agent::services::ChapterBrief;
+    use futures::StreamExt;
+    use serde_json::json;
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    use crate::support::story::xianxia_story;
+    use crate::support::stub_server::{StubResponse, StubServer};
+
+    fn anthropic_config(base_url: &str) -> ai_novel_agent::config::LlmConfig {
+        let mut config = Config::default().llm;
+        config.provider = "anthropic".to_string();
+        config.api_key = "test-key".to_string();
+        config.base_url = Some(format!("{}/v1", base_url));
+        config.model = Some("claude-test".to_string());
+        config.max_tokens = 256;
+        config
+    }
+
+    /// Test the Messages API request shape and response parsing
+    #[tokio::test]
+    async fn test_generate_against_local_server() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "夜色" }, { "type": "text", "text": "渐深。" }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 12, "output_tokens": 5 }
+            }))
+        }).await;
+
+        let client = create_client_with_config(&anthropic_config(&server.base_url)).unwrap();
+        let completion = client.generate("背景", "写一句话").await.unwrap();
+        assert_eq!(completion.text, "夜色渐深。");
+        assert_eq!(completion.usage, TokenUsage::new(12, 5));
+        assert_eq!(completion.provider, "anthropic");
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/v1/messages");
+        assert_eq!(request.header("x-api-key"), Some("test-key"));
+        assert_eq!(request.header("anthropic-version"), Some("2023-06-01"));
+
+        let body = request.json();
+        assert_eq!(body["system"], "背景");
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(body["messages"], json!([
+            { "role": "user", "content": [{ "type": "text", "text": "写一句话" }] }
+        ]));
+    }
+
+    /// Test streamed deltas and usage split across message_start and message_delta
+    #[tokio::test]
+    async fn test_stream_against_local_server() {
+        let server = StubServer::start(|_| {
+            StubResponse::sse(&[
+                r#"{"type":"message_start","message":{"usage":{"input_tokens":8,"output_tokens":1}}}"#,
+                r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+                r#"{"type":"ping"}"#,
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"第一"}}"#,
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"段"}}"#,
+                r#"{"type":"content_block_stop","index":0}"#,
+                r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":2}}"#,
+                r#"{"type":"message_stop"}"#,
+            ])
+        }).await;
+
+        let client = create_client_with_config(&anthropic_config(&server.base_url)).unwrap();
+        let mut stream = client.generate_stream("", "写").await.unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, vec!["第一".to_string(), "段".to_string()]);
+        assert_eq!(stream.usage(), TokenUsage::new(8, 2));
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    /// Test Anthropic's error body is classified by its error type
+    #[tokio::test]
+    async fn test_error_shape_is_typed() {
+        let server = StubServer::start(|_| {
+            StubResponse::status(429, r#"{"type":"error","error":{"type":"rate_limit_error","message":"Number of request tokens has exceeded your rate limit"}}"#)
+        }).await;
+
+        let client = create_client_with_config(&anthropic_config(&server.base_url))
+            .unwrap()
+            .with_retry_policy(RetryPolicy::none());
+        let err = client.generate("", "hi").await.unwrap_err();
+        match err.downcast_ref::<LlmError>() {
+            Some(LlmError::RateLimited { message, .. }) => {
+                assert_eq!(message, "Number of request tokens has exceeded your rate limit")
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    /// Test candidate drafts still differ, and miss the cache, when the seed is not sent
+    #[tokio::test]
+    async fn test_candidate_drafts_differ_without_seed() {
+        let server = StubServer::start(|request| {
+            let prompt = request.json()["messages"][0]["content"][0]["text"].as_str().unwrap().to_string();
+            StubResponse::json(json!({
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "text", "text": format!("山风吹过。{}", prompt) }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 12, "output_tokens": 5 }
+            }))
+        }).await;
+
+        let dir = tempdir().unwrap();
+        let mut config = anthropic_config(&server.base_url);
+        config.max_tokens = 4096;
+        config.cache = ResponseCacheConfig {
+            enabled: true,
+            path: dir.path().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+        let brief = ChapterBrief::new(Some(&outline), &plan, 1, None).unwrap();
+
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let ranked = service
+            .generate_candidates(project_id, &brief, 3, 3, false, &GenerationOptions::default())
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.json().get("seed").is_none()));
+        let mut contents: Vec<&str> = ranked.iter().map(|c| c.chapter.content.as_str()).collect();
+        contents.sort();
+        contents.dedup();
+        assert_eq!(contents.len(), 3);
+    }
+}
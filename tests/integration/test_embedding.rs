@@ -0,0 +1,63 @@
+//! Integration tests for remote embedding providers against a local stub server
+
+#[cfg(test)]
+mod tests {
+    use ai_novel_agent::services::llm::{
+        DashScopeEmbeddingProvider, EmbeddingProvider, OpenAiEmbeddingProvider,
+    };
+    use serde_json::json;
+
+    use crate::support::stub_server::{StubResponse, StubServer};
+
+    /// Test `/embeddings` batches are sent whole and reordered by index
+    #[tokio::test]
+    async fn test_openai_compatible_embeddings() {
+        let server = StubServer::start(|request| {
+            let inputs = request.json()["input"].as_array().unwrap().len();
+            // Answer in reverse order to check results are sorted by index
+            let data: Vec<_> = (0..inputs)
+                .rev()
+                .map(|i| json!({ "object": "embedding", "index": i, "embedding": [i as f32, 1.0] }))
+                .collect();
+            StubResponse::json(json!({ "data": data, "usage": { "prompt_tokens": 3, "total_tokens": 3 } }))
+        }).await;
+
+        let provider = OpenAiEmbeddingProvider::new(format!("{}/v1", server.base_url), None, "bge-m3")
+            .with_batch_size(2);
+        let texts: Vec<String> = ["林风", "青云宗", "夜色"].iter().map(|s| s.to_string()).collect();
+        let vectors = provider.embed(&texts).await.unwrap();
+        assert_eq!(vectors, vec![vec![0.0, 1.0], vec![1.0, 1.0], vec![0.0, 1.0]]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/v1/embeddings");
+        assert_eq!(requests[0].json()["model"], "bge-m3");
+        assert_eq!(requests[0].json()["input"], json!(["林风", "青云宗"]));
+        assert!(requests[0].header("authorization").is_none());
+    }
+
+    /// Test the DashScope request shape and response parsing
+    #[tokio::test]
+    async fn test_dashscope_embeddings() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "output": { "embeddings": [
+                    { "text_index": 1, "embedding": [0.5, 0.5] },
+                    { "text_index": 0, "embedding": [1.0, 0.0] }
+                ]},
+                "usage": { "total_tokens": 4 }
+            }))
+        }).await;
+
+        let provider = DashScopeEmbeddingProvider::new("ds-key".to_string(), None)
+            .with_base_url(format!("{}/api/v1", server.base_url));
+        let vectors = provider.embed(&["第一章".to_string(), "第二章".to_string()]).await.unwrap();
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.5, 0.5]]);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path, "/api/v1/services/embeddings/text-embedding/text-embedding");
+        assert_eq!(request.header("authorization"), Some("Bearer ds-key"));
+        assert_eq!(request.json()["model"], "text-embedding-v2");
+        assert_eq!(request.json()["input"]["texts"], json!(["第一章", "第二章"]));
+    }
+}
@@ -0,0 +1,407 @@
+//! Integration tests for plan-driven chapter generation
+
+#[cfg(test)]
+mod tests {
+    use ai_novel_agent::config::EndpointConfig;
+    use ai_novel_agent::models::{ChapterPlan, ChapterSummary, GeneratedChapter, GenerationParams, UsageStage};
+    use ai_novel_agent::services::generation::GenerationService;
+    use ai_novel_agent::services::llm::{create_client_with_config, GenerationOptions};
+    use ai_novel_agent::services::{ChapterBrief, SegmentOptions, UsageTracker};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    use crate::support::story::{stub_llm_config, xianxia_story};
+    use crate::support::stub_server::{StubResponse, StubServer};
+
+    /// Test prompts are built from the plan, the outline and the previous chapter
+    #[tokio::test]
+    async fn test_batch_uses_plan_outline_and_previous_chapter() {
+        let server = StubServer::start(|request| {
+            let plan = request.json()["messages"][1]["content"].as_str().unwrap().to_string();
+            let title = plan.lines().next().unwrap().to_string();
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": format!("正文开始。{}结束。", title) },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let config = stub_llm_config(&server);
+
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let chapters = service
+            .generate_batch(project_id, Some(&outline), &plan, &[1, 2], None, &GenerationOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title, plan.chapters[0].title);
+        assert_eq!(chapters[1].title, plan.chapters[1].title);
+
+        let requests = server.requests();
+        let first = requests[0].json();
+        let system = first["messages"][0]["content"].as_str().unwrap();
+        let prompt = first["messages"][1]["content"].as_str().unwrap();
+        assert!(system.contains("灵气修炼") && system.contains(&outline.protagonist.name));
+        assert!(!system.contains("上一章结尾"));
+        assert!(prompt.contains(&plan.chapters[0].summary));
+        assert!(prompt.contains(&plan.chapters[0].key_events[0]));
+
+        // The second chapter continues from the end of the first
+        let second = requests[1].json();
+        let system = second["messages"][0]["content"].as_str().unwrap();
+        assert!(system.contains("上一章结尾") && system.contains(&chapters[0].content));
+    }
+
+    /// Test long chapters are written in segments with smooth seams
+    #[tokio::test]
+    async fn test_long_chapter_written_in_segments() {
+        let calls = AtomicUsize::new(0);
+        let server = StubServer::start(move |_| {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            // Continuations restate the last sentence of the previous segment
+            let repeat = if n > 1 { format!("第{}段结束。", n - 1) } else { String::new() };
+            let content = format!("{}第{}段开始。{}第{}段结束。", repeat, n, "风过青云峰。".repeat(70), n);
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let config = stub_llm_config(&server);
+
+        let project_id = Uuid::new_v4();
+        let (outline, mut plan) = xianxia_story(project_id).await;
+        plan.chapters[0].word_count_estimate = 1000;
+
+        let service = GenerationService::new(create_client_with_config(&config).unwrap())
+            .with_segments(SegmentOptions { segment_chars: Some(500), ..Default::default() });
+        let chapters = service
+            .generate_batch(project_id, Some(&outline), &plan, &[1], None, &GenerationOptions::default())
+            .await
+            .unwrap();
+
+        // Two segments fall short of 900 characters; the third is cut at 1100
+        let chapter = &chapters[0];
+        assert_eq!(server.requests().len(), 3);
+        assert!((900..=1100).contains(&chapter.content.chars().count()), "{}", chapter.content.chars().count());
+        assert_eq!(chapter.word_count as usize, chapter.content.chars().count());
+        assert_eq!(chapter.content.matches("第1段结束。").count(), 1);
+        assert!(chapter.content.contains("第1段结束。\n第2段开始。"));
+
+        let requests = server.requests();
+        let second = requests[1].json();
+        let system = second["messages"][0]["content"].as_str().unwrap();
+        let prompt = second["messages"][1]["content"].as_str().unwrap();
+        assert!(system.contains("本章已写内容结尾") && system.contains("第1段结束。"));
+        assert!(prompt.contains("第2段") && prompt.contains("不要重复已写内容"));
+    }
+
+    /// Test parallel drafts are committed in order and their seams smoothed
+    #[tokio::test]
+    async fn test_parallel_drafts_in_order_with_continuity_pass() {
+        let server = StubServer::start(|request| {
+            let body = request.json();
+            let system = body["messages"][0]["content"].as_str().unwrap();
+            let content = if system.contains("小说编辑") {
+                format!("衔接后的开头。{}", "山风吹过。".repeat(100))
+            } else {
+                let prompt = body["messages"][1]["content"].as_str().unwrap();
+                let title = prompt.lines().next().unwrap().to_string();
+                format!("{}原来的开头。{}本章完。", title, "山风吹过。".repeat(150))
+            };
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let config = stub_llm_config(&server);
+
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let mut committed = Vec::new();
+        let drafts = service
+            .generate_parallel(project_id, Some(&outline), &plan, &[1, 2, 3], None, 3, &GenerationOptions::default(), |chapter| {
+                committed.push(chapter.chapter_number);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        let chapters = drafts.chapters;
+
+        assert_eq!(committed, vec![1, 2, 3]);
+        assert!(drafts.failed.is_empty() && drafts.unsmoothed.is_empty());
+        assert_eq!(server.requests().len(), 3 + 2);
+
+        // Drafts after the first see the previous chapter's plan, not its text
+        let requests = server.requests();
+        let second = requests
+            .iter()
+            .map(|r| r.json())
+            .find(|r| r["messages"][1]["content"].as_str().unwrap().starts_with(&format!("请创作{}", plan.chapters[1].title)))
+            .unwrap();
+        let system = second["messages"][0]["content"].as_str().unwrap();
+        assert!(system.contains("上一章概要") && system.contains(&plan.chapters[0].summary));
+        assert!(!system.contains("上一章结尾"));
+
+        // The continuity pass rewrote the openings of chapters 2 and 3 only
+        assert!(chapters[0].content.contains("原来的开头。"));
+        for chapter in &chapters[1..] {
+            assert!(chapter.content.starts_with("衔接后的开头。"), "{}", chapter.content);
+            assert!(chapter.content.ends_with("本章完。"));
+            assert_eq!(chapter.word_count as usize, chapter.content.chars().count());
+        }
+    }
+
+    /// Test a failed draft leaves the others saved and their seams reported
+    #[tokio::test]
+    async fn test_parallel_draft_failure_keeps_other_drafts() {
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+        let failing = format!("请创作{}", plan.chapters[1].title);
+        let server = StubServer::start(move |request| {
+            let prompt = request.json()["messages"][1]["content"].as_str().unwrap().to_string();
+            if prompt.starts_with(&failing) {
+                return StubResponse::status(400, r#"{"error": {"message": "bad request"}}"#);
+            }
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": format!("原来的开头。{}", "山风吹过。".repeat(150)) },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let config = stub_llm_config(&server);
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let mut committed = Vec::new();
+        let drafts = service
+            .generate_parallel(project_id, Some(&outline), &plan, &[1, 2, 3], None, 3, &GenerationOptions::default(), |chapter| {
+                committed.push(chapter.chapter_number);
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(committed, vec![1, 3]);
+        let failed: Vec<u32> = drafts.failed.iter().map(|(n, _)| *n).collect();
+        assert_eq!(failed, vec![2]);
+        // Chapter 3 follows a missing chapter, so nothing was smoothed
+        assert_eq!(drafts.unsmoothed, vec![3]);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    /// Test the continuity pass and the judge go to the consistency route
+    #[tokio::test]
+    async fn test_consistency_tasks_use_their_route() {
+        let writer = StubServer::start(|request| {
+            let prompt = request.json()["messages"][1]["content"].as_str().unwrap().to_string();
+            let title = prompt.lines().next().unwrap().to_string();
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": format!("{}原来的开头。{}", title, "山风吹过。".repeat(150)) },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+        let editor = StubServer::start(|request| {
+            let prompt = request.json()["messages"][1]["content"].as_str().unwrap().to_string();
+            let content = if prompt.contains("候选稿") {
+                r#"{"scores": [{"candidate": 1, "score": 8}, {"candidate": 2, "score": 3}]}"#.to_string()
+            } else {
+                format!("衔接后的开头。{}", "山风吹过。".repeat(100))
+            };
+            StubResponse::json(json!({
+                "choices": [{ "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }]
+            }))
+        }).await;
+
+        let mut config = stub_llm_config(&writer);
+        config.routes.consistency = Some(EndpointConfig {
+            base_url: Some(format!("{}/v1", editor.base_url)),
+            model: Some("qwen2.5-1.5b-instruct".to_string()),
+            ..Default::default()
+        });
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+        let service = GenerationService::from_config(&config).unwrap();
+
+        let chapters = service
+            .generate_parallel(project_id, Some(&outline), &plan, &[1, 2], None, 2, &GenerationOptions::default(), |_| Ok(()))
+            .await
+            .unwrap()
+            .chapters;
+        assert!(chapters[1].content.starts_with("衔接后的开头。"));
+        let brief = ChapterBrief::new(Some(&outline), &plan, 3, None).unwrap();
+        let ranked = service
+            .generate_candidates(project_id, &brief, 2, 2, true, &GenerationOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(ranked[0].score.judge, Some(0.8));
+
+        // Drafts from the prose model; the rewrite and the judge from the consistency model
+        assert_eq!(writer.requests().len(), 2 + 2);
+        let models: Vec<String> = editor.requests().iter().map(|r| r.json()["model"].as_str().unwrap().to_string()).collect();
+        assert_eq!(models, ["qwen2.5-1.5b-instruct", "qwen2.5-1.5b-instruct"]);
+    }
+
+    /// Test a revision sends the text, the instruction and the story context
+    #[tokio::test]
+    async fn test_revise_chapter_with_instruction() {
+        let server = StubServer::start(|_| {
+            StubResponse::sse(&[
+                r#"{"choices":[{"delta":{"content":"林风与魔尊"}}]}"#,
+                r#"{"choices":[{"delta":{"content":"大战三百回合。"}}]}"#,
+                "[DONE]",
+            ])
+        }).await;
+
+        let config = stub_llm_config(&server);
+
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+        let chapter = GeneratedChapter::new(
+            project_id,
+            1,
+            plan.chapters[0].title.clone(),
+            "林风与魔尊交手一招。".to_string(),
+            GenerationParams::default(),
+        );
+        let mut brief = ChapterBrief::new(Some(&outline), &plan, 1, None).unwrap();
+        brief.related = vec!["林风曾在山门前败给魔尊。".to_string()];
+
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let mut streamed = String::new();
+        let revised = service
+            .revise_chapter(&chapter, "把打斗写得更长", Some(&brief), &GenerationOptions::default(), |chunk| {
+                streamed.push_str(chunk)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(revised.id, chapter.id);
+        assert_eq!(revised.title, chapter.title);
+        assert_eq!(revised.content, "林风与魔尊大战三百回合。");
+        assert_eq!(streamed, revised.content);
+        assert_eq!(revised.word_count, 12);
+
+        let request = server.requests()[0].json();
+        let system = request["messages"][0]["content"].as_str().unwrap();
+        let prompt = request["messages"][1]["content"].as_str().unwrap();
+        assert!(system.contains("灵气修炼") && system.contains(&outline.protagonist.name));
+        assert!(system.contains("前文相关片段:\n林风曾在山门前败给魔尊。"));
+        assert!(prompt.contains("把打斗写得更长") && prompt.contains("林风与魔尊交手一招。"));
+    }
+
+    /// Test a revision's reply limit stops at the model's and longer chapters fail early
+    #[tokio::test]
+    async fn test_revise_reply_limit_clamped_to_model() {
+        let server = StubServer::start(|_| {
+            StubResponse::sse(&[r#"{"choices":[{"delta":{"content":"改写后的正文。"}}]}"#, "[DONE]"])
+        }).await;
+        let mut config = stub_llm_config(&server);
+        config.max_output_tokens = Some(100);
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let chapter = |content: String| {
+            GeneratedChapter::new(Uuid::new_v4(), 1, "第1章".to_string(), content, GenerationParams::default())
+        };
+
+        // About 75 tokens: half as much again would pass the model's limit
+        let fits = chapter("林风拔剑。".repeat(12));
+        service
+            .revise_chapter(&fits, "改短", None, &GenerationOptions::default().max_tokens(10), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(server.requests()[0].json()["max_tokens"], 100);
+
+        let too_long = chapter("林风拔剑。".repeat(20));
+        let error = service
+            .revise_chapter(&too_long, "改短", None, &GenerationOptions::default(), |_| {})
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("max_output_tokens"), "{:#}", error);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    /// Test candidate drafts differ by seed and are ranked by metrics and the judge
+    #[tokio::test]
+    async fn test_candidates_ranked_with_judge() {
+        let drafts = AtomicUsize::new(0);
+        let server = StubServer::start(move |request| {
+            let body = request.json();
+            let prompt = body["messages"][1]["content"].as_str().unwrap().to_string();
+            let content = if prompt.contains("候选稿") {
+                r#"{"scores": [{"candidate": 1, "score": 2}, {"candidate": 2, "score": 9, "reason": "转折有力"}]}"#.to_string()
+            } else {
+                match drafts.fetch_add(1, Ordering::SeqCst) {
+                    // Repeats itself and misses the twist
+                    0 => "林风夜探藏经阁，四下无人。林风夜探藏经阁，四下无人。".to_string(),
+                    _ => "林风夜探藏经阁，在书架深处找到密信。信上的字迹让他明白，师父竟是魔尊。".to_string(),
+                }
+            };
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let config = stub_llm_config(&server);
+
+        let project_id = Uuid::new_v4();
+        let mut plan = ChapterPlan::new(project_id, 1);
+        plan.chapters.push(ChapterSummary {
+            number: 1,
+            title: "第1章 真相".to_string(),
+            summary: "林风发现师父的秘密".to_string(),
+            key_events: vec!["夜探藏经阁".to_string(), "找到密信".to_string()],
+            protagonist_development: String::new(),
+            word_count_estimate: 40,
+            is_plot_twist_chapter: true,
+            plot_twist_description: Some("师父竟是魔尊".to_string()),
+        });
+        let brief = ChapterBrief::new(None, &plan, 1, None).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let usage = Arc::new(UsageTracker::open(dir.path(), project_id).unwrap());
+        let service = GenerationService::new(create_client_with_config(&config).unwrap()).with_usage_tracker(usage.clone());
+        let ranked = service
+            .generate_candidates(project_id, &brief, 2, 1, true, &GenerationOptions::default())
+            .await
+            .unwrap();
+
+        let order: Vec<(u32, u32)> = ranked.iter().map(|c| (c.rank, c.candidate)).collect();
+        assert_eq!(order, [(1, 2), (2, 1)]);
+        assert_eq!(ranked[0].score.coverage, 1.0);
+        assert_eq!(ranked[0].score.judge, Some(0.9));
+        assert!(ranked[1].score.repetition > 0.0);
+        assert!(ranked.iter().all(|c| c.chapter.title == "第1章 真相"));
+
+        // Two drafts with their own seeds from a random base, then the judge
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let seeds: Vec<u64> = requests[..2].iter().map(|r| r.json()["seed"].as_u64().unwrap()).collect();
+        assert_eq!(seeds[1], seeds[0] + 1);
+        assert_eq!(ranked[1].chapter.generation_params.seed, Some(seeds[0]));
+        assert_eq!(ranked[0].chapter.generation_params.seed, Some(seeds[1]));
+        let judge = requests[2].json()["messages"][1]["content"].as_str().unwrap().to_string();
+        assert!(judge.contains("候选稿2") && judge.contains("师父竟是魔尊"));
+        let stages: Vec<UsageStage> = usage.ledger().records.iter().map(|r| r.stage).collect();
+        assert_eq!(stages.iter().filter(|s| **s == UsageStage::Generate).count(), 2);
+        assert_eq!(stages.last(), Some(&UsageStage::Judge));
+    }
+}
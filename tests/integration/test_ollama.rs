@@ -0,0 +1,216 @@
+//! Integration tests for the native Ollama provider against a local stub server
+
+#[cfg(test)]
+mod tests {
+    use ai_novel_agent::config::Config;
+    use ai_novel_agent::models::TokenUsage;
+    use ai_novel_agent::services::llm::{
+        create_client_with_config, ollama_provider, ChatMessage, GenerationOptions, LlmError,
+        LlmProvider, OllamaModel, RetryPolicy, StreamEvent, DEFAULT_CONTEXT_WINDOW,
+    };
+    use ai_novel_agent::services::{ChapterBrief, GenerationService};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use crate::support::story::xianxia_story;
+    use crate::support::stub_server::{StubResponse, StubServer};
+
+    fn ollama_config(base_url: &str) -> ai_novel_agent::config::LlmConfig {
+        let mut config = Config::default().llm;
+        config.provider = "ollama".to_string();
+        config.api_key = String::new();
+        config.base_url = Some(base_url.to_string());
+        config.model = Some("qwen2.5:7b".to_string());
+        config.keep_alive = Some("5m".to_string());
+        config.max_tokens = 256;
+        config
+    }
+
+    /// Test a chat round trip through `/api/chat` without an API key
+    #[tokio::test]
+    async fn test_chat_against_local_server() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "model": "qwen2.5:7b",
+                "message": { "role": "assistant", "content": "夜色渐深。" },
+                "done": true,
+                "prompt_eval_count": 12,
+                "eval_count": 5
+            }))
+        }).await;
+
+        let client = create_client_with_config(&ollama_config(&server.base_url)).unwrap();
+        let completion = client.generate("背景", "写一句话").await.unwrap();
+        assert_eq!(completion.text, "夜色渐深。");
+        assert_eq!(completion.usage, TokenUsage::new(12, 5));
+        assert_eq!(completion.provider, "ollama");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/chat");
+        let body = requests[0].json();
+        assert_eq!(body["model"], "qwen2.5:7b");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["keep_alive"], "5m");
+        assert_eq!(body["options"]["num_predict"], 256);
+        assert_eq!(body["messages"][0], json!({ "role": "system", "content": "背景" }));
+    }
+
+    /// Test newline-delimited stream chunks arrive in order with final usage
+    #[tokio::test]
+    async fn test_stream_against_local_server() {
+        let server = StubServer::start(|_| {
+            StubResponse::ndjson(&[
+                json!({ "message": { "role": "assistant", "content": "第一" }, "done": false }),
+                json!({ "message": { "role": "assistant", "content": "段" }, "done": false }),
+                json!({ "message": { "role": "assistant", "content": "" }, "done": true, "prompt_eval_count": 8, "eval_count": 2 }),
+            ])
+        }).await;
+
+        let client = create_client_with_config(&ollama_config(&server.base_url)).unwrap();
+        let mut stream = client.chat_stream_with(&[ChatMessage::user("写")], &GenerationOptions::default()).await.unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, vec!["第一".to_string(), "段".to_string()]);
+        assert_eq!(stream.usage(), TokenUsage::new(8, 2));
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    /// Test the provider's own generate goes to `/api/generate` with the context as system
+    #[tokio::test]
+    async fn test_generate_endpoint() {
+        let server = StubServer::start(|request| {
+            if request.json()["stream"] == true {
+                StubResponse::ndjson(&[
+                    json!({ "response": "风", "done": false }),
+                    json!({ "response": "起", "done": true, "prompt_eval_count": 3, "eval_count": 2 }),
+                ])
+            } else {
+                StubResponse::json(json!({ "response": "风起。", "done": true, "prompt_eval_count": 3, "eval_count": 2 }))
+            }
+        }).await;
+
+        let provider = ollama_provider(&ollama_config(&server.base_url));
+        let options = GenerationOptions::new().json(true);
+        let completion = provider.generate("你是作家", "写", &options).await.unwrap();
+        assert_eq!(completion.text, "风起。");
+
+        let mut stream = provider.generate_stream("", "写", &GenerationOptions::default()).await.unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = stream.next().await {
+            events.push(event.unwrap());
+        }
+        assert_eq!(events, vec![
+            StreamEvent::Text("风".to_string()),
+            StreamEvent::Text("起".to_string()),
+            StreamEvent::Usage(TokenUsage::new(3, 2)),
+            StreamEvent::Done,
+        ]);
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/generate");
+        let body = requests[0].json();
+        assert_eq!(body["system"], "你是作家");
+        assert_eq!(body["prompt"], "写");
+        assert_eq!(body["format"], "json");
+    }
+
+    /// Test installed models are listed from `/api/tags`
+    #[tokio::test]
+    async fn test_list_models() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "models": [
+                    { "name": "qwen2.5:7b", "size": 4683087332u64, "digest": "abc" },
+                    { "name": "llama3:8b", "size": 4661224676u64 }
+                ]
+            }))
+        }).await;
+
+        let models = ollama_provider(&ollama_config(&server.base_url)).list_models().await.unwrap();
+        assert_eq!(models, vec![
+            OllamaModel { name: "qwen2.5:7b".to_string(), size: 4683087332 },
+            OllamaModel { name: "llama3:8b".to_string(), size: 4661224676 },
+        ]);
+        assert_eq!(server.requests()[0].method, "GET");
+        assert_eq!(server.requests()[0].path, "/api/tags");
+    }
+
+    /// Test a missing model surfaces as a typed error
+    #[tokio::test]
+    async fn test_missing_model_is_typed_error() {
+        let server = StubServer::start(|_| {
+            StubResponse::status(404, r#"{"error":"model 'qwen2.5:7b' not found"}"#)
+        }).await;
+
+        let client = create_client_with_config(&ollama_config(&server.base_url))
+            .unwrap()
+            .with_retry_policy(RetryPolicy::none());
+        let err = client.generate("", "hi").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::InvalidRequest { status: 404, .. })
+        ));
+    }
+
+    /// Test errors reported in the body of a 200 response are typed errors
+    #[tokio::test]
+    async fn test_error_in_body_is_typed_error() {
+        let server = StubServer::start(|request| {
+            if request.json()["stream"] == true {
+                StubResponse::ndjson(&[
+                    json!({ "message": { "role": "assistant", "content": "第一" }, "done": false }),
+                    json!({ "error": "model runner has unexpectedly stopped" }),
+                ])
+            } else {
+                StubResponse::json(json!({ "error": "model runner has unexpectedly stopped" }))
+            }
+        }).await;
+
+        let client = create_client_with_config(&ollama_config(&server.base_url))
+            .unwrap()
+            .with_retry_policy(RetryPolicy::none());
+        let err = client.generate("", "hi").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::Server { status: 500, message, .. }) if message.contains("unexpectedly stopped")
+        ));
+
+        let mut stream = client.chat_stream_with(&[ChatMessage::user("写")], &GenerationOptions::default()).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "第一");
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err.downcast_ref::<LlmError>(), Some(LlmError::Server { .. })));
+    }
+
+    /// Test a chapter fits the default window with the default reply limit
+    #[tokio::test]
+    async fn test_generation_fits_default_window() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "message": { "role": "assistant", "content": "林风踏上了青云山。" },
+                "done": true,
+                "prompt_eval_count": 900,
+                "eval_count": 9
+            }))
+        }).await;
+
+        let mut config = ollama_config(&server.base_url);
+        config.max_tokens = 4096;
+        let project_id = uuid::Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+        let brief = ChapterBrief::new(Some(&outline), &plan, 1, None).unwrap();
+
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let chapter = service
+            .generate_planned_chapter(project_id, &brief, &GenerationOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(chapter.content, "林风踏上了青云山。");
+
+        // The server is told to load the model with the window the prompt was fitted to
+        let body = server.requests()[0].json();
+        assert_eq!(body["options"]["num_ctx"], DEFAULT_CONTEXT_WINDOW);
+        assert_eq!(body["options"]["num_predict"], 4096);
+    }
+}
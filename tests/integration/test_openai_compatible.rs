@@ -0,0 +1,123 @@
+//! Integration tests for the OpenAI-compatible provider against a local stub server
+
+#[cfg(test)]
+mod tests {
+    use ai_novel_agent::config::Config;
+    use ai_novel_agent::models::TokenUsage;
+    use ai_novel_agent::services::llm::{create_client_with_config, ChatMessage, LlmError, RetryPolicy};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use crate::support::stub_server::{StubResponse, StubServer};
+
+    fn local_config(base_url: &str) -> ai_novel_agent::config::LlmConfig {
+        let mut config = Config::default().llm;
+        config.provider = "openai_compatible".to_string();
+        config.api_key = String::new();
+        config.base_url = Some(format!("{}/v1", base_url));
+        config.model = Some("qwen2.5-7b-instruct".to_string());
+        config.temperature = 0.6;
+        config.max_tokens = 256;
+        config
+    }
+
+    /// Test a full request/response round trip without an API key
+    #[tokio::test]
+    async fn test_generate_against_local_server() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": "夜色渐深。" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+            }))
+        }).await;
+
+        let client = create_client_with_config(&local_config(&server.base_url)).unwrap();
+        let completion = client.generate("背景", "写一句话").await.unwrap();
+        assert_eq!(completion.text, "夜色渐深。");
+        assert_eq!(completion.usage, TokenUsage::new(12, 5));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/v1/chat/completions");
+        assert!(requests[0].header("authorization").is_none());
+
+        let body = requests[0].json();
+        assert_eq!(body["model"], "qwen2.5-7b-instruct");
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(body["messages"][1]["content"], "写一句话");
+    }
+
+    /// Test streamed chunks are yielded in order and `[DONE]` ends the stream
+    #[tokio::test]
+    async fn test_stream_against_local_server() {
+        let server = StubServer::start(|_| {
+            StubResponse::sse(&[
+                r#"{"choices":[{"delta":{"role":"assistant"}}]}"#,
+                r#"{"choices":[{"delta":{"content":"第一"}}]}"#,
+                r#"{"choices":[{"delta":{"content":"段"}}]}"#,
+                r#"{"choices":[],"usage":{"prompt_tokens":8,"completion_tokens":2}}"#,
+                "[DONE]",
+            ])
+        }).await;
+
+        let mut config = local_config(&server.base_url);
+        config.api_key = "local-key".to_string();
+        let client = create_client_with_config(&config).unwrap();
+
+        let mut stream = client.generate_stream("", "写").await.unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, vec!["第一".to_string(), "段".to_string()]);
+        assert_eq!(stream.usage(), TokenUsage::new(8, 2));
+
+        let requests = server.requests();
+        assert_eq!(requests[0].header("authorization"), Some("Bearer local-key"));
+        assert_eq!(requests[0].json()["stream"], true);
+        assert_eq!(requests[0].json()["stream_options"]["include_usage"], true);
+    }
+
+    /// Test chat messages reach the server verbatim, with no injected system text
+    #[tokio::test]
+    async fn test_chat_sends_messages_verbatim() {
+        let server = StubServer::start(|_| {
+            StubResponse::json(json!({
+                "choices": [{ "message": { "role": "assistant", "content": "第二段。" } }]
+            }))
+        }).await;
+
+        let client = create_client_with_config(&local_config(&server.base_url)).unwrap();
+        let messages = vec![
+            ChatMessage::user("示例：写一段"),
+            ChatMessage::assistant("第一段。"),
+            ChatMessage::user("继续"),
+        ];
+        assert_eq!(client.chat(&messages).await.unwrap().text, "第二段。");
+
+        let body = server.requests()[0].json();
+        assert_eq!(body["messages"], serde_json::to_value(&messages).unwrap());
+    }
+
+    /// Test HTTP errors surface as typed errors rather than decode failures
+    #[tokio::test]
+    async fn test_error_status_is_typed() {
+        let server = StubServer::start(|_| {
+            StubResponse::status(429, r#"{"error":"too many requests"}"#)
+        }).await;
+
+        let client = create_client_with_config(&local_config(&server.base_url))
+            .unwrap()
+            .with_retry_policy(RetryPolicy::none());
+
+        let err = client.generate("", "hi").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::RateLimited { .. })
+        ));
+    }
+}
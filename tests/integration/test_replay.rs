@@ -0,0 +1,149 @@
+//! Integration tests for recording and replaying LLM responses
+
+#[cfg(test)]
+mod tests {
+    use ai_novel_agent::config::{CassetteConfig, CassetteMode, Config, LlmConfig};
+    use ai_novel_agent::models::{ChapterCandidate, GeneratedChapter};
+    use ai_novel_agent::services::generation::GenerationService;
+    use ai_novel_agent::services::llm::{create_client_with_config, is_configured, GenerationOptions};
+    use ai_novel_agent::services::ChapterBrief;
+    use serde_json::json;
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    use crate::support::story::xianxia_story;
+    use crate::support::stub_server::{StubResponse, StubServer};
+
+    /// Run outline → plan → generate for the first chapters
+    async fn run_pipeline(config: &LlmConfig, project_id: Uuid) -> Vec<GeneratedChapter> {
+        let (outline, plan) = xianxia_story(project_id).await;
+
+        let service = GenerationService::new(create_client_with_config(config).unwrap());
+        let mut chapters = Vec::new();
+        for summary in plan.chapters.iter().take(2) {
+            let chapter = service.generate_chapter(
+                project_id,
+                summary.number,
+                &outline.premise,
+                &format!("{}: {}", summary.title, summary.summary),
+                &GenerationOptions::default(),
+            ).await.unwrap();
+            chapters.push(chapter);
+        }
+        chapters
+    }
+
+    /// Test a recorded run replays identically without network access
+    #[tokio::test]
+    async fn test_record_then_replay_pipeline() {
+        let dir = tempdir().unwrap();
+        let cassette = dir.path().join("pipeline.json").to_string_lossy().to_string();
+        let project_id = Uuid::new_v4();
+
+        let server = StubServer::start(|request| {
+            let prompt = request.json()["messages"][1]["content"].as_str().unwrap().to_string();
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": format!("正文：{}", prompt) },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let mut config = Config::default().llm;
+        config.provider = "openai_compatible".to_string();
+        config.base_url = Some(format!("{}/v1", server.base_url));
+        config.model = Some("local-model".to_string());
+        config.cassette = Some(CassetteConfig { path: cassette.clone(), mode: CassetteMode::Record });
+
+        let recorded = run_pipeline(&config, project_id).await;
+        assert_eq!(server.requests().len(), 2);
+        drop(server);
+
+        // No base URL or API key: replay must not need a real provider
+        let mut config = Config::default().llm;
+        config.api_key = String::new();
+        config.cassette = Some(CassetteConfig { path: cassette, mode: CassetteMode::Replay });
+        assert!(is_configured(&config));
+
+        let replayed = run_pipeline(&config, project_id).await;
+        assert_eq!(replayed.len(), recorded.len());
+        for (a, b) in recorded.iter().zip(&replayed) {
+            assert_eq!(a.content, b.content);
+            assert_eq!(a.generation_params.model, b.generation_params.model);
+        }
+        assert_eq!(replayed[0].generation_params.model, "local-model");
+    }
+
+    /// Test a candidates run replays, with the same seeds, from its recording
+    #[tokio::test]
+    async fn test_record_then_replay_candidates() {
+        let dir = tempdir().unwrap();
+        let cassette = dir.path().join("candidates.json").to_string_lossy().to_string();
+        let project_id = Uuid::new_v4();
+        let (outline, plan) = xianxia_story(project_id).await;
+        let brief = ChapterBrief::new(Some(&outline), &plan, 1, None).unwrap();
+
+        let server = StubServer::start(|request| {
+            let body = request.json();
+            let prompt = body["messages"][1]["content"].as_str().unwrap();
+            StubResponse::json(json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": format!("种子{}：{}", body["seed"], prompt) },
+                    "finish_reason": "stop"
+                }]
+            }))
+        }).await;
+
+        let mut config = Config::default().llm;
+        config.provider = "openai_compatible".to_string();
+        config.base_url = Some(format!("{}/v1", server.base_url));
+        config.model = Some("local-model".to_string());
+        config.cassette = Some(CassetteConfig { path: cassette.clone(), mode: CassetteMode::Record });
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let recorded = service
+            .generate_candidates(project_id, &brief, 3, 2, false, &GenerationOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(server.requests().len(), 3);
+        drop(server);
+
+        let mut config = Config::default().llm;
+        config.cassette = Some(CassetteConfig { path: cassette, mode: CassetteMode::Replay });
+        let service = GenerationService::new(create_client_with_config(&config).unwrap());
+        let replayed = service
+            .generate_candidates(project_id, &brief, 3, 2, false, &GenerationOptions::default())
+            .await
+            .unwrap();
+
+        let drafts = |ranked: &[ChapterCandidate]| {
+            let mut drafts: Vec<(u32, Option<u64>, String)> = ranked
+                .iter()
+                .map(|c| (c.candidate, c.chapter.generation_params.seed, c.chapter.content.clone()))
+                .collect();
+            drafts.sort();
+            drafts
+        };
+        assert_eq!(drafts(&replayed), drafts(&recorded));
+        let seeds: Vec<Option<u64>> = drafts(&recorded).into_iter().map(|(_, seed, _)| seed).collect();
+        assert_eq!(seeds, [Some(0), Some(1), Some(2)]);
+    }
+
+    /// Test a request missing from the cassette is an error, not a network call
+    #[tokio::test]
+    async fn test_replay_miss_is_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("empty.json");
+        std::fs::write(&path, r#"{"provider":"qwen","model":"qwen-max","interactions":[]}"#).unwrap();
+
+        let mut config = Config::default().llm;
+        config.cassette = Some(CassetteConfig {
+            path: path.to_string_lossy().to_string(),
+            mode: CassetteMode::Replay,
+        });
+
+        let client = create_client_with_config(&config).unwrap();
+        let err = client.generate("ctx", "新的提示").await.unwrap_err();
+        assert!(err.to_string().contains("No recorded response"));
+    }
+}
@@ -3,3 +3,4 @@
 pub mod contract;
 pub mod integration;
 pub mod unit;
+pub mod support;
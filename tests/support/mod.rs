@@ -0,0 +1,4 @@
+//! Shared test helpers
+
+pub mod stub_server;
+pub mod story;
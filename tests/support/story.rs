@@ -0,0 +1,31 @@
+//! Story fixtures for generation tests
+
+use ai_novel_agent::config::{Config, LlmConfig};
+use ai_novel_agent::models::{ChapterPlan, NovelGenre, NovelOutline};
+use ai_novel_agent::services::chapter_planning::ChapterPlanningService;
+use ai_novel_agent::services::outline::OutlineService;
+use uuid::Uuid;
+
+use super::stub_server::StubServer;
+
+/// LLM settings pointing an OpenAI-compatible client at `server`
+pub fn stub_llm_config(server: &StubServer) -> LlmConfig {
+    let mut config = Config::default().llm;
+    config.provider = "openai_compatible".to_string();
+    config.base_url = Some(format!("{}/v1", server.base_url));
+    config.model = Some("qwen2.5-7b-instruct".to_string());
+    config
+}
+
+/// Template outline and chapter plan of a xianxia novel for `project_id`
+pub async fn xianxia_story(project_id: Uuid) -> (NovelOutline, ChapterPlan) {
+    let outline = OutlineService::new()
+        .generate(project_id, NovelGenre::Xianxia, "少年踏上修仙之路".to_string(), "成长".to_string(), 100_000)
+        .await
+        .unwrap();
+    let plan = ChapterPlanningService::new()
+        .generate_plan(project_id, &outline)
+        .await
+        .unwrap();
+    (outline, plan)
+}